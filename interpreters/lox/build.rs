@@ -0,0 +1,47 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+const KEYWORDS: [&str; 38] = [
+    "as", "async", "await", "break", "const", "continue", "crate", "do", "dyn", "else", "enum",
+    "extern", "false", "final", "fn", "for", "if", "impl", "in", "let", "loop", "macro", "match",
+    "mod", "move", "mut", "pub", "ref", "return", "self", "static", "struct", "super", "trait",
+    "true", "try", "type", "while",
+];
+
+// generates one test per file in src/tests/cases, see src/tests/mod.rs
+fn main() {
+    let cases = Path::new("src/tests/cases");
+    println!("cargo:rerun-if-changed={}", cases.display());
+
+    let mut names = vec![];
+    for entry in fs::read_dir(cases).expect("missing src/tests/cases") {
+        let path = entry.expect("unreadable test case").path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "jlox")
+        {
+            names.push(path.file_name().unwrap().to_string_lossy().to_string());
+        }
+    }
+    names.sort();
+
+    let mut generated = String::new();
+    for name in names {
+        let mut test: String = name
+            .trim_end_matches(".jlox")
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        if KEYWORDS.contains(&test.as_str()) {
+            test.push('_');
+        }
+        if test.starts_with(|c: char| c.is_ascii_digit()) {
+            test.insert(0, '_');
+        }
+        generated.push_str(&format!("case!({}, {:?});\n", test, name));
+    }
+
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("cases.rs");
+    fs::write(out, generated).expect("could not write the generated cases");
+}
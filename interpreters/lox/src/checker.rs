@@ -0,0 +1,848 @@
+use crate::diagnostic::{Diagnostic, DiagnosticKind};
+use crate::expr::{Expr, LiteralValue, Pattern};
+use crate::scanner::{Token, TokenType};
+use crate::stmt::Stmt;
+use std::collections::HashMap;
+
+// a gradual type checker, run after the resolver. only annotated names and
+// literals have a known type, everything else is Any and fits anywhere. operators
+// are only checked when an operand goes back to an annotation, so code without
+// annotations passes unchanged and keeps failing at runtime where it did before
+#[derive(Debug, Clone, PartialEq)]
+enum Type {
+    Any,
+    Number,
+    String,
+    Bool,
+    Nil,
+    List,
+    Map,
+    // the signature is known for declared functions, not for a `Function` annotation
+    Function(Option<Box<Signature>>),
+    Class(String),
+    Instance(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Signature {
+    name: String,
+    params: Vec<Type>,
+    returns: Type,
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Type::Any => "Any",
+            Type::Number => "Number",
+            Type::String => "String",
+            Type::Bool => "Bool",
+            Type::Nil => "Nil",
+            Type::List => "List",
+            Type::Map => "Map",
+            Type::Function(_) => "Function",
+            Type::Class(_) => "Class",
+            Type::Instance(class) => class,
+        };
+        write!(f, "{}", name)
+    }
+}
+
+pub struct Checker {
+    // the innermost scope last, names that are not found are Any
+    scopes: Vec<HashMap<String, Type>>,
+    // every class in the program with its superclass, so annotations can name
+    // classes declared further down
+    classes: HashMap<String, Option<String>>,
+    current_class: Option<String>,
+    // the function being checked and its declared return type
+    current_function: Option<(String, Type)>,
+    errors: Vec<Diagnostic>,
+}
+
+impl Checker {
+    pub fn new() -> Self {
+        return Self {
+            scopes: vec![HashMap::new()],
+            classes: HashMap::new(),
+            current_class: None,
+            current_function: None,
+            errors: vec![],
+        };
+    }
+
+    // all mismatches in the program, in source order
+    pub fn check(mut self, stms: &Vec<&Stmt>) -> Result<(), Vec<Diagnostic>> {
+        self.collect_classes(stms);
+        for stm in stms {
+            self.statement(stm);
+        }
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+        self.errors
+            .sort_by_key(|error| error.span.map(|span| span.start));
+        return Err(self.errors);
+    }
+
+    fn collect_classes(&mut self, stms: &Vec<&Stmt>) {
+        for stm in stms {
+            match stm {
+                Stmt::Class {
+                    name,
+                    superclass,
+                    methods,
+                } => {
+                    let superclass = match superclass {
+                        Some(Expr::Variable { id: _, name }) => Some(name.lexeme.clone()),
+                        _ => None,
+                    };
+                    self.classes.insert(name.lexeme.clone(), superclass);
+                    self.collect_classes(&methods.iter().map(|m| m.as_ref()).collect());
+                }
+                Stmt::Block { statements: body }
+                | Stmt::Function {
+                    name: _,
+                    params: _,
+                    param_annotations: _,
+                    return_annotation: _,
+                    body,
+                } => self.collect_classes(&body.iter().map(|b| b.as_ref()).collect()),
+                Stmt::IfStmt {
+                    predicate: _,
+                    then,
+                    els,
+                } => {
+                    self.collect_classes(&vec![then.as_ref()]);
+                    if let Some(els) = els {
+                        self.collect_classes(&vec![els.as_ref()]);
+                    }
+                }
+                Stmt::WhileStmt {
+                    condition: _,
+                    body,
+                    increment: _,
+                } => self.collect_classes(&vec![body.as_ref()]),
+                Stmt::Try {
+                    keyword: _,
+                    body,
+                    catch,
+                    finally,
+                } => {
+                    self.collect_classes(&vec![body.as_ref()]);
+                    if let Some((_, handler)) = catch {
+                        self.collect_classes(&vec![handler.as_ref()]);
+                    }
+                    if let Some(finally) = finally {
+                        self.collect_classes(&vec![finally.as_ref()]);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn statement(&mut self, stm: &Stmt) {
+        match stm {
+            Stmt::Expression { expression } | Stmt::Print { expression } => {
+                self.expr(expression);
+            }
+            Stmt::Var {
+                name,
+                annotation,
+                initializer,
+            } => {
+                let declared = self.annotation(annotation);
+                let value = self.expr(initializer);
+                // `var a: T;` starts out nil like any other declaration without a value
+                let implicit = matches!(
+                    initializer,
+                    Expr::Literal {
+                        id: _,
+                        value: LiteralValue::Nil
+                    }
+                );
+                if !implicit {
+                    self.assignable(name, &declared, &value);
+                }
+                self.declare(name, declared);
+            }
+            Stmt::Block { statements } => {
+                self.scopes.push(HashMap::new());
+                for statement in statements {
+                    self.statement(statement);
+                }
+                self.scopes.pop();
+            }
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                if let Some(superclass) = superclass {
+                    self.expr(superclass);
+                }
+                self.declare(name, Type::Class(name.lexeme.clone()));
+
+                let enclosing = self.current_class.replace(name.lexeme.clone());
+                for method in methods {
+                    if let Stmt::Function {
+                        name,
+                        params,
+                        param_annotations,
+                        return_annotation,
+                        body,
+                    } = method.as_ref()
+                    {
+                        let signature = self.signature(name, param_annotations, return_annotation);
+                        self.function(params, &signature, body);
+                        // an initializer always returns its instance
+                        if name.lexeme != "init" {
+                            self.falls_off(name, &signature, body);
+                        }
+                    }
+                }
+                self.current_class = enclosing;
+            }
+            Stmt::IfStmt {
+                predicate,
+                then,
+                els,
+            } => {
+                self.expr(predicate);
+                self.statement(then);
+                if let Some(els) = els {
+                    self.statement(els);
+                }
+            }
+            Stmt::WhileStmt {
+                condition,
+                body,
+                increment,
+            } => {
+                self.expr(condition);
+                self.statement(body);
+                if let Some(increment) = increment {
+                    self.expr(increment);
+                }
+            }
+            Stmt::Function {
+                name,
+                params,
+                param_annotations,
+                return_annotation,
+                body,
+            } => {
+                let signature = self.signature(name, param_annotations, return_annotation);
+                // declared first so the function can call itself
+                self.declare(name, Type::Function(Some(Box::new(signature.clone()))));
+                self.function(params, &signature, body);
+                self.falls_off(name, &signature, body);
+            }
+            Stmt::ReturnStmt { keyword, value } => {
+                let found = match value {
+                    Some(value) => self.expr(value),
+                    None => Type::Nil,
+                };
+                if let Some((function, returns)) = &self.current_function {
+                    if !self.accepts(returns, &found) {
+                        let msg =
+                            format!("'{}' must return {}, found {}", function, returns, found);
+                        self.error(keyword, msg);
+                    }
+                }
+            }
+            Stmt::Import { path: _, name } => self.declare(name, Type::Any),
+            Stmt::Throw { keyword: _, value } => {
+                self.expr(value);
+            }
+            Stmt::Try {
+                keyword: _,
+                body,
+                catch,
+                finally,
+            } => {
+                self.statement(body);
+                if let Some((name, handler)) = catch {
+                    self.scopes.push(HashMap::new());
+                    self.declare(name, Type::Any);
+                    self.statement(handler);
+                    self.scopes.pop();
+                }
+                if let Some(finally) = finally {
+                    self.statement(finally);
+                }
+            }
+            Stmt::Break { keyword: _ } | Stmt::Continue { keyword: _ } => {}
+        }
+    }
+
+    fn signature(
+        &mut self,
+        name: &Token,
+        param_annotations: &[Option<Token>],
+        return_annotation: &Option<Token>,
+    ) -> Signature {
+        return Signature {
+            name: name.lexeme.clone(),
+            params: param_annotations
+                .iter()
+                .map(|annotation| self.annotation(annotation))
+                .collect(),
+            returns: self.annotation(return_annotation),
+        };
+    }
+
+    fn function(&mut self, params: &[Token], signature: &Signature, body: &[Box<Stmt>]) {
+        let enclosing = self
+            .current_function
+            .replace((signature.name.clone(), signature.returns.clone()));
+        self.scopes.push(HashMap::new());
+        for (param, typ) in params.iter().zip(&signature.params) {
+            self.declare(param, typ.clone());
+        }
+        for statement in body {
+            self.statement(statement);
+        }
+        self.scopes.pop();
+        self.current_function = enclosing;
+    }
+
+    // reaching the end of the body returns nil, which the annotation may not allow
+    fn falls_off(&mut self, name: &Token, signature: &Signature, body: &[Box<Stmt>]) {
+        if self.accepts(&signature.returns, &Type::Nil) || body.iter().any(|s| always_returns(s)) {
+            return;
+        }
+        let msg = format!(
+            "'{}' must return {}, found {}",
+            name.lexeme,
+            signature.returns,
+            Type::Nil
+        );
+        self.error(name, msg);
+    }
+
+    fn expr(&mut self, exp: &Expr) -> Type {
+        match exp {
+            Expr::Literal { id: _, value } => match value {
+                LiteralValue::Number(_) => return Type::Number,
+                LiteralValue::StringLit(_) => return Type::String,
+                LiteralValue::True | LiteralValue::False => return Type::Bool,
+                LiteralValue::Nil => return Type::Nil,
+                _ => return Type::Any,
+            },
+            Expr::Variable { id: _, name } => return self.lookup(name),
+            Expr::Assign { id: _, name, value } => {
+                let value = self.expr(value);
+                let declared = self.lookup(name);
+                // only annotations restrict assignments, a function or class name
+                // can be rebound to anything like in untyped code
+                if !matches!(declared, Type::Function(Some(_)) | Type::Class(_)) {
+                    self.assignable(name, &declared, &value);
+                }
+                return value;
+            }
+            Expr::Grouping { id: _, expression } => return self.expr(expression),
+            Expr::Unary {
+                id: _,
+                operator,
+                right: operand,
+            } => {
+                let right = self.expr(operand);
+                if operator.token_type == TokenType::Bang {
+                    return Type::Bool;
+                }
+                if right != Type::Any && right != Type::Number {
+                    if self.annotated(operand) {
+                        let msg = format!("cannot apply '{}' to {}", operator.lexeme, right);
+                        self.error(operator, msg);
+                    }
+                    return Type::Any;
+                }
+                return Type::Number;
+            }
+            Expr::Binary {
+                id: _,
+                left,
+                operator,
+                right,
+            } => {
+                let left_type = self.expr(left);
+                let right_type = self.expr(right);
+                let result = self.binary(operator, &left_type, &right_type);
+                if result.is_none() && (self.annotated(left) || self.annotated(right)) {
+                    let msg = format!(
+                        "cannot apply '{}' to {} and {}",
+                        operator.lexeme, left_type, right_type
+                    );
+                    self.error(operator, msg);
+                }
+                return result.unwrap_or(Type::Any);
+            }
+            Expr::Logical {
+                id: _,
+                left,
+                operator: _,
+                right,
+            } => {
+                let left = self.expr(left);
+                let right = self.expr(right);
+                if left == right {
+                    return left;
+                }
+                return Type::Any;
+            }
+            Expr::Call {
+                id: _,
+                callee,
+                paren,
+                arguments,
+            } => {
+                let callee = self.expr(callee);
+                let arguments: Vec<Type> = arguments.iter().map(|arg| self.expr(arg)).collect();
+                match callee {
+                    Type::Function(Some(signature)) => {
+                        for (i, (param, arg)) in signature.params.iter().zip(&arguments).enumerate()
+                        {
+                            if !self.accepts(param, arg) {
+                                let msg = format!(
+                                    "argument {} of '{}' must be {}, found {}",
+                                    i + 1,
+                                    signature.name,
+                                    param,
+                                    arg
+                                );
+                                self.error(paren, msg);
+                            }
+                        }
+                        return signature.returns;
+                    }
+                    Type::Class(class) => return Type::Instance(class),
+                    _ => return Type::Any,
+                }
+            }
+            Expr::AnonFunction {
+                id: _,
+                paren: _,
+                arguments,
+                body,
+            } => {
+                let signature = Signature {
+                    name: "anonymous function".to_string(),
+                    params: arguments.iter().map(|_| Type::Any).collect(),
+                    returns: Type::Any,
+                };
+                self.function(arguments, &signature, body);
+                return Type::Function(Some(Box::new(signature)));
+            }
+            Expr::Get {
+                id: _,
+                object,
+                name: _,
+            } => {
+                self.expr(object);
+                return Type::Any;
+            }
+            Expr::Set {
+                id: _,
+                object,
+                name: _,
+                value,
+            } => {
+                self.expr(object);
+                return self.expr(value);
+            }
+            Expr::Index {
+                id: _,
+                object,
+                bracket: _,
+                index,
+            } => {
+                self.expr(object);
+                self.expr(index);
+                return Type::Any;
+            }
+            Expr::SetIndex {
+                id: _,
+                object,
+                bracket: _,
+                index,
+                value,
+            } => {
+                self.expr(object);
+                self.expr(index);
+                return self.expr(value);
+            }
+            Expr::Interpolation {
+                id: _,
+                quote: _,
+                parts,
+            } => {
+                for part in parts {
+                    self.expr(part);
+                }
+                return Type::String;
+            }
+            Expr::List {
+                id: _,
+                bracket: _,
+                elements,
+            } => {
+                for element in elements {
+                    self.expr(element);
+                }
+                return Type::List;
+            }
+            Expr::Map {
+                id: _,
+                brace: _,
+                entries,
+            } => {
+                for (key, value) in entries {
+                    self.expr(key);
+                    self.expr(value);
+                }
+                return Type::Map;
+            }
+            Expr::Match {
+                id: _,
+                keyword: _,
+                subject,
+                arms,
+            } => {
+                // a bare name only takes the subject's type if it was declared
+                let subject = match self.annotated(subject) {
+                    true => self.expr(subject),
+                    false => {
+                        self.expr(subject);
+                        Type::Any
+                    }
+                };
+                for (pattern, body) in arms {
+                    let bound = match pattern {
+                        Pattern::Literal { value } => {
+                            self.expr(value);
+                            Type::Any
+                        }
+                        Pattern::Class { class, name: _ } => {
+                            if self.classes.contains_key(&class.lexeme) {
+                                Type::Instance(class.lexeme.clone())
+                            } else {
+                                Type::Any
+                            }
+                        }
+                        Pattern::Binding { name: _ } => subject.clone(),
+                    };
+                    self.scopes.push(HashMap::new());
+                    if let Some(name) = pattern.binding() {
+                        self.declare(name, bound);
+                    }
+                    self.expr(body);
+                    self.scopes.pop();
+                }
+                // arms may differ, so the result is only known at runtime
+                return Type::Any;
+            }
+            Expr::This { id: _, keyword: _ } => match &self.current_class {
+                Some(class) => return Type::Instance(class.clone()),
+                None => return Type::Any,
+            },
+            Expr::Super {
+                id: _,
+                keyword: _,
+                method: _,
+            } => return Type::Any,
+        }
+    }
+
+    // the operand types each operator takes, the same rules as expr::binary_op,
+    // none when the operands cannot be combined
+    fn binary(&self, operator: &Token, left: &Type, right: &Type) -> Option<Type> {
+        let (operands, result): (&[Type], Type) = match operator.token_type {
+            TokenType::EqualEqual | TokenType::BangEqual => return Some(Type::Bool),
+            TokenType::Minus | TokenType::Star | TokenType::Slash => {
+                (&[Type::Number], Type::Number)
+            }
+            TokenType::Greater | TokenType::GreaterEqual => (&[Type::Number], Type::Bool),
+            TokenType::Less | TokenType::LessEqual => (&[Type::Number, Type::String], Type::Bool),
+            TokenType::Plus => {
+                let result = if *left == Type::Any {
+                    right.clone()
+                } else {
+                    left.clone()
+                };
+                (&[Type::Number, Type::String], result)
+            }
+            _ => return Some(Type::Any),
+        };
+
+        let fits = |typ: &Type| *typ == Type::Any || operands.contains(typ);
+        let consistent = *left == Type::Any || *right == Type::Any || left == right;
+        if !fits(left) || !fits(right) || !consistent {
+            return None;
+        }
+        return Some(result);
+    }
+
+    // whether the type of an expression comes from an annotation somewhere, as
+    // opposed to a literal or a declaration without one
+    fn annotated(&self, exp: &Expr) -> bool {
+        match exp {
+            Expr::Variable { id: _, name }
+            | Expr::Assign {
+                id: _,
+                name,
+                value: _,
+            } => match self.lookup(name) {
+                Type::Any | Type::Function(Some(_)) | Type::Class(_) => return false,
+                _ => return true,
+            },
+            Expr::Call {
+                id: _,
+                callee,
+                paren: _,
+                arguments: _,
+            } => match callee.as_ref() {
+                Expr::Variable { id: _, name } => match self.lookup(name) {
+                    Type::Function(Some(signature)) => return signature.returns != Type::Any,
+                    _ => return false,
+                },
+                _ => return false,
+            },
+            Expr::Grouping { id: _, expression } => return self.annotated(expression),
+            Expr::Unary {
+                id: _,
+                operator: _,
+                right,
+            } => return self.annotated(right),
+            Expr::Binary {
+                id: _,
+                left,
+                operator: _,
+                right,
+            }
+            | Expr::Logical {
+                id: _,
+                left,
+                operator: _,
+                right,
+            } => return self.annotated(left) || self.annotated(right),
+            _ => return false,
+        }
+    }
+
+    // the type a name is annotated with, Any when there is no annotation
+    fn annotation(&mut self, annotation: &Option<Token>) -> Type {
+        let name = match annotation {
+            Some(name) => name,
+            None => return Type::Any,
+        };
+        match name.lexeme.as_str() {
+            "Any" => return Type::Any,
+            "Number" => return Type::Number,
+            "String" => return Type::String,
+            "Bool" => return Type::Bool,
+            "Nil" => return Type::Nil,
+            "List" => return Type::List,
+            "Map" => return Type::Map,
+            "Function" => return Type::Function(None),
+            class if self.classes.contains_key(class) => return Type::Instance(class.to_string()),
+            other => {
+                self.error(name, format!("unknown type '{}'", other));
+                return Type::Any;
+            }
+        }
+    }
+
+    fn accepts(&self, expected: &Type, found: &Type) -> bool {
+        match (expected, found) {
+            (Type::Any, _) | (_, Type::Any) => return true,
+            (Type::Function(_), Type::Function(_) | Type::Class(_)) => return true,
+            (Type::Instance(expected), Type::Instance(found)) => {
+                // an instance of a subclass fits wherever its superclass does
+                let mut class = Some(found.clone());
+                while let Some(name) = class {
+                    if name == *expected {
+                        return true;
+                    }
+                    class = self.classes.get(&name).cloned().flatten();
+                }
+                return false;
+            }
+            (expected, found) => return expected == found,
+        }
+    }
+
+    fn assignable(&mut self, name: &Token, declared: &Type, value: &Type) {
+        if !self.accepts(declared, value) {
+            let msg = format!(
+                "cannot assign {} to '{}' of type {}",
+                value, name.lexeme, declared
+            );
+            self.error(name, msg);
+        }
+    }
+
+    fn declare(&mut self, name: &Token, typ: Type) {
+        self.scopes
+            .last_mut()
+            .expect("the global scope is never popped")
+            .insert(name.lexeme.clone(), typ);
+    }
+
+    fn lookup(&self, name: &Token) -> Type {
+        for scope in self.scopes.iter().rev() {
+            if let Some(typ) = scope.get(&name.lexeme) {
+                return typ.clone();
+            }
+        }
+        return Type::Any;
+    }
+
+    fn error(&mut self, token: &Token, msg: String) {
+        self.errors
+            .push(Diagnostic::at_token(DiagnosticKind::Type, msg, token));
+    }
+}
+
+// whether every path through the statement ends in a return or a throw, loops
+// are assumed to be able to run zero times unless they can only be left
+// through a return
+fn always_returns(statement: &Stmt) -> bool {
+    match statement {
+        Stmt::ReturnStmt {
+            keyword: _,
+            value: _,
+        }
+        | Stmt::Throw {
+            keyword: _,
+            value: _,
+        } => return true,
+        Stmt::Block { statements } => return statements.iter().any(|s| always_returns(s)),
+        Stmt::WhileStmt {
+            condition:
+                Expr::Literal {
+                    id: _,
+                    value: LiteralValue::True,
+                },
+            body,
+            increment: _,
+        } => return !breaks(body),
+        Stmt::IfStmt {
+            predicate: _,
+            then,
+            els: Some(els),
+        } => return always_returns(then) && always_returns(els),
+        Stmt::Try {
+            keyword: _,
+            body,
+            catch,
+            finally,
+        } => {
+            let handled = match catch {
+                Some((_, handler)) => always_returns(handler),
+                None => true,
+            };
+            return (always_returns(body) && handled)
+                || finally
+                    .as_ref()
+                    .is_some_and(|finally| always_returns(finally));
+        }
+        _ => return false,
+    }
+}
+
+// whether the statement can break out of the loop it is in, breaks in nested
+// loops and functions leave those instead
+fn breaks(statement: &Stmt) -> bool {
+    match statement {
+        Stmt::Break { keyword: _ } => return true,
+        Stmt::Block { statements } => return statements.iter().any(|s| breaks(s)),
+        Stmt::IfStmt {
+            predicate: _,
+            then,
+            els,
+        } => return breaks(then) || els.as_ref().is_some_and(|els| breaks(els)),
+        Stmt::Try {
+            keyword: _,
+            body,
+            catch,
+            finally,
+        } => {
+            return breaks(body)
+                || catch.as_ref().is_some_and(|(_, handler)| breaks(handler))
+                || finally.as_ref().is_some_and(|finally| breaks(finally));
+        }
+        _ => return false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn errors(source: &str) -> Vec<String> {
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        return match Checker::new().check(&statements.iter().collect()) {
+            Ok(()) => vec![],
+            Err(errors) => errors.into_iter().map(|error| error.message).collect(),
+        };
+    }
+
+    #[test]
+    fn accepts_unannotated_code() {
+        assert_eq!(
+            errors("var a = 1; a = \"s\"; fun f(x) { return x + 1; } print f(\"s\") + a; print nil + 1; f = 5; class A {} A = nil;"),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn reports_operator_mismatches() {
+        assert_eq!(
+            errors(
+                "var n: Number = 1; var s: String = \"a\"; print n + s; print -s; print s < \"b\";"
+            ),
+            vec![
+                "cannot apply '+' to Number and String",
+                "cannot apply '-' to String"
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_calls_and_returns() {
+        assert_eq!(
+            errors("fun f(a: String, b) -> Bool { if (b) return nil; return a == \"x\"; } var n: Number = f(1, 2);"),
+            vec![
+                "'f' must return Bool, found Nil",
+                "cannot assign Bool to 'n' of type Number",
+                "argument 1 of 'f' must be String, found Number"
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_falling_off_the_end() {
+        assert_eq!(
+            errors("fun a() -> Number {} fun b(x: Number) -> Number { if (x > 0) return 1; } fun c(x) -> Number { if (x) return 1; else throw \"no\"; } fun d() -> Nil {} fun e(x: Number) -> Number { while (true) { return x; } } fun g(x: Number) -> Number { for (;;) { if (x > 0) break; return x; } } class P { n() -> Number { return 1; } m() -> Bool {} }"),
+            vec![
+                "'a' must return Number, found Nil",
+                "'b' must return Number, found Nil",
+                "'g' must return Number, found Nil",
+                "'m' must return Bool, found Nil"
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_classes() {
+        assert_eq!(
+            errors("class A {} class B < A {} var a: A = B(); var b: B = A(); var c: Point;"),
+            vec!["cannot assign A to 'b' of type B", "unknown type 'Point'"]
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use crate::expr;
+use crate::scanner;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum OpCode {
+    Constant, // u16 constant index
+    Nil,
+    True,
+    False,
+    Pop,
+    GetLocal,     // u8 slot
+    SetLocal,     // u8 slot
+    GetGlobal,    // u16 name constant
+    DefineGlobal, // u16 name constant
+    SetGlobal,    // u16 name constant
+    GetUpvalue,   // u8 upvalue index
+    SetUpvalue,   // u8 upvalue index
+    GetProperty,  // u16 name constant
+    SetProperty,  // u16 name constant
+    GetSuper,     // u16 name constant
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Print,
+    Jump,        // u16 forward offset
+    JumpIfFalse, // u16 forward offset, leaves the condition on the stack
+    Loop,        // u16 backward offset
+    Call,        // u8 argument count
+    Closure,     // u16 function index, then (is_local, index) per upvalue
+    CloseUpvalue,
+    Return,
+    Class,     // u16 name constant, u8 has_superclass
+    Method,    // u16 name constant
+    BuildList, // u16 element count
+    BuildMap,  // u16 entry count, keys and values interleaved
+    GetIndex,
+    SetIndex,
+    Import,      // u16 path constant
+    Concat,      // u16 part count, joins the parts as print would show them
+    PushHandler, // u16 forward offset to the handler, u8 whether it is a finally block
+    PopHandler,
+    Throw,
+    Rethrow,      // resumes the error a finally block was entered with
+    MatchLiteral, // pops a pattern and a value, pushes whether they are equal, any types
+    MatchClass,   // u16 class name constant, replaces the value with whether it is one
+    NoMatch,      // fails with the value no match arm took
+}
+
+const OPCODES: [OpCode; 50] = [
+    OpCode::Constant,
+    OpCode::Nil,
+    OpCode::True,
+    OpCode::False,
+    OpCode::Pop,
+    OpCode::GetLocal,
+    OpCode::SetLocal,
+    OpCode::GetGlobal,
+    OpCode::DefineGlobal,
+    OpCode::SetGlobal,
+    OpCode::GetUpvalue,
+    OpCode::SetUpvalue,
+    OpCode::GetProperty,
+    OpCode::SetProperty,
+    OpCode::GetSuper,
+    OpCode::Equal,
+    OpCode::NotEqual,
+    OpCode::Greater,
+    OpCode::GreaterEqual,
+    OpCode::Less,
+    OpCode::LessEqual,
+    OpCode::Add,
+    OpCode::Subtract,
+    OpCode::Multiply,
+    OpCode::Divide,
+    OpCode::Not,
+    OpCode::Negate,
+    OpCode::Print,
+    OpCode::Jump,
+    OpCode::JumpIfFalse,
+    OpCode::Loop,
+    OpCode::Call,
+    OpCode::Closure,
+    OpCode::CloseUpvalue,
+    OpCode::Return,
+    OpCode::Class,
+    OpCode::Method,
+    OpCode::BuildList,
+    OpCode::BuildMap,
+    OpCode::GetIndex,
+    OpCode::SetIndex,
+    OpCode::Import,
+    OpCode::Concat,
+    OpCode::PushHandler,
+    OpCode::PopHandler,
+    OpCode::Throw,
+    OpCode::Rethrow,
+    OpCode::MatchLiteral,
+    OpCode::MatchClass,
+    OpCode::NoMatch,
+];
+
+impl OpCode {
+    pub fn from_byte(byte: u8) -> OpCode {
+        return OPCODES[byte as usize];
+    }
+}
+
+pub struct Function {
+    pub name: String,
+    pub arity: usize,
+    pub upvalue_count: usize,
+    pub chunk: Chunk,
+}
+
+impl Function {
+    pub fn new(name: &str) -> Self {
+        return Self {
+            name: name.to_string(),
+            arity: 0,
+            upvalue_count: 0,
+            chunk: Chunk::new(),
+        };
+    }
+}
+
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<expr::LiteralValue>,
+    pub functions: Vec<Rc<Function>>,
+    // every byte points at the token it was compiled from, for error locations
+    tokens: Vec<scanner::Token>,
+    token_index: Vec<u32>,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        return Self {
+            code: vec![],
+            constants: vec![],
+            functions: vec![],
+            tokens: vec![],
+            token_index: vec![],
+        };
+    }
+
+    pub fn write(&mut self, byte: u8, token: &scanner::Token) {
+        let same_token = match self.tokens.last() {
+            Some(last) => last.offset == token.offset && last.lexeme == token.lexeme,
+            None => false,
+        };
+        if !same_token {
+            self.tokens.push(token.clone());
+        }
+
+        self.code.push(byte);
+        self.token_index.push((self.tokens.len() - 1) as u32);
+    }
+
+    pub fn add_constant(&mut self, value: expr::LiteralValue) -> usize {
+        self.constants.push(value);
+        return self.constants.len() - 1;
+    }
+
+    pub fn token_at(&self, offset: usize) -> &scanner::Token {
+        return &self.tokens[self.token_index[offset] as usize];
+    }
+
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        return ((self.code[offset] as u16) << 8) | self.code[offset + 1] as u16;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(offset: usize) -> scanner::Token {
+        return scanner::Token {
+            token_type: scanner::TokenType::Nil,
+            lexeme: "nil".to_string(),
+            literal: None,
+            line_number: 1,
+            column: offset + 1,
+            offset,
+        };
+    }
+
+    #[test]
+    fn opcodes_round_trip() {
+        for (i, op) in OPCODES.iter().enumerate() {
+            assert_eq!(*op as u8, i as u8);
+            assert_eq!(OpCode::from_byte(i as u8), *op);
+        }
+    }
+
+    #[test]
+    fn bytes_remember_their_tokens() {
+        let mut chunk = Chunk::new();
+        chunk.write(OpCode::Nil as u8, &token(0));
+        chunk.write(OpCode::Nil as u8, &token(4));
+        chunk.write(OpCode::Pop as u8, &token(4));
+
+        assert_eq!(chunk.token_at(0).offset, 0);
+        assert_eq!(chunk.token_at(1).offset, 4);
+        assert_eq!(chunk.token_at(2).offset, 4);
+        assert_eq!(chunk.tokens.len(), 2);
+    }
+}
@@ -0,0 +1,1049 @@
+use crate::chunk::{Function, OpCode};
+use crate::diagnostic::{Diagnostic, DiagnosticKind};
+use crate::expr;
+use crate::scanner;
+use crate::stmt;
+use std::rc::Rc;
+
+const MAX_LOCALS: usize = 256;
+const MAX_UPVALUES: usize = 256;
+
+// the name of the functions match expressions compile to, a keyword so it
+// cannot clash with a function in the source
+pub const MATCH_FUNCTION: &str = "match";
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionKind {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+
+struct Local {
+    name: String,
+    depth: Option<usize>,
+    is_captured: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct UpvalueRef {
+    index: u8,
+    is_local: bool,
+}
+
+// jumps out of a loop body that are patched once the loop's end is known
+struct LoopState {
+    scope_depth: usize,
+    // handlers pushed inside the body are exited by break and continue
+    handler_depth: usize,
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+// a try block that is being compiled, jumps out of it pop its handler and
+// run its finally block on the way
+#[derive(Clone)]
+struct HandlerState {
+    finally: Option<stmt::Stmt>,
+}
+
+struct FunctionState {
+    function: Function,
+    kind: FunctionKind,
+    locals: Vec<Local>,
+    upvalues: Vec<UpvalueRef>,
+    scope_depth: usize,
+    loops: Vec<LoopState>,
+    handlers: Vec<HandlerState>,
+}
+
+// compiles a resolved program into bytecode, the resolver has already rejected
+// programs with scoping errors so those are not checked again here
+pub struct Compiler {
+    states: Vec<FunctionState>,
+    token: scanner::Token,
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        return Self {
+            states: vec![],
+            token: scanner::Token {
+                token_type: scanner::TokenType::Eof,
+                lexeme: "".to_string(),
+                literal: None,
+                line_number: 0,
+                column: 0,
+                offset: 0,
+            },
+        };
+    }
+
+    pub fn compile(mut self, stmts: &Vec<&stmt::Stmt>) -> Result<Rc<Function>, Diagnostic> {
+        self.begin_function("script", FunctionKind::Script);
+        for stm in stmts {
+            self.statement(stm)?;
+        }
+        let (function, _) = self.end_function();
+
+        return Ok(Rc::new(function));
+    }
+
+    fn statement(&mut self, stm: &stmt::Stmt) -> Result<(), Diagnostic> {
+        match stm {
+            stmt::Stmt::Expression { expression } => {
+                self.expression(expression)?;
+                self.emit_op(OpCode::Pop);
+            }
+            stmt::Stmt::Print { expression } => {
+                self.expression(expression)?;
+                self.emit_op(OpCode::Print);
+            }
+            stmt::Stmt::Var {
+                name,
+                annotation: _,
+                initializer,
+            } => {
+                self.at(name);
+                self.declare_variable(name)?;
+                self.expression(initializer)?;
+                self.at(name);
+                self.define_variable(name)?;
+            }
+            stmt::Stmt::Block { statements } => {
+                self.begin_scope();
+                for statement in statements {
+                    self.statement(statement)?;
+                }
+                self.end_scope();
+            }
+            stmt::Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => self.class_declaration(name, superclass, methods)?,
+            stmt::Stmt::IfStmt {
+                predicate,
+                then,
+                els,
+            } => {
+                self.expression(predicate)?;
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+                self.statement(then)?;
+                let else_jump = self.emit_jump(OpCode::Jump);
+
+                self.patch_jump(then_jump)?;
+                self.emit_op(OpCode::Pop);
+                if let Some(els) = els {
+                    self.statement(els)?;
+                }
+                self.patch_jump(else_jump)?;
+            }
+            stmt::Stmt::WhileStmt {
+                condition,
+                body,
+                increment,
+            } => {
+                let loop_start = self.current().function.chunk.code.len();
+                self.expression(condition)?;
+                let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+
+                let scope_depth = self.current().scope_depth;
+                let handler_depth = self.current().handlers.len();
+                self.current().loops.push(LoopState {
+                    scope_depth,
+                    handler_depth,
+                    breaks: vec![],
+                    continues: vec![],
+                });
+                self.statement(body)?;
+                let state = self.current().loops.pop().expect("loop state underflow");
+
+                for jump in state.continues {
+                    self.patch_jump(jump)?;
+                }
+                if let Some(increment) = increment {
+                    self.expression(increment)?;
+                    self.emit_op(OpCode::Pop);
+                }
+                self.emit_loop(loop_start)?;
+
+                self.patch_jump(exit_jump)?;
+                self.emit_op(OpCode::Pop);
+                // breaks leave from inside the body, where the condition is already popped
+                for jump in state.breaks {
+                    self.patch_jump(jump)?;
+                }
+            }
+            stmt::Stmt::Break { keyword } => {
+                self.at(keyword);
+                let handler_depth = self.current_loop().handler_depth;
+                self.exit_handlers(handler_depth)?;
+                self.at(keyword);
+                self.discard_loop_locals();
+                let jump = self.emit_jump(OpCode::Jump);
+                self.current_loop().breaks.push(jump);
+            }
+            stmt::Stmt::Continue { keyword } => {
+                self.at(keyword);
+                let handler_depth = self.current_loop().handler_depth;
+                self.exit_handlers(handler_depth)?;
+                self.at(keyword);
+                self.discard_loop_locals();
+                let jump = self.emit_jump(OpCode::Jump);
+                self.current_loop().continues.push(jump);
+            }
+            stmt::Stmt::Function {
+                name,
+                params,
+                param_annotations: _,
+                return_annotation: _,
+                body,
+            } => {
+                self.at(name);
+                self.declare_variable(name)?;
+                // a function may refer to itself, so it is usable before its body is compiled
+                self.mark_initialized();
+                self.function(&name.lexeme, FunctionKind::Function, params, body)?;
+                self.at(name);
+                self.define_variable(name)?;
+            }
+            stmt::Stmt::Import { path, name } => {
+                self.at(name);
+                self.declare_variable(name)?;
+                let constant = self.make_constant(expr::LiteralValue::from_token(path.clone()))?;
+                self.at(path);
+                self.emit_op(OpCode::Import);
+                self.emit_u16(constant);
+                self.at(name);
+                self.define_variable(name)?;
+            }
+            stmt::Stmt::ReturnStmt { keyword, value } => {
+                self.at(keyword);
+                match value {
+                    Some(value) => self.expression(value)?,
+                    None => self.emit_return_value(),
+                }
+                if !self.current().handlers.is_empty() {
+                    // finally blocks run while the return value waits in a hidden local
+                    self.begin_scope();
+                    self.add_local("")?;
+                    self.mark_initialized();
+                    self.exit_handlers(0)?;
+                    self.forget_scope();
+                }
+                self.at(keyword);
+                self.emit_op(OpCode::Return);
+            }
+            stmt::Stmt::Throw { keyword, value } => {
+                self.expression(value)?;
+                self.at(keyword);
+                self.emit_op(OpCode::Throw);
+            }
+            stmt::Stmt::Try {
+                keyword,
+                body,
+                catch,
+                finally,
+            } => self.try_statement(keyword, body, catch, finally)?,
+        }
+
+        return Ok(());
+    }
+
+    fn class_declaration(
+        &mut self,
+        name: &scanner::Token,
+        superclass: &Option<expr::Expr>,
+        methods: &[Box<stmt::Stmt>],
+    ) -> Result<(), Diagnostic> {
+        self.at(name);
+        self.declare_variable(name)?;
+        let is_local = self.current().scope_depth > 0;
+        if is_local {
+            // reserve the local slot now so methods can capture the class by name
+            self.emit_op(OpCode::Nil);
+            self.mark_initialized();
+        }
+
+        if let Some(superclass) = superclass {
+            self.expression(superclass)?;
+            self.begin_scope();
+            self.add_local("super")?;
+            self.mark_initialized();
+        }
+
+        let name_constant = self.identifier_constant(&name.lexeme)?;
+        self.at(name);
+        self.emit_op(OpCode::Class);
+        self.emit_u16(name_constant);
+        self.emit_byte(superclass.is_some() as u8);
+
+        for method in methods {
+            if let stmt::Stmt::Function {
+                name: method_name,
+                params,
+                param_annotations: _,
+                return_annotation: _,
+                body,
+            } = method.as_ref()
+            {
+                let kind = if method_name.lexeme == "init" {
+                    FunctionKind::Initializer
+                } else {
+                    FunctionKind::Method
+                };
+                self.function(&method_name.lexeme, kind, params, body)?;
+
+                let method_constant = self.identifier_constant(&method_name.lexeme)?;
+                self.at(method_name);
+                self.emit_op(OpCode::Method);
+                self.emit_u16(method_constant);
+            } else {
+                panic!("class method expects function type");
+            }
+        }
+
+        self.at(name);
+        if is_local {
+            let slot = self.resolve_local(self.states.len() - 1, &name.lexeme);
+            self.emit_op(OpCode::SetLocal);
+            self.emit_byte(slot.expect("class slot was reserved above"));
+            self.emit_op(OpCode::Pop);
+        } else {
+            // the super scope is still open here, so define_variable would treat the class as local
+            let constant = self.identifier_constant(&name.lexeme)?;
+            self.emit_op(OpCode::DefineGlobal);
+            self.emit_u16(constant);
+        }
+
+        if superclass.is_some() {
+            self.end_scope();
+        }
+
+        return Ok(());
+    }
+
+    fn try_statement(
+        &mut self,
+        keyword: &scanner::Token,
+        body: &stmt::Stmt,
+        catch: &Option<(scanner::Token, Box<stmt::Stmt>)>,
+        finally: &Option<Box<stmt::Stmt>>,
+    ) -> Result<(), Diagnostic> {
+        self.at(keyword);
+        let finally_handler = match finally {
+            Some(finally) => {
+                let jump = self.emit_handler(true);
+                self.current().handlers.push(HandlerState {
+                    finally: Some(finally.as_ref().clone()),
+                });
+                Some(jump)
+            }
+            None => None,
+        };
+
+        match catch {
+            Some((name, handler)) => {
+                let catch_handler = self.emit_handler(false);
+                self.current().handlers.push(HandlerState { finally: None });
+                self.statement(body)?;
+                self.current().handlers.pop();
+                self.at(keyword);
+                self.emit_op(OpCode::PopHandler);
+                let end_jump = self.emit_jump(OpCode::Jump);
+
+                // the vm pushes the caught value where the catch variable's slot is
+                self.patch_jump(catch_handler)?;
+                self.begin_scope();
+                self.at(name);
+                self.add_local(&name.lexeme)?;
+                self.mark_initialized();
+                self.statement(handler)?;
+                self.end_scope();
+                self.patch_jump(end_jump)?;
+            }
+            None => self.statement(body)?,
+        }
+
+        if let (Some(finally), Some(finally_handler)) = (finally, finally_handler) {
+            self.current().handlers.pop();
+            self.at(keyword);
+            self.emit_op(OpCode::PopHandler);
+            self.statement(finally)?;
+            let end_jump = self.emit_jump(OpCode::Jump);
+
+            // on an error the vm pushes a reference to it, which Rethrow resumes
+            // once the block completes
+            self.patch_jump(finally_handler)?;
+            self.begin_scope();
+            self.add_local("")?;
+            self.mark_initialized();
+            self.statement(finally)?;
+            self.at(keyword);
+            self.emit_op(OpCode::Rethrow);
+            self.forget_scope();
+            self.patch_jump(end_jump)?;
+        }
+
+        return Ok(());
+    }
+
+    fn function(
+        &mut self,
+        name: &str,
+        kind: FunctionKind,
+        params: &[scanner::Token],
+        body: &[Box<stmt::Stmt>],
+    ) -> Result<(), Diagnostic> {
+        self.begin_function(name, kind);
+        self.begin_scope();
+
+        for param in params {
+            self.at(param);
+            self.add_local(&param.lexeme)?;
+            self.mark_initialized();
+        }
+        self.current().function.arity = params.len();
+
+        for statement in body {
+            self.statement(statement)?;
+        }
+
+        self.emit_closure();
+        return Ok(());
+    }
+
+    // match (subject) { ... } is called like fun (subject) { ... }, so the
+    // subject and the bindings get slots of their own wherever the match is
+    fn match_expression(
+        &mut self,
+        keyword: &scanner::Token,
+        subject: &expr::Expr,
+        arms: &Vec<(expr::Pattern, expr::Expr)>,
+    ) -> Result<(), Diagnostic> {
+        self.at(keyword);
+        self.begin_function(MATCH_FUNCTION, FunctionKind::Function);
+        self.begin_scope();
+        self.add_local("")?;
+        self.mark_initialized();
+        self.current().function.arity = 1;
+
+        for (pattern, body) in arms {
+            self.emit_op(OpCode::GetLocal);
+            self.emit_byte(1);
+            match pattern {
+                expr::Pattern::Literal { value } => {
+                    self.expression(value)?;
+                    self.emit_op(OpCode::MatchLiteral);
+                }
+                expr::Pattern::Class { class, name: _ } => {
+                    let constant = self.identifier_constant(&class.lexeme)?;
+                    self.at(class);
+                    self.emit_op(OpCode::MatchClass);
+                    self.emit_u16(constant);
+                }
+                expr::Pattern::Binding { name: _ } => self.emit_op(OpCode::True),
+            }
+            let next_arm = self.emit_jump(OpCode::JumpIfFalse);
+            self.emit_op(OpCode::Pop);
+
+            self.begin_scope();
+            if let Some(name) = pattern.binding() {
+                self.at(name);
+                self.emit_op(OpCode::GetLocal);
+                self.emit_byte(1);
+                self.add_local(&name.lexeme)?;
+                self.mark_initialized();
+            }
+            self.expression(body)?;
+            self.emit_op(OpCode::Return);
+            self.forget_scope();
+
+            self.patch_jump(next_arm)?;
+            self.emit_op(OpCode::Pop);
+        }
+
+        self.at(keyword);
+        self.emit_op(OpCode::GetLocal);
+        self.emit_byte(1);
+        self.emit_op(OpCode::NoMatch);
+        self.emit_closure();
+
+        self.expression(subject)?;
+        self.at(keyword);
+        self.emit_op(OpCode::Call);
+        self.emit_byte(1);
+        return Ok(());
+    }
+
+    // ends the function being compiled and emits the closure for it in the
+    // enclosing one
+    fn emit_closure(&mut self) {
+        let (function, upvalues) = self.end_function();
+
+        let chunk = &mut self.current().function.chunk;
+        chunk.functions.push(Rc::new(function));
+        let index = chunk.functions.len() - 1;
+
+        self.emit_op(OpCode::Closure);
+        self.emit_u16(index as u16);
+        for upvalue in upvalues {
+            self.emit_byte(upvalue.is_local as u8);
+            self.emit_byte(upvalue.index);
+        }
+    }
+
+    fn begin_function(&mut self, name: &str, kind: FunctionKind) {
+        // slot zero holds the callee, or the instance for methods
+        let slot_zero = match kind {
+            FunctionKind::Method | FunctionKind::Initializer => "this",
+            FunctionKind::Script | FunctionKind::Function => "",
+        };
+
+        self.states.push(FunctionState {
+            function: Function::new(name),
+            kind,
+            locals: vec![Local {
+                name: slot_zero.to_string(),
+                depth: Some(0),
+                is_captured: false,
+            }],
+            upvalues: vec![],
+            scope_depth: 0,
+            loops: vec![],
+            handlers: vec![],
+        });
+    }
+
+    fn end_function(&mut self) -> (Function, Vec<UpvalueRef>) {
+        self.emit_return();
+        let mut state = self.states.pop().expect("function state underflow");
+        state.function.upvalue_count = state.upvalues.len();
+
+        return (state.function, state.upvalues);
+    }
+
+    fn expression(&mut self, exp: &expr::Expr) -> Result<(), Diagnostic> {
+        match exp {
+            expr::Expr::AnonFunction {
+                id: _,
+                paren,
+                arguments,
+                body,
+            } => {
+                self.at(paren);
+                let name = format!("anon_function@{}", paren.line_number);
+                self.function(&name, FunctionKind::Function, arguments, body)?;
+            }
+            expr::Expr::Assign { id: _, name, value } => {
+                self.expression(value)?;
+                self.at(name);
+                self.named_variable(&name.lexeme, true)?;
+            }
+            expr::Expr::Binary {
+                id: _,
+                left,
+                operator,
+                right,
+            } => {
+                self.expression(left)?;
+                self.expression(right)?;
+                self.at(operator);
+                let op = match operator.token_type {
+                    scanner::TokenType::Plus => OpCode::Add,
+                    scanner::TokenType::Minus => OpCode::Subtract,
+                    scanner::TokenType::Star => OpCode::Multiply,
+                    scanner::TokenType::Slash => OpCode::Divide,
+                    scanner::TokenType::Greater => OpCode::Greater,
+                    scanner::TokenType::GreaterEqual => OpCode::GreaterEqual,
+                    scanner::TokenType::Less => OpCode::Less,
+                    scanner::TokenType::LessEqual => OpCode::LessEqual,
+                    scanner::TokenType::EqualEqual => OpCode::Equal,
+                    scanner::TokenType::BangEqual => OpCode::NotEqual,
+                    other => return Err(self.error(&format!("{} is not a binary operator", other))),
+                };
+                self.emit_op(op);
+            }
+            expr::Expr::Call {
+                id: _,
+                callee,
+                paren,
+                arguments,
+            } => {
+                self.expression(callee)?;
+                for argument in arguments {
+                    self.expression(argument)?;
+                }
+                self.at(paren);
+                self.emit_op(OpCode::Call);
+                self.emit_byte(arguments.len() as u8);
+            }
+            expr::Expr::Get {
+                id: _,
+                object,
+                name,
+            } => {
+                self.expression(object)?;
+                let constant = self.identifier_constant(&name.lexeme)?;
+                self.at(name);
+                self.emit_op(OpCode::GetProperty);
+                self.emit_u16(constant);
+            }
+            expr::Expr::Grouping { id: _, expression } => self.expression(expression)?,
+            expr::Expr::Index {
+                id: _,
+                object,
+                bracket,
+                index,
+            } => {
+                self.expression(object)?;
+                self.expression(index)?;
+                self.at(bracket);
+                self.emit_op(OpCode::GetIndex);
+            }
+            expr::Expr::Interpolation {
+                id: _,
+                quote,
+                parts,
+            } => {
+                for part in parts {
+                    self.expression(part)?;
+                }
+                self.at(quote);
+                self.emit_op(OpCode::Concat);
+                self.emit_u16(self.operand_count(parts.len())?);
+            }
+            expr::Expr::List {
+                id: _,
+                bracket,
+                elements,
+            } => {
+                for element in elements {
+                    self.expression(element)?;
+                }
+                self.at(bracket);
+                self.emit_op(OpCode::BuildList);
+                self.emit_u16(self.operand_count(elements.len())?);
+            }
+            expr::Expr::Map {
+                id: _,
+                brace,
+                entries,
+            } => {
+                for (key, value) in entries {
+                    self.expression(key)?;
+                    self.expression(value)?;
+                }
+                self.at(brace);
+                self.emit_op(OpCode::BuildMap);
+                self.emit_u16(self.operand_count(entries.len())?);
+            }
+            expr::Expr::Literal { id: _, value } => match value {
+                expr::LiteralValue::Nil => self.emit_op(OpCode::Nil),
+                expr::LiteralValue::True => self.emit_op(OpCode::True),
+                expr::LiteralValue::False => self.emit_op(OpCode::False),
+                value => {
+                    let constant = self.make_constant(value.clone())?;
+                    self.emit_op(OpCode::Constant);
+                    self.emit_u16(constant);
+                }
+            },
+            expr::Expr::Logical {
+                id: _,
+                left,
+                operator,
+                right,
+            } => {
+                self.expression(left)?;
+                self.at(operator);
+                if operator.token_type == scanner::TokenType::Or {
+                    let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                    let end_jump = self.emit_jump(OpCode::Jump);
+                    self.patch_jump(else_jump)?;
+                    self.emit_op(OpCode::Pop);
+                    self.expression(right)?;
+                    self.patch_jump(end_jump)?;
+                } else {
+                    // a falsy left hand side of 'and' evaluates to false, not to itself
+                    let false_jump = self.emit_jump(OpCode::JumpIfFalse);
+                    self.emit_op(OpCode::Pop);
+                    self.expression(right)?;
+                    let end_jump = self.emit_jump(OpCode::Jump);
+                    self.patch_jump(false_jump)?;
+                    self.emit_op(OpCode::Pop);
+                    self.emit_op(OpCode::False);
+                    self.patch_jump(end_jump)?;
+                }
+            }
+            expr::Expr::Match {
+                id: _,
+                keyword,
+                subject,
+                arms,
+            } => self.match_expression(keyword, subject, arms)?,
+            expr::Expr::Set {
+                id: _,
+                object,
+                name,
+                value,
+            } => {
+                self.expression(object)?;
+                self.expression(value)?;
+                let constant = self.identifier_constant(&name.lexeme)?;
+                self.at(name);
+                self.emit_op(OpCode::SetProperty);
+                self.emit_u16(constant);
+            }
+            expr::Expr::SetIndex {
+                id: _,
+                object,
+                bracket,
+                index,
+                value,
+            } => {
+                self.expression(object)?;
+                self.expression(index)?;
+                self.expression(value)?;
+                self.at(bracket);
+                self.emit_op(OpCode::SetIndex);
+            }
+            expr::Expr::Super {
+                id: _,
+                keyword,
+                method,
+            } => {
+                self.at(keyword);
+                self.named_variable("this", false)?;
+                self.named_variable("super", false)?;
+                let constant = self.identifier_constant(&method.lexeme)?;
+                self.at(method);
+                self.emit_op(OpCode::GetSuper);
+                self.emit_u16(constant);
+            }
+            expr::Expr::This { id: _, keyword } => {
+                self.at(keyword);
+                self.named_variable("this", false)?;
+            }
+            expr::Expr::Unary {
+                id: _,
+                operator,
+                right,
+            } => {
+                self.expression(right)?;
+                self.at(operator);
+                match operator.token_type {
+                    scanner::TokenType::Minus => self.emit_op(OpCode::Negate),
+                    scanner::TokenType::Bang => self.emit_op(OpCode::Not),
+                    other => {
+                        return Err(self.error(&format!("{} is not a valid unary operator", other)))
+                    }
+                }
+            }
+            expr::Expr::Variable { id: _, name } => {
+                self.at(name);
+                self.named_variable(&name.lexeme, false)?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn named_variable(&mut self, name: &str, assign: bool) -> Result<(), Diagnostic> {
+        let current = self.states.len() - 1;
+        if let Some(slot) = self.resolve_local(current, name) {
+            self.emit_op(if assign {
+                OpCode::SetLocal
+            } else {
+                OpCode::GetLocal
+            });
+            self.emit_byte(slot);
+        } else if let Some(index) = self.resolve_upvalue(current, name)? {
+            self.emit_op(if assign {
+                OpCode::SetUpvalue
+            } else {
+                OpCode::GetUpvalue
+            });
+            self.emit_byte(index);
+        } else {
+            let constant = self.identifier_constant(name)?;
+            self.emit_op(if assign {
+                OpCode::SetGlobal
+            } else {
+                OpCode::GetGlobal
+            });
+            self.emit_u16(constant);
+        }
+
+        return Ok(());
+    }
+
+    fn resolve_local(&self, state: usize, name: &str) -> Option<u8> {
+        for (i, local) in self.states[state].locals.iter().enumerate().rev() {
+            if local.name == name && local.depth.is_some() {
+                return Some(i as u8);
+            }
+        }
+        return None;
+    }
+
+    fn resolve_upvalue(&mut self, state: usize, name: &str) -> Result<Option<u8>, Diagnostic> {
+        if state == 0 {
+            return Ok(None);
+        }
+
+        if let Some(local) = self.resolve_local(state - 1, name) {
+            self.states[state - 1].locals[local as usize].is_captured = true;
+            return Ok(Some(self.add_upvalue(state, local, true)?));
+        }
+
+        if let Some(upvalue) = self.resolve_upvalue(state - 1, name)? {
+            return Ok(Some(self.add_upvalue(state, upvalue, false)?));
+        }
+
+        return Ok(None);
+    }
+
+    fn add_upvalue(&mut self, state: usize, index: u8, is_local: bool) -> Result<u8, Diagnostic> {
+        let upvalue = UpvalueRef { index, is_local };
+        let upvalues = &mut self.states[state].upvalues;
+        if let Some(existing) = upvalues.iter().position(|u| *u == upvalue) {
+            return Ok(existing as u8);
+        }
+
+        if upvalues.len() >= MAX_UPVALUES {
+            return Err(self.error("too many closure variables in function"));
+        }
+        upvalues.push(upvalue);
+
+        return Ok((upvalues.len() - 1) as u8);
+    }
+
+    fn declare_variable(&mut self, name: &scanner::Token) -> Result<(), Diagnostic> {
+        if self.current().scope_depth == 0 {
+            return Ok(());
+        }
+        return self.add_local(&name.lexeme);
+    }
+
+    fn define_variable(&mut self, name: &scanner::Token) -> Result<(), Diagnostic> {
+        if self.current().scope_depth > 0 {
+            self.mark_initialized();
+            return Ok(());
+        }
+
+        let constant = self.identifier_constant(&name.lexeme)?;
+        self.emit_op(OpCode::DefineGlobal);
+        self.emit_u16(constant);
+
+        return Ok(());
+    }
+
+    fn add_local(&mut self, name: &str) -> Result<(), Diagnostic> {
+        if self.current().locals.len() >= MAX_LOCALS {
+            return Err(self.error("too many local variables in function"));
+        }
+
+        self.current().locals.push(Local {
+            name: name.to_string(),
+            depth: None,
+            is_captured: false,
+        });
+
+        return Ok(());
+    }
+
+    fn mark_initialized(&mut self) {
+        let state = self.current();
+        if state.scope_depth == 0 {
+            return;
+        }
+        let depth = state.scope_depth;
+        if let Some(local) = state.locals.last_mut() {
+            local.depth = Some(depth);
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.current().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        self.current().scope_depth -= 1;
+        let depth = self.current().scope_depth;
+
+        loop {
+            let captured = match self.current().locals.last() {
+                Some(local) if local.depth.is_none_or(|d| d > depth) => local.is_captured,
+                _ => break,
+            };
+
+            if captured {
+                self.emit_op(OpCode::CloseUpvalue);
+            } else {
+                self.emit_op(OpCode::Pop);
+            }
+            self.current().locals.pop();
+        }
+    }
+
+    // closes a scope whose locals the next instruction consumes, such as Return
+    // or Rethrow, so no pops are emitted
+    fn forget_scope(&mut self) {
+        self.current().scope_depth -= 1;
+        let depth = self.current().scope_depth;
+        let state = self.current();
+        while state
+            .locals
+            .last()
+            .is_some_and(|local| local.depth.is_none_or(|d| d > depth))
+        {
+            state.locals.pop();
+        }
+    }
+
+    // pops every handler above the given depth, innermost first, and runs the
+    // finally blocks on the way. the handlers stay active for the code that
+    // follows, since the jump out of them only happens at runtime
+    fn exit_handlers(&mut self, depth: usize) -> Result<(), Diagnostic> {
+        let handlers = self.current().handlers.clone();
+        for i in (depth..handlers.len()).rev() {
+            self.emit_op(OpCode::PopHandler);
+            self.current().handlers.truncate(i);
+            if let Some(finally) = &handlers[i].finally {
+                self.statement(finally)?;
+            }
+        }
+        self.current().handlers = handlers;
+        return Ok(());
+    }
+
+    // pops the locals declared inside the innermost loop body without ending their
+    // scopes, since compilation continues after a break or continue
+    fn discard_loop_locals(&mut self) {
+        let depth = self.current_loop().scope_depth;
+        let mut ops = vec![];
+        for local in self.current().locals.iter().rev() {
+            if local.depth.is_some_and(|d| d <= depth) {
+                break;
+            }
+            ops.push(if local.is_captured {
+                OpCode::CloseUpvalue
+            } else {
+                OpCode::Pop
+            });
+        }
+        for op in ops {
+            self.emit_op(op);
+        }
+    }
+
+    fn current_loop(&mut self) -> &mut LoopState {
+        return self
+            .current()
+            .loops
+            .last_mut()
+            .expect("the resolver rejects break and continue outside of loops");
+    }
+
+    fn identifier_constant(&mut self, name: &str) -> Result<u16, Diagnostic> {
+        return self.make_constant(expr::LiteralValue::StringLit(name.to_string()));
+    }
+
+    fn make_constant(&mut self, value: expr::LiteralValue) -> Result<u16, Diagnostic> {
+        let constant = self.current().function.chunk.add_constant(value);
+        if constant > u16::MAX as usize {
+            return Err(self.error("too many constants in one chunk"));
+        }
+        return Ok(constant as u16);
+    }
+
+    fn operand_count(&self, count: usize) -> Result<u16, Diagnostic> {
+        if count > u16::MAX as usize {
+            return Err(self.error("too many elements in collection literal"));
+        }
+        return Ok(count as u16);
+    }
+
+    fn emit_return(&mut self) {
+        self.emit_return_value();
+        self.emit_op(OpCode::Return);
+    }
+
+    fn emit_return_value(&mut self) {
+        if self.current().kind == FunctionKind::Initializer {
+            self.emit_op(OpCode::GetLocal);
+            self.emit_byte(0);
+        } else {
+            self.emit_op(OpCode::Nil);
+        }
+    }
+
+    fn emit_handler(&mut self, finally: bool) -> usize {
+        let jump = self.emit_jump(OpCode::PushHandler);
+        self.emit_byte(finally as u8);
+        return jump;
+    }
+
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit_op(op);
+        self.emit_u16(u16::MAX);
+        return self.current().function.chunk.code.len() - 2;
+    }
+
+    fn patch_jump(&mut self, offset: usize) -> Result<(), Diagnostic> {
+        let jump = self.current().function.chunk.code.len() - offset - 2;
+        if jump > u16::MAX as usize {
+            return Err(self.error("too much code to jump over"));
+        }
+
+        let code = &mut self.current().function.chunk.code;
+        code[offset] = (jump >> 8) as u8;
+        code[offset + 1] = jump as u8;
+
+        return Ok(());
+    }
+
+    fn emit_loop(&mut self, loop_start: usize) -> Result<(), Diagnostic> {
+        self.emit_op(OpCode::Loop);
+        let offset = self.current().function.chunk.code.len() - loop_start + 2;
+        if offset > u16::MAX as usize {
+            return Err(self.error("loop body too large"));
+        }
+        self.emit_u16(offset as u16);
+
+        return Ok(());
+    }
+
+    fn emit_op(&mut self, op: OpCode) {
+        self.emit_byte(op as u8);
+    }
+
+    fn emit_u16(&mut self, value: u16) {
+        self.emit_byte((value >> 8) as u8);
+        self.emit_byte(value as u8);
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        let token = self.token.clone();
+        self.current().function.chunk.write(byte, &token);
+    }
+
+    fn at(&mut self, token: &scanner::Token) {
+        self.token = token.clone();
+    }
+
+    fn current(&mut self) -> &mut FunctionState {
+        return self
+            .states
+            .last_mut()
+            .expect("no function is being compiled");
+    }
+
+    fn error(&self, msg: &str) -> Diagnostic {
+        return Diagnostic::at_token(DiagnosticKind::Compile, msg, &self.token);
+    }
+}
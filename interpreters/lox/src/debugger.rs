@@ -0,0 +1,455 @@
+use crate::checker::Checker;
+use crate::diagnostic::Diagnostic;
+use crate::environment::{self, Environment};
+use crate::error::RuntimeError;
+use crate::interpreter::Interpreter;
+use crate::parser::Parser;
+use crate::resolver::Resolver;
+use crate::scanner::Scanner;
+use crate::stmt::Stmt;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+s, step         stop at the next statement, stepping into calls
+n, next         stop at the next statement in this function or its callers
+f, finish       run until the current function returns
+c, continue     run until the next breakpoint
+b, break <n>    set a breakpoint on line n
+d, delete <n>   remove the breakpoint on line n
+l, locals       print the local variables in scope
+g, globals      print the global variables
+bt, backtrace   print the active calls
+q, quit         stop the script";
+
+thread_local! {
+    // the debugger attached to the running script, the tree-walking
+    // interpreter reports every statement and call to it
+    static DEBUGGER: RefCell<Option<Debugger>> = const { RefCell::new(None) };
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    // run until a breakpoint
+    Continue,
+    // stop at the next statement
+    Step,
+    // stop at the next statement at most this many calls deep
+    Next(usize),
+    // stop at the next statement fewer than this many calls deep
+    Finish(usize),
+    Quit,
+}
+
+struct Frame {
+    function: String,
+    // the line the function was called from
+    line: usize,
+}
+
+struct Debugger {
+    source: String,
+    // the line each full expression starts on, from the parser
+    lines: HashMap<usize, usize>,
+    globals: Environment,
+    breakpoints: BTreeSet<usize>,
+    mode: Mode,
+    frames: Vec<Frame>,
+    last_command: String,
+    commands: Box<dyn BufRead>,
+    out: Box<dyn Write>,
+}
+
+pub fn run(source: &str) -> Result<(), Vec<Diagnostic>> {
+    return debug(source, Box::new(io::stdin().lock()), Box::new(io::stdout()));
+}
+
+// runs a script on the tree-walking interpreter, stopping at the first statement
+fn debug(
+    source: &str,
+    commands: Box<dyn BufRead>,
+    out: Box<dyn Write>,
+) -> Result<(), Vec<Diagnostic>> {
+    let tokens = Scanner::new(source).scan_tokens()?;
+    let mut parser = Parser::new(tokens);
+    let statements = parser.parse()?;
+    let locals = Resolver::new()
+        .resolve(&statements.iter().collect())
+        .map_err(|diagnostic| vec![diagnostic])?;
+    Checker::new().check(&statements.iter().collect())?;
+
+    let mut interp = Interpreter::new();
+    interp.resolve(locals);
+    let debugger = Debugger {
+        source: source.to_string(),
+        lines: parser.lines().clone(),
+        globals: interp.environment.clone(),
+        breakpoints: BTreeSet::new(),
+        mode: Mode::Step,
+        frames: vec![],
+        last_command: String::new(),
+        commands,
+        out,
+    };
+
+    DEBUGGER.with(|attached| *attached.borrow_mut() = Some(debugger));
+    let result = interp.interpret(statements.iter().collect());
+    let debugger = DEBUGGER.with(|attached| attached.borrow_mut().take());
+
+    match result {
+        Ok(_) => return Ok(()),
+        Err(_) if debugger.is_some_and(|debugger| debugger.mode == Mode::Quit) => return Ok(()),
+        Err(err) => return Err(vec![Diagnostic::from(err)]),
+    }
+}
+
+pub fn on_statement(statement: &Stmt, env: &Environment) -> Result<(), RuntimeError> {
+    return DEBUGGER.with(|attached| match attached.borrow_mut().as_mut() {
+        Some(debugger) => debugger.statement(statement, env),
+        None => Ok(()),
+    });
+}
+
+pub fn enter(function: &str, line: usize) {
+    DEBUGGER.with(|attached| {
+        if let Some(debugger) = attached.borrow_mut().as_mut() {
+            debugger.frames.push(Frame {
+                function: function.to_string(),
+                line,
+            });
+        }
+    });
+}
+
+pub fn exit() {
+    DEBUGGER.with(|attached| {
+        if let Some(debugger) = attached.borrow_mut().as_mut() {
+            debugger.frames.pop();
+        }
+    });
+}
+
+// blocks have no line of their own, the statements in them are used instead
+pub fn statement_line(statement: &Stmt, lines: &HashMap<usize, usize>) -> Option<usize> {
+    match statement {
+        Stmt::Expression { expression } | Stmt::Print { expression } => {
+            return lines.get(&expression.get_id()).copied();
+        }
+        Stmt::IfStmt {
+            predicate,
+            then: _,
+            els: _,
+        } => return lines.get(&predicate.get_id()).copied(),
+        Stmt::WhileStmt {
+            condition,
+            body: _,
+            increment: _,
+        } => return lines.get(&condition.get_id()).copied(),
+        Stmt::Var {
+            name,
+            annotation: _,
+            initializer: _,
+        } => return Some(name.line_number),
+        Stmt::Class {
+            name,
+            superclass: _,
+            methods: _,
+        } => return Some(name.line_number),
+        Stmt::Function {
+            name,
+            params: _,
+            param_annotations: _,
+            return_annotation: _,
+            body: _,
+        } => return Some(name.line_number),
+        Stmt::ReturnStmt { keyword, value: _ } => return Some(keyword.line_number),
+        Stmt::Break { keyword } | Stmt::Continue { keyword } => {
+            return Some(keyword.line_number);
+        }
+        Stmt::Import { path, name: _ } => return Some(path.line_number),
+        Stmt::Throw { keyword, value: _ } => return Some(keyword.line_number),
+        // the try keyword stops once, its blocks stop at their own statements
+        Stmt::Try {
+            keyword,
+            body: _,
+            catch: _,
+            finally: _,
+        } => return Some(keyword.line_number),
+        Stmt::Block { statements: _ } => return None,
+    }
+}
+
+impl Debugger {
+    fn statement(&mut self, statement: &Stmt, env: &Environment) -> Result<(), RuntimeError> {
+        if self.mode == Mode::Quit {
+            return Err(RuntimeError::new("debugging session ended"));
+        }
+        // code from imported modules has globals of its own and is run through
+        if !env.shares_globals(&self.globals) {
+            return Ok(());
+        }
+        let line = match statement_line(statement, &self.lines) {
+            Some(line) => line,
+            None => return Ok(()),
+        };
+
+        let depth = self.frames.len();
+        let stop = match self.mode {
+            Mode::Continue | Mode::Quit => false,
+            Mode::Step => true,
+            Mode::Next(max_depth) => depth <= max_depth,
+            Mode::Finish(max_depth) => depth < max_depth,
+        };
+        if !stop && !self.breakpoints.contains(&line) {
+            return Ok(());
+        }
+
+        let function = match self.frames.last() {
+            Some(frame) => format!("{}()", frame.function),
+            None => "<script>".to_string(),
+        };
+        let text = self
+            .source
+            .lines()
+            .nth(line - 1)
+            .unwrap_or("")
+            .trim()
+            .to_string();
+        self.say(&format!("stopped at line {} in {}", line, function));
+        self.say(&format!("{} | {}", line, text));
+
+        return self.prompt(line, env);
+    }
+
+    fn prompt(&mut self, line: usize, env: &Environment) -> Result<(), RuntimeError> {
+        loop {
+            let _ = write!(self.out, "(debug) ");
+            let _ = self.out.flush();
+
+            let mut input = String::new();
+            match self.commands.read_line(&mut input) {
+                Ok(0) | Err(_) => {
+                    // without commands left the script runs to the end
+                    self.say("");
+                    self.breakpoints.clear();
+                    self.mode = Mode::Continue;
+                    return Ok(());
+                }
+                Ok(_) => (),
+            }
+
+            // an empty line repeats the last command
+            let mut command = input.trim().to_string();
+            if command.is_empty() {
+                command = self.last_command.clone();
+            }
+            self.last_command = command.clone();
+
+            let (name, arg) = match command.split_once(char::is_whitespace) {
+                Some((name, arg)) => (name, arg.trim()),
+                None => (command.as_str(), ""),
+            };
+            let depth = self.frames.len();
+
+            match name {
+                "s" | "step" => {
+                    self.mode = Mode::Step;
+                    return Ok(());
+                }
+                "n" | "next" => {
+                    self.mode = Mode::Next(depth);
+                    return Ok(());
+                }
+                "f" | "finish" => {
+                    self.mode = Mode::Finish(depth);
+                    return Ok(());
+                }
+                "c" | "continue" => {
+                    self.mode = Mode::Continue;
+                    return Ok(());
+                }
+                "b" | "break" => match arg.parse::<usize>() {
+                    Ok(target) => {
+                        self.breakpoints.insert(target);
+                        self.say(&format!("breakpoint at line {}", target));
+                    }
+                    Err(_) => self.say("break expects a line number"),
+                },
+                "d" | "delete" => match arg.parse::<usize>() {
+                    Ok(target) if self.breakpoints.remove(&target) => {
+                        self.say(&format!("removed breakpoint at line {}", target));
+                    }
+                    Ok(target) => self.say(&format!("no breakpoint at line {}", target)),
+                    Err(_) => self.say("delete expects a line number"),
+                },
+                "l" | "locals" => self.locals(env),
+                "g" | "globals" => self.globals(),
+                "bt" | "backtrace" => self.backtrace(line),
+                "h" | "help" => self.say(HELP),
+                "q" | "quit" => {
+                    self.mode = Mode::Quit;
+                    return Err(RuntimeError::new("debugging session ended"));
+                }
+                "" => (),
+                _ => self.say(&format!("unknown command '{}', try help", name)),
+            }
+        }
+    }
+
+    // only the innermost variable with a name is visible, so shadowed ones are skipped
+    fn locals(&mut self, env: &Environment) {
+        let mut seen = HashSet::new();
+        let mut lines = vec![];
+        let mut scope = env;
+        while let Some(enclosing) = &scope.enclosing {
+            let mut values: Vec<_> = scope.values().into_iter().collect();
+            values.sort_by(|a, b| a.0.cmp(&b.0));
+            for (name, value) in values {
+                if seen.insert(name.clone()) {
+                    lines.push(format!("{} = {}", name, value.to_string()));
+                }
+            }
+            scope = enclosing;
+        }
+
+        if lines.is_empty() {
+            self.say("no locals");
+        }
+        for line in lines {
+            self.say(&line);
+        }
+    }
+
+    fn globals(&mut self) {
+        let natives = environment::get_globals();
+        let natives = natives.borrow();
+        let mut values: Vec<_> = self
+            .globals
+            .values()
+            .into_iter()
+            .filter(|(name, _)| !natives.contains_key(name))
+            .collect();
+        values.sort_by(|a, b| a.0.cmp(&b.0));
+        for (name, value) in values {
+            self.say(&format!("{} = {}", name, value.to_string()));
+        }
+    }
+
+    // innermost call first, each frame is at the line its callee was called from
+    fn backtrace(&mut self, line: usize) {
+        let mut lines = vec![];
+        let mut current = line;
+        for (i, frame) in self.frames.iter().rev().enumerate() {
+            lines.push(format!("#{} {}() at line {}", i, frame.function, current));
+            current = frame.line;
+        }
+        lines.push(format!(
+            "#{} <script> at line {}",
+            self.frames.len(),
+            current
+        ));
+
+        for line in lines {
+            self.say(&line);
+        }
+    }
+
+    fn say(&mut self, text: &str) {
+        let _ = writeln!(self.out, "{}", text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output;
+    use std::rc::Rc;
+
+    #[derive(Clone)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            return Ok(buf.len());
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            return Ok(());
+        }
+    }
+
+    // the debugger's own lines, and what the script printed
+    fn session(source: &str, commands: &str) -> (Vec<String>, String) {
+        let output = Output(Rc::new(RefCell::new(vec![])));
+        let printed = Output(Rc::new(RefCell::new(vec![])));
+        let commands = io::Cursor::new(commands.as_bytes().to_vec());
+        let previous = output::redirect(Some(Box::new(printed.clone())));
+        let result = debug(source, Box::new(commands), Box::new(output.clone()));
+        output::redirect(previous);
+        result.unwrap();
+
+        let text = String::from_utf8(output.0.borrow().clone()).unwrap();
+        let lines = text
+            .lines()
+            .map(|line| line.trim_start_matches("(debug) ").to_string())
+            .filter(|line| !line.is_empty())
+            .collect();
+        return (
+            lines,
+            String::from_utf8(printed.0.borrow().clone()).unwrap(),
+        );
+    }
+
+    const SOURCE: &str = "\
+fun double(x) {
+  var twice = x * 2;
+  return twice;
+}
+var a = 1;
+var b = double(a);
+print b;";
+
+    #[test]
+    fn breakpoints_locals_and_backtrace() {
+        let (output, printed) = session(SOURCE, "break 3\ncontinue\nlocals\nbacktrace\ncontinue\n");
+        assert_eq!(
+            output,
+            vec![
+                "stopped at line 1 in <script>",
+                "1 | fun double(x) {",
+                "breakpoint at line 3",
+                "stopped at line 3 in double()",
+                "3 | return twice;",
+                "twice = 2",
+                "x = 1",
+                "#0 double() at line 3",
+                "#1 <script> at line 6",
+            ]
+        );
+        assert_eq!(printed, "2\n");
+    }
+
+    #[test]
+    fn step_next_and_finish() {
+        let (output, printed) = session(SOURCE, "next\nnext\nstep\nstep\nfinish\nquit\n");
+        let stops: Vec<&String> = output
+            .iter()
+            .filter(|line| line.starts_with("stopped"))
+            .collect();
+        assert_eq!(
+            stops,
+            vec![
+                "stopped at line 1 in <script>",
+                "stopped at line 5 in <script>",
+                "stopped at line 6 in <script>",
+                "stopped at line 2 in double()",
+                "stopped at line 3 in double()",
+                "stopped at line 7 in <script>",
+            ]
+        );
+        // quitting at the print stops the script before it runs
+        assert_eq!(printed, "");
+    }
+}
@@ -0,0 +1,218 @@
+use crate::error::RuntimeError;
+use crate::json;
+use crate::scanner;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiagnosticKind {
+    Scan,
+    Parse,
+    Resolve,
+    Type,
+    Compile,
+    Runtime,
+    Lint,
+}
+
+impl std::fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            DiagnosticKind::Scan => "scan",
+            DiagnosticKind::Parse => "parse",
+            DiagnosticKind::Resolve => "resolve",
+            DiagnosticKind::Type => "type",
+            DiagnosticKind::Compile => "compile",
+            DiagnosticKind::Runtime => "runtime",
+            DiagnosticKind::Lint => "lint",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// line and column are 1-based and count characters, start and end are byte
+// offsets into the source
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn from_token(token: &scanner::Token) -> Self {
+        return Self {
+            line: token.line_number,
+            column: token.column,
+            start: token.offset,
+            end: token.offset + token.lexeme.len(),
+        };
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub message: String,
+    pub file: String,
+    pub span: Option<Span>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(kind: DiagnosticKind, message: impl Into<String>, span: Option<Span>) -> Self {
+        return Self {
+            kind,
+            message: message.into(),
+            file: "<script>".to_string(),
+            span,
+            notes: vec![],
+        };
+    }
+
+    pub fn at_token(
+        kind: DiagnosticKind,
+        message: impl Into<String>,
+        token: &scanner::Token,
+    ) -> Self {
+        return Self::new(kind, message, Some(Span::from_token(token)));
+    }
+
+    pub fn with_file(mut self, file: &str) -> Self {
+        self.file = file.to_string();
+        return self;
+    }
+
+    // error: message
+    //  --> file:line:column
+    //   |
+    // 3 | print a.test;
+    //   |         ^^^^
+    pub fn render(&self, source: &str) -> String {
+        let mut out = match self.kind {
+            DiagnosticKind::Lint => format!("warning: {}", self.message),
+            kind => format!("{} error: {}", kind, self.message),
+        };
+
+        if let Some(span) = self.span {
+            out.push_str(&format!(
+                "\n --> {}:{}:{}",
+                self.file, span.line, span.column
+            ));
+
+            if let Some(text) = source.lines().nth(span.line.saturating_sub(1)) {
+                let gutter = " ".repeat(span.line.to_string().len());
+                let line_length = text.chars().count();
+                let caret_start = span.column.saturating_sub(1).min(line_length);
+                let underlined = source.get(span.start..span.end.max(span.start));
+                let caret_length = underlined
+                    .map_or(1, |text| text.chars().count())
+                    .min(line_length.saturating_sub(caret_start))
+                    .max(1);
+
+                out.push_str(&format!("\n{} |", gutter));
+                out.push_str(&format!("\n{} | {}", span.line, text));
+                out.push_str(&format!(
+                    "\n{} | {}{}",
+                    gutter,
+                    " ".repeat(caret_start),
+                    "^".repeat(caret_length)
+                ));
+            }
+        }
+
+        for note in &self.notes {
+            out.push_str(&format!("\n  = {}", note));
+        }
+
+        return out;
+    }
+
+    pub fn to_json(&self) -> String {
+        let location = match self.span {
+            Some(span) => format!(
+                "\"line\":{},\"column\":{},\"span\":{{\"start\":{},\"end\":{}}}",
+                span.line, span.column, span.start, span.end
+            ),
+            None => "\"line\":null,\"column\":null,\"span\":null".to_string(),
+        };
+        let notes = self
+            .notes
+            .iter()
+            .map(|note| json::string(note))
+            .collect::<Vec<String>>()
+            .join(",");
+
+        return format!(
+            "{{\"kind\":{},\"message\":{},\"file\":{},{},\"notes\":[{}]}}",
+            json::string(&self.kind.to_string()),
+            json::string(&self.message),
+            json::string(&self.file),
+            location,
+            notes
+        );
+    }
+}
+
+impl From<RuntimeError> for Diagnostic {
+    fn from(err: RuntimeError) -> Self {
+        let mut diagnostic = Diagnostic::new(
+            DiagnosticKind::Runtime,
+            err.message,
+            err.token.as_deref().map(Span::from_token),
+        );
+        for frame in err.trace {
+            diagnostic.notes.push(format!(
+                "in {}() called at line {}",
+                frame.function, frame.line
+            ));
+        }
+        diagnostic.notes.extend(err.notes);
+        return diagnostic;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(line: usize, column: usize, start: usize, end: usize) -> Option<Span> {
+        return Some(Span {
+            line,
+            column,
+            start,
+            end,
+        });
+    }
+
+    #[test]
+    fn render_underlines_span() {
+        let source = "var a = 1;\nprint a.test;\n";
+        let diagnostic = Diagnostic::new(
+            DiagnosticKind::Runtime,
+            "no field named test",
+            span(2, 9, 19, 23),
+        );
+
+        assert_eq!(
+            diagnostic.render(source),
+            "runtime error: no field named test\n --> <script>:2:9\n  |\n2 | print a.test;\n  |         ^^^^"
+        );
+    }
+
+    #[test]
+    fn render_without_span() {
+        let diagnostic = Diagnostic::new(DiagnosticKind::Parse, "oops", None);
+        assert_eq!(diagnostic.render(""), "parse error: oops");
+    }
+
+    #[test]
+    fn json_escapes_strings() {
+        let diagnostic = Diagnostic::new(DiagnosticKind::Scan, "bad \"char\"", span(1, 2, 1, 2))
+            .with_file("a.jlox");
+
+        assert_eq!(
+            diagnostic.to_json(),
+            "{\"kind\":\"scan\",\"message\":\"bad \\\"char\\\"\",\"file\":\"a.jlox\",\"line\":1,\"column\":2,\"span\":{\"start\":1,\"end\":2},\"notes\":[]}"
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use crate::error::RuntimeError;
+use crate::expr::LiteralValue;
+use crate::limits::{self, Limits};
+use crate::output;
+use crate::repl::with_semicolon;
+use crate::scanner::{Token, TokenType};
+use crate::stmt::Stmt;
+use crate::{diagnostic::Diagnostic, execute, parse, Backend};
+use std::io::Write;
+use std::rc::Rc;
+
+// the value of a trailing expression is parked in a global no script can name
+const RESULT: &str = "<result>";
+
+// an interpreter owned by a Rust host, state carries over between calls
+pub struct Lox {
+    backend: Backend,
+    // expression ids keep counting across calls, see Parser::starting_at
+    next_id: usize,
+    // installed for the duration of each call, None prints to stdout
+    output: Option<Box<dyn Write>>,
+    // each call starts with the full budget
+    limits: Limits,
+}
+
+impl Lox {
+    pub fn new() -> Self {
+        return Self::with_backend(false);
+    }
+
+    // runs scripts on the bytecode vm instead of the tree-walking interpreter
+    pub fn with_vm() -> Self {
+        return Self::with_backend(true);
+    }
+
+    fn with_backend(use_vm: bool) -> Self {
+        return Self {
+            backend: Backend::new(use_vm),
+            next_id: 0,
+            output: None,
+            limits: Limits::default(),
+        };
+    }
+
+    pub fn set_output(&mut self, writer: impl Write + 'static) {
+        self.output = Some(Box::new(writer));
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    // the arity is checked before the closure is called, an Err becomes a
+    // runtime error at the call site
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        fun: impl Fn(&[LiteralValue]) -> Result<LiteralValue, String> + 'static,
+    ) {
+        let callable = LiteralValue::Callable {
+            name: name.to_string(),
+            arity,
+            fun: Rc::new(move |args: &Vec<LiteralValue>| fun(args).map_err(RuntimeError::new)),
+        };
+        self.backend.define(name, callable);
+    }
+
+    pub fn set_global(&mut self, name: &str, value: LiteralValue) {
+        self.backend.define(name, value);
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<LiteralValue> {
+        return self.backend.get(name);
+    }
+
+    pub fn run(&mut self, source: &str) -> Result<(), Vec<Diagnostic>> {
+        let (statements, next_id) = parse(source, self.next_id)?;
+        self.next_id = next_id;
+        return self.execute(statements);
+    }
+
+    // runs a snippet and returns the value of its last statement when that is
+    // an expression, nil otherwise, the trailing semicolon is optional
+    pub fn eval(&mut self, source: &str) -> Result<LiteralValue, Vec<Diagnostic>> {
+        let (mut statements, next_id) = parse(&with_semicolon(source), self.next_id)?;
+        self.next_id = next_id;
+
+        let has_result = match statements.pop() {
+            Some(Stmt::Expression { expression }) => {
+                let name = Token {
+                    token_type: TokenType::Identifier,
+                    lexeme: RESULT.to_string(),
+                    literal: None,
+                    line_number: 0,
+                    column: 0,
+                    offset: 0,
+                };
+                statements.push(Stmt::Var {
+                    name,
+                    annotation: None,
+                    initializer: expression,
+                });
+                true
+            }
+            Some(statement) => {
+                statements.push(statement);
+                false
+            }
+            None => false,
+        };
+
+        self.execute(statements)?;
+        if !has_result {
+            return Ok(LiteralValue::Nil);
+        }
+        return Ok(self.backend.remove(RESULT).unwrap_or(LiteralValue::Nil));
+    }
+
+    fn execute(&mut self, statements: Vec<Stmt>) -> Result<(), Vec<Diagnostic>> {
+        let previous = output::redirect(self.output.take());
+        let previous_limits = limits::install(self.limits);
+        let result = execute(&mut self.backend, statements);
+        limits::install(previous_limits);
+        self.output = output::redirect(previous);
+        return result;
+    }
+}
+
+impl Default for Lox {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::io;
+
+    // a writer the test can still read after handing it to the interpreter
+    #[derive(Clone)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            return self.0.borrow_mut().write(buf);
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            return Ok(());
+        }
+    }
+
+    fn each_backend(test: impl Fn(Lox)) {
+        test(Lox::new());
+        test(Lox::with_vm());
+    }
+
+    #[test]
+    fn evaluates_snippets_against_host_state() {
+        each_backend(|mut lox| {
+            lox.set_global("limit", LiteralValue::Number(10.0));
+            lox.define_native("double", 1, |args| match &args[0] {
+                LiteralValue::Number(x) => Ok(LiteralValue::Number(x * 2.0)),
+                other => Err(format!("double expects a Number, got {}", other.to_type())),
+            });
+
+            lox.run("var total = double(limit);").unwrap();
+            assert_eq!(lox.get_global("total"), Some(LiteralValue::Number(20.0)));
+            assert_eq!(lox.eval("total + 1").unwrap(), LiteralValue::Number(21.0));
+            assert!(matches!(
+                lox.eval("var unused = 1;").unwrap(),
+                LiteralValue::Nil
+            ));
+            assert_eq!(lox.get_global(RESULT), None);
+
+            let errors = lox.eval("double(\"x\")").unwrap_err();
+            assert_eq!(errors[0].message, "double expects a Number, got String");
+        });
+    }
+
+    #[test]
+    fn print_goes_to_the_host_writer() {
+        each_backend(|mut lox| {
+            let output = Output(Rc::new(RefCell::new(vec![])));
+            lox.set_output(output.clone());
+
+            lox.run("fun show(x) { print x * 2; }").unwrap();
+            lox.eval("show(21)").unwrap();
+            lox.run("print 1 + 2;").unwrap();
+
+            let printed = String::from_utf8(output.0.borrow().clone()).unwrap();
+            assert_eq!(printed, "42\n3\n");
+        });
+    }
+
+    #[test]
+    fn stops_scripts_at_their_limits() {
+        each_backend(|mut lox| {
+            let output = Output(Rc::new(RefCell::new(vec![])));
+            lox.set_output(output.clone());
+            lox.set_limits(Limits {
+                max_steps: Some(10_000),
+                max_depth: Some(8),
+                max_memory: Some(4096),
+            });
+
+            let errors = lox.run("while (true) { try { while (true) {} } catch (e) {} }");
+            assert_eq!(errors.unwrap_err()[0].message, "step limit exceeded");
+
+            lox.run("fun down(n) { return down(n + 1); }").unwrap();
+            let errors = lox.run("down(0);");
+            assert_eq!(errors.unwrap_err()[0].message, "call depth limit exceeded");
+            lox.run("try { down(0); } catch (e) { print e.message; }")
+                .unwrap();
+
+            let errors = lox.run("var s = \"ab\"; while (true) s = s + s;");
+            assert_eq!(errors.unwrap_err()[0].message, "memory limit exceeded");
+
+            // growing collections is charged too
+            let errors = lox.run("var m = {}; for (var i = 0; i < 50000; i = i + 1) { m[i] = i; }");
+            assert_eq!(errors.unwrap_err()[0].message, "memory limit exceeded");
+            let errors = lox.run(
+                "var l = []; for (var i = 0; i < 50000; i = i + 1) { push(l, \"0123456789abcdef\"); }",
+            );
+            assert_eq!(errors.unwrap_err()[0].message, "memory limit exceeded");
+            let errors = lox.run("var s = \"0123456789abcdef\"; while (true) upper(s);");
+            assert_eq!(errors.unwrap_err()[0].message, "memory limit exceeded");
+
+            // every call starts over with the full budget
+            lox.run("var s = \"\"; for (var i = 0; i < 50; i = i + 1) s = s + \"x\";")
+                .unwrap();
+
+            let printed = String::from_utf8(output.0.borrow().clone()).unwrap();
+            assert_eq!(printed, "call depth limit exceeded\n");
+        });
+    }
+
+    #[test]
+    fn deep_limits_cannot_overflow_the_native_stack() {
+        let mut lox = Lox::new();
+        lox.set_limits(Limits {
+            max_depth: Some(1_000_000),
+            ..Limits::default()
+        });
+        let errors = lox.run("fun down(n) { return down(n + 1); } down(0);");
+        assert_eq!(errors.unwrap_err()[0].message, "call depth limit exceeded");
+
+        // the stack is guarded without a depth limit too
+        lox.set_limits(Limits {
+            max_steps: Some(usize::MAX),
+            ..Limits::default()
+        });
+        let errors = lox.run("down(0);");
+        assert_eq!(errors.unwrap_err()[0].message, "call depth limit exceeded");
+    }
+}
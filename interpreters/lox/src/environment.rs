@@ -1,29 +1,15 @@
 use crate::expr;
+use crate::gc;
+use crate::natives;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 
 pub fn get_globals() -> Rc<RefCell<HashMap<String, expr::LiteralValue>>> {
     let mut env = HashMap::new();
-    env.insert(
-        "clock".to_string(),
-        expr::LiteralValue::Callable {
-            name: "clock".to_string(),
-            arity: 0,
-            fun: Rc::new(clock_impl),
-        },
-    );
-
-    return Rc::new(RefCell::new(env));
-}
-
-fn clock_impl(_args: &Vec<expr::LiteralValue>) -> expr::LiteralValue {
-    let now = std::time::SystemTime::now()
-        .duration_since(std::time::SystemTime::UNIX_EPOCH)
-        .expect("could not get system time")
-        .as_millis();
+    natives::register(&mut env);
 
-    return expr::LiteralValue::Number(now as f64 / 1000.0);
+    return gc::scope(env);
 }
 
 #[derive(Clone)]
@@ -50,21 +36,68 @@ impl Environment {
 
     pub fn enclose(&self) -> Environment {
         return Self {
-            values: Rc::new(RefCell::new(HashMap::new())),
+            values: gc::scope(HashMap::new()),
             locals: self.locals.clone(),
             enclosing: Some(Box::new(self.clone())),
         };
     }
 
+    // the names defined directly in this scope, for the global scope of a module
+    pub fn values(&self) -> HashMap<String, expr::LiteralValue> {
+        return self.values.borrow().clone();
+    }
+
+    // the scope itself, shared rather than copied
+    pub fn scope(&self) -> Rc<gc::Scope> {
+        return self.values.clone();
+    }
+
+    // whether both environments end in the same global scope, which tells a
+    // script's code apart from the code of the modules it imported
+    pub fn shares_globals(&self, other: &Environment) -> bool {
+        return Rc::ptr_eq(&self.root().values, &other.root().values);
+    }
+
+    // the scope of every environment in the chain, innermost first
+    pub fn scopes(&self) -> Vec<Rc<gc::Scope>> {
+        let mut scopes = vec![self.values.clone()];
+        if let Some(env) = &self.enclosing {
+            scopes.extend(env.scopes());
+        }
+        return scopes;
+    }
+
+    fn root(&self) -> &Environment {
+        match &self.enclosing {
+            Some(env) => return env.root(),
+            None => return self,
+        }
+    }
+
     pub fn define(&self, name: String, value: expr::LiteralValue) {
         self.values.borrow_mut().insert(name, value);
     }
 
+    // a name defined directly in this scope, without going through the resolver
+    pub fn lookup(&self, name: &str) -> Option<expr::LiteralValue> {
+        return self.values.borrow().get(name).cloned();
+    }
+
+    pub fn remove(&self, name: &str) -> Option<expr::LiteralValue> {
+        return self.values.borrow_mut().remove(name);
+    }
+
     pub fn get(&self, name: &str, expr_id: usize) -> Option<expr::LiteralValue> {
         let distance = self.locals.borrow().get(&expr_id).cloned();
         self.get_internal(name, distance)
     }
 
+    pub fn get_this(&self, super_id: usize) -> Option<expr::LiteralValue> {
+        // 'this' is always bound one scope inside the one holding 'super'
+        let distance = self.locals.borrow().get(&super_id).cloned()?;
+        self.get_internal("this", Some(distance - 1))
+    }
+
     fn get_internal(&self, name: &str, distance: Option<usize>) -> Option<expr::LiteralValue> {
         if let None = distance {
             match &self.enclosing {
@@ -87,10 +120,6 @@ impl Environment {
         }
     }
 
-    pub fn assign_global(&self, name: &str, value: expr::LiteralValue) -> bool {
-        return self.assign_internal(name, value, None);
-    }
-
     pub fn assign(&self, name: &str, value: expr::LiteralValue, expr_id: usize) -> bool {
         // ! important that this ID matches with the resolver
         let distance = self.locals.borrow().get(&expr_id).cloned();
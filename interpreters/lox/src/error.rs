@@ -0,0 +1,108 @@
+use crate::expr::LiteralValue;
+use crate::gc;
+use crate::scanner;
+
+#[derive(Debug, Clone)]
+pub struct TraceFrame {
+    pub function: String,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone)]
+// the token and thrown value are boxed to keep the error small, it is the Err
+// of nearly every function in the interpreters
+pub struct RuntimeError {
+    pub message: String,
+    pub token: Option<Box<scanner::Token>>,
+    pub trace: Vec<TraceFrame>,
+    // extra context rendered after the trace, such as the module an error came from
+    pub notes: Vec<String>,
+    // the value of a throw statement, built-in errors have none
+    pub thrown: Option<Box<LiteralValue>>,
+}
+
+impl RuntimeError {
+    pub fn new(message: impl Into<String>) -> Self {
+        return Self {
+            message: message.into(),
+            token: None,
+            trace: vec![],
+            notes: vec![],
+            thrown: None,
+        };
+    }
+
+    pub fn at(token: &scanner::Token, message: impl Into<String>) -> Self {
+        return Self {
+            message: message.into(),
+            token: Some(Box::new(token.clone())),
+            trace: vec![],
+            notes: vec![],
+            thrown: None,
+        };
+    }
+
+    pub fn thrown(token: &scanner::Token, value: LiteralValue) -> Self {
+        let mut err = Self::at(token, format!("uncaught exception: {}", value.display()));
+        err.thrown = Some(Box::new(value));
+        return err;
+    }
+
+    // the value a catch clause binds, built-in errors become an Error instance
+    // carrying their message and line
+    pub fn value(&self) -> LiteralValue {
+        if let Some(value) = &self.thrown {
+            return value.as_ref().clone();
+        }
+
+        let line = match &self.token {
+            Some(token) => LiteralValue::Number(token.line_number as f64),
+            None => LiteralValue::Nil,
+        };
+        let fields = gc::fields();
+        fields.borrow_mut().extend([
+            (
+                "message".to_string(),
+                LiteralValue::StringLit(self.message.clone()),
+            ),
+            ("line".to_string(), line),
+        ]);
+        return LiteralValue::LoxInstance {
+            class: Box::new(LiteralValue::LoxClass {
+                name: "Error".to_string(),
+                methods: std::collections::HashMap::new(),
+                superclass: None,
+            }),
+            fields,
+        };
+    }
+
+    // errors raised by natives have no location of their own, so they borrow the call site
+    pub fn locate(&mut self, token: &scanner::Token) {
+        if self.token.is_none() {
+            self.token = Some(Box::new(token.clone()));
+        }
+    }
+
+    // called on the way out of each callable, so the innermost call comes first
+    pub fn push_frame(&mut self, function: &str, line: usize) {
+        self.trace.push(TraceFrame {
+            function: function.to_string(),
+            line,
+        });
+    }
+}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        for frame in &self.trace {
+            write!(
+                f,
+                "\n    in {}() called at line {}",
+                frame.function, frame.line
+            )?;
+        }
+        return Ok(());
+    }
+}
@@ -1,8 +1,15 @@
+use crate::debugger;
 use crate::environment;
+use crate::error::RuntimeError;
 use crate::expr;
+use crate::gc;
 use crate::interpreter;
+use crate::limits;
+use crate::natives;
+use crate::profiler;
 use crate::scanner;
 use crate::stmt;
+use crate::vm;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
@@ -21,6 +28,179 @@ fn unwrap_as_string(literal: Option<scanner::LiteralValue>) -> String {
     }
 }
 
+// operator semantics shared by the tree-walker and the bytecode vm
+pub fn unary_op(
+    operator: scanner::TokenType,
+    right: &LiteralValue,
+) -> Result<LiteralValue, String> {
+    match (right, operator) {
+        (LiteralValue::Number(x), scanner::TokenType::Minus) => Ok(LiteralValue::Number(-x)),
+        (_, scanner::TokenType::Minus) => Err(format!(
+            "minus operation not supported for {}",
+            right.to_type()
+        )),
+        (any, scanner::TokenType::Bang) => any.is_falsy(),
+        (_, toktype) => Err(format!("{} is not a valid unary operator", toktype)),
+    }
+}
+
+pub fn binary_op(
+    left: &LiteralValue,
+    operator: scanner::TokenType,
+    right: &LiteralValue,
+) -> Result<LiteralValue, String> {
+    match (left, operator, right) {
+        (LiteralValue::Number(x), scanner::TokenType::Star, LiteralValue::Number(y)) => {
+            Ok(LiteralValue::Number(x * y))
+        }
+        (LiteralValue::Number(x), scanner::TokenType::Slash, LiteralValue::Number(y)) => {
+            Ok(LiteralValue::Number(x / y))
+        }
+        (LiteralValue::Number(x), scanner::TokenType::Plus, LiteralValue::Number(y)) => {
+            Ok(LiteralValue::Number(x + y))
+        }
+        (LiteralValue::Number(x), scanner::TokenType::Minus, LiteralValue::Number(y)) => {
+            Ok(LiteralValue::Number(x - y))
+        }
+
+        (LiteralValue::Number(x), scanner::TokenType::Greater, LiteralValue::Number(y)) => {
+            Ok(LiteralValue::from_bool(x > y))
+        }
+        (LiteralValue::Number(x), scanner::TokenType::GreaterEqual, LiteralValue::Number(y)) => {
+            Ok(LiteralValue::from_bool(x >= y))
+        }
+        (LiteralValue::Number(x), scanner::TokenType::Less, LiteralValue::Number(y)) => {
+            Ok(LiteralValue::from_bool(x < y))
+        }
+        (LiteralValue::Number(x), scanner::TokenType::LessEqual, LiteralValue::Number(y)) => {
+            Ok(LiteralValue::from_bool(x <= y))
+        }
+
+        (LiteralValue::StringLit(_), op, LiteralValue::Number(_)) => Err(format!(
+            "binary operation {} not supported for inconsistent types",
+            op
+        )),
+        (LiteralValue::Number(_), op, LiteralValue::StringLit(_)) => Err(format!(
+            "binary operation {} not supported for inconsistent types",
+            op
+        )),
+
+        (LiteralValue::StringLit(s1), scanner::TokenType::Plus, LiteralValue::StringLit(s2)) => {
+            Ok(LiteralValue::StringLit(format!("{}{}", s1, s2)))
+        }
+        (LiteralValue::StringLit(s1), scanner::TokenType::Less, LiteralValue::StringLit(s2)) => {
+            Ok(LiteralValue::from_bool(s1 < s2))
+        }
+        (
+            LiteralValue::StringLit(s1),
+            scanner::TokenType::LessEqual,
+            LiteralValue::StringLit(s2),
+        ) => Ok(LiteralValue::from_bool(s1 <= s2)),
+
+        (x, scanner::TokenType::BangEqual, y) => Ok(LiteralValue::from_bool(x != y)),
+        (x, scanner::TokenType::EqualEqual, y) => Ok(LiteralValue::from_bool(x == y)),
+
+        (x, toktype, y) => Err(format!(
+            "binary operator {} not implemented for operands {:?} and {:?}",
+            toktype, x, y
+        )),
+    }
+}
+
+// indexing semantics shared by the tree-walker and the bytecode vm
+pub fn index_get(object: &LiteralValue, index: &LiteralValue) -> Result<LiteralValue, String> {
+    match object {
+        LiteralValue::List(items) => {
+            let items = items.borrow();
+            let i = list_index(index, items.len())?;
+            return Ok(items[i].clone());
+        }
+        LiteralValue::Map(entries) => {
+            check_key(index)?;
+            for (key, value) in entries.borrow().iter() {
+                if key == index {
+                    return Ok(value.clone());
+                }
+            }
+            return Err(format!("key {} not found in map", index.to_string()));
+        }
+        LiteralValue::StringLit(s) => {
+            let i = list_index(index, s.chars().count())?;
+            return Ok(LiteralValue::StringLit(
+                s.chars().nth(i).unwrap().to_string(),
+            ));
+        }
+        other => return Err(format!("cannot index into type {}", other.to_type())),
+    }
+}
+
+pub fn index_set(
+    object: &LiteralValue,
+    index: &LiteralValue,
+    value: LiteralValue,
+) -> Result<(), String> {
+    match object {
+        LiteralValue::List(items) => {
+            let mut items = items.borrow_mut();
+            let i = list_index(index, items.len())?;
+            items[i] = value;
+            return Ok(());
+        }
+        LiteralValue::Map(entries) => {
+            check_key(index)?;
+            let mut entries = entries.borrow_mut();
+            match entries.iter_mut().find(|(key, _)| key == index) {
+                Some(entry) => entry.1 = value,
+                None => {
+                    // a new entry, replacing a value does not grow the map
+                    limits::allocate(2 * limits::VALUE_BYTES).map_err(|err| err.message)?;
+                    entries.push((index.clone(), value));
+                }
+            }
+            return Ok(());
+        }
+        other => {
+            return Err(format!(
+                "cannot assign to an index of type {}",
+                other.to_type()
+            ))
+        }
+    }
+}
+
+// map keys need a meaningful equality, which rules out nil and reference types
+pub fn check_key(key: &LiteralValue) -> Result<(), String> {
+    match key {
+        LiteralValue::Number(_)
+        | LiteralValue::StringLit(_)
+        | LiteralValue::True
+        | LiteralValue::False => return Ok(()),
+        other => {
+            return Err(format!(
+                "map keys must be numbers, strings or booleans, got {}",
+                other.to_type()
+            ))
+        }
+    }
+}
+
+fn list_index(index: &LiteralValue, length: usize) -> Result<usize, String> {
+    match index {
+        LiteralValue::Number(x) if x.fract() == 0.0 => {
+            if *x < 0.0 || *x >= length as f64 {
+                return Err(format!("index {} out of bounds for length {}", x, length));
+            }
+            return Ok(*x as usize);
+        }
+        other => {
+            return Err(format!(
+                "index must be a whole number, got {}",
+                other.to_string()
+            ))
+        }
+    }
+}
+
 #[derive(Clone)]
 pub enum LiteralValue {
     Number(f64),
@@ -31,17 +211,31 @@ pub enum LiteralValue {
     Callable {
         name: String,
         arity: usize,
-        fun: Rc<dyn Fn(&Vec<LiteralValue>) -> LiteralValue>,
+        fun: Rc<gc::Function>,
     },
     LoxClass {
         name: String,
         methods: HashMap<String, LiteralValue>,
         // methods: Vec<(String, LiteralValue)>, // TODO Could also add static fields?
+        superclass: Option<Box<LiteralValue>>,
     },
     LoxInstance {
         class: Box<LiteralValue>,
         fields: Rc<RefCell<Vec<(String, LiteralValue)>>>,
     },
+    // lists and maps are shared by reference, like instance fields
+    List(Rc<RefCell<Vec<LiteralValue>>>),
+    Map(Rc<RefCell<Vec<(LiteralValue, LiteralValue)>>>),
+    // the live global scope of an imported script, reads see later changes
+    Module {
+        name: String,
+        fields: Rc<gc::Scope>,
+    },
+    // a function compiled for the bytecode vm, methods carry their bound instance
+    Closure {
+        closure: Rc<vm::Closure>,
+        receiver: Option<Box<LiteralValue>>,
+    },
 }
 
 impl std::fmt::Debug for LiteralValue {
@@ -57,6 +251,7 @@ impl PartialEq for LiteralValue {
             (LiteralValue::StringLit(s1), LiteralValue::StringLit(s2)) => s1 == s2,
             (LiteralValue::True, LiteralValue::True) => true,
             (LiteralValue::False, LiteralValue::False) => true,
+            (LiteralValue::Nil, LiteralValue::Nil) => true,
             (
                 LiteralValue::Callable {
                     name: name_1,
@@ -69,6 +264,45 @@ impl PartialEq for LiteralValue {
                     fun: _,
                 },
             ) => name_1 == name_2 && arity_1 == arity_2,
+            (
+                LiteralValue::LoxInstance {
+                    class: _,
+                    fields: fields_1,
+                },
+                LiteralValue::LoxInstance {
+                    class: _,
+                    fields: fields_2,
+                },
+            ) => Rc::ptr_eq(fields_1, fields_2),
+            (LiteralValue::List(items_1), LiteralValue::List(items_2)) => {
+                Rc::ptr_eq(items_1, items_2)
+            }
+            (LiteralValue::Map(entries_1), LiteralValue::Map(entries_2)) => {
+                Rc::ptr_eq(entries_1, entries_2)
+            }
+            (
+                LiteralValue::Module {
+                    name: _,
+                    fields: fields_1,
+                },
+                LiteralValue::Module {
+                    name: _,
+                    fields: fields_2,
+                },
+            ) => Rc::ptr_eq(fields_1, fields_2),
+            (
+                LiteralValue::Closure {
+                    closure: closure_1,
+                    receiver: _,
+                },
+                LiteralValue::Closure {
+                    closure: closure_2,
+                    receiver: _,
+                },
+            ) => {
+                closure_1.function.name == closure_2.function.name
+                    && closure_1.function.arity == closure_2.function.arity
+            }
             _ => false,
         }
     }
@@ -76,7 +310,12 @@ impl PartialEq for LiteralValue {
 
 macro_rules! class_name {
     ($class:expr) => {{
-        if let LiteralValue::LoxClass { name, methods: _ } = &**$class {
+        if let LiteralValue::LoxClass {
+            name,
+            methods: _,
+            superclass: _,
+        } = &**$class
+        {
             name
         } else {
             panic!("unreachable")
@@ -97,10 +336,39 @@ impl LiteralValue {
                 arity,
                 fun: _,
             } => format!("{name}/{arity}"),
-            LiteralValue::LoxClass { name, methods: _ } => format!("class '{name}'"),
+            LiteralValue::LoxClass {
+                name,
+                methods: _,
+                superclass: _,
+            } => format!("class '{name}'"),
             LiteralValue::LoxInstance { class, fields: _ } => {
                 format!("instance of '{}'", class_name!(class))
             }
+            LiteralValue::List(items) => {
+                let items: Vec<String> = items.borrow().iter().map(|v| v.to_string()).collect();
+                format!("[{}]", items.join(", "))
+            }
+            LiteralValue::Map(entries) => {
+                let entries: Vec<String> = entries
+                    .borrow()
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", k.to_string(), v.to_string()))
+                    .collect();
+                format!("{{{}}}", entries.join(", "))
+            }
+            LiteralValue::Module { name, fields: _ } => format!("module '{name}'"),
+            LiteralValue::Closure {
+                closure,
+                receiver: _,
+            } => format!("{}/{}", closure.function.name, closure.function.arity),
+        }
+    }
+
+    // how print and interpolation show a value, strings lose their quotes
+    pub fn display(&self) -> String {
+        match self {
+            LiteralValue::StringLit(s) => return s.clone(),
+            value => return value.to_string(),
         }
     }
 
@@ -119,15 +387,34 @@ impl LiteralValue {
             LiteralValue::LoxClass {
                 name: _,
                 methods: _,
+                superclass: _,
             } => "Class",
             LiteralValue::LoxInstance { class, fields: _ } => &class_name!(class),
+            LiteralValue::List(_) => "List",
+            LiteralValue::Map(_) => "Map",
+            LiteralValue::Module { name: _, fields: _ } => "Module",
+            LiteralValue::Closure {
+                closure: _,
+                receiver: _,
+            } => "Callable",
+        }
+    }
+
+    // what a class pattern in a match checks, the class itself and not its
+    // superclasses
+    pub fn is_instance_of(&self, name: &str) -> bool {
+        match self {
+            LiteralValue::LoxInstance { class, fields: _ } => return class_name!(class) == name,
+            _ => return false,
         }
     }
 
     pub fn from_token(token: scanner::Token) -> Self {
         match token.token_type {
             scanner::TokenType::NumberLit => Self::Number(unwrap_as_f64(token.literal)),
-            scanner::TokenType::StringLit => Self::StringLit(unwrap_as_string(token.literal)),
+            scanner::TokenType::StringLit | scanner::TokenType::Interpolation => {
+                Self::StringLit(unwrap_as_string(token.literal))
+            }
             scanner::TokenType::False => Self::False,
             scanner::TokenType::True => Self::True,
             scanner::TokenType::Nil => Self::Nil,
@@ -146,8 +433,118 @@ impl LiteralValue {
         }
     }
 
-    pub fn is_falsy(&self) -> LiteralValue {
-        match self {
+    pub fn find_method(&self, name: &str) -> Option<LiteralValue> {
+        if let LiteralValue::LoxClass {
+            name: _,
+            methods,
+            superclass,
+        } = self
+        {
+            if let Some(method) = methods.get(name) {
+                return Some(method.clone());
+            }
+            match superclass {
+                Some(superclass) => superclass.find_method(name),
+                None => None,
+            }
+        } else {
+            panic!("tried to look up a method on something that is not a class");
+        }
+    }
+
+    // methods are stored on the class expecting the receiver as their first
+    // argument, binding fixes that receiver so the result is a normal callable
+    pub fn bind(&self, instance: LiteralValue) -> LiteralValue {
+        if let LiteralValue::Callable { name, arity, fun } = self {
+            let fun = fun.clone();
+            // a copy of what the closure captures, for the collector to trace
+            let receiver = instance.clone();
+            let bound_impl = move |args: &Vec<LiteralValue>| {
+                let mut with_this = vec![instance.clone()];
+                with_this.extend(args.iter().cloned());
+                return fun(&with_this);
+            };
+
+            return LiteralValue::Callable {
+                name: name.clone(),
+                arity: *arity,
+                fun: gc::function(Rc::new(bound_impl), None, vec![self, &receiver]),
+            };
+        } else if let LiteralValue::Closure {
+            closure,
+            receiver: _,
+        } = self
+        {
+            return LiteralValue::Closure {
+                closure: closure.clone(),
+                receiver: Some(Box::new(instance)),
+            };
+        } else {
+            panic!("tried to bind something that is not a method");
+        }
+    }
+
+    // the class of an instance whose method, not field, has this name
+    pub fn method_class(&self, name: &str) -> Option<String> {
+        if let LiteralValue::LoxInstance { class, fields } = self {
+            if fields.borrow().iter().any(|(field, _)| field == name) {
+                return None;
+            }
+            if class.find_method(name).is_some() {
+                return Some(class_name!(class).clone());
+            }
+        }
+        return None;
+    }
+
+    // fields shadow methods, methods come back bound to the instance
+    pub fn get_property(&self, name: &str) -> Result<LiteralValue, String> {
+        if let LiteralValue::LoxInstance { class, fields } = self {
+            for (field_name, value) in (*fields.borrow()).iter() {
+                if field_name == name {
+                    return Ok(value.clone());
+                }
+            }
+            if let Some(method) = class.find_method(name) {
+                return Ok(method.bind(self.clone()));
+            }
+            return Err(format!("no field named {} on this instance", name));
+        } else if let LiteralValue::Module {
+            name: module_name,
+            fields,
+        } = self
+        {
+            match fields.borrow().get(name) {
+                Some(value) if !natives::is_native(name, value) => return Ok(value.clone()),
+                _ => {
+                    return Err(format!(
+                        "module {} has no global named {}",
+                        module_name, name
+                    ))
+                }
+            }
+        } else {
+            return Err(format!("cannot access property on type {}", self.to_type()));
+        }
+    }
+
+    pub fn set_property(&self, name: &str, value: LiteralValue) -> Result<(), String> {
+        if let LiteralValue::LoxInstance { class: _, fields } = self {
+            let mut fields = fields.borrow_mut();
+            match fields.iter_mut().find(|(field_name, _)| field_name == name) {
+                Some(field) => field.1 = value,
+                None => fields.push((name.to_string(), value)),
+            }
+            return Ok(());
+        } else {
+            return Err(format!("cannot set property on type {}", self.to_type()));
+        }
+    }
+
+    // callables, classes, instances and modules have no truth value, using
+    // one as a condition is a runtime error
+    pub fn is_falsy(&self) -> Result<LiteralValue, String> {
+        let value = match self {
             LiteralValue::Number(x) => {
                 if *x == 0.0 as f64 {
                     LiteralValue::True
@@ -169,24 +566,31 @@ impl LiteralValue {
                 name: _,
                 arity: _,
                 fun: _,
-            } => {
-                panic!("cannot use callable as a falsy value")
-            }
+            } => return Err("cannot use callable as a condition".to_string()),
             LiteralValue::LoxClass {
                 name: _,
                 methods: _,
-            } => panic!("cannot use class as a falsy value"),
+                superclass: _,
+            } => return Err("cannot use class as a condition".to_string()),
             LiteralValue::LoxInstance {
                 class: _,
                 fields: _,
-            } => {
-                panic!("cannot use class instance as a falsy value")
+            } => return Err("cannot use class instance as a condition".to_string()),
+            LiteralValue::List(items) => LiteralValue::from_bool(items.borrow().is_empty()),
+            LiteralValue::Map(entries) => LiteralValue::from_bool(entries.borrow().is_empty()),
+            LiteralValue::Module { name: _, fields: _ } => {
+                return Err("cannot use module as a condition".to_string())
             }
-        }
+            LiteralValue::Closure {
+                closure: _,
+                receiver: _,
+            } => return Err("cannot use callable as a condition".to_string()),
+        };
+        return Ok(value);
     }
 
-    pub fn is_truthy(&self) -> LiteralValue {
-        match self {
+    pub fn is_truthy(&self) -> Result<LiteralValue, String> {
+        let value = match self {
             LiteralValue::Number(x) => {
                 if *x == 0.0 as f64 {
                     LiteralValue::False
@@ -208,20 +612,27 @@ impl LiteralValue {
                 name: _,
                 arity: _,
                 fun: _,
-            } => {
-                panic!("cannot use callable as a truthy value")
-            }
+            } => return Err("cannot use callable as a condition".to_string()),
             LiteralValue::LoxClass {
                 name: _,
                 methods: _,
-            } => panic!("cannot use class as a truthy value"),
+                superclass: _,
+            } => return Err("cannot use class as a condition".to_string()),
             LiteralValue::LoxInstance {
                 class: _,
                 fields: _,
-            } => {
-                panic!("cannot use class instance as a truthy value")
+            } => return Err("cannot use class instance as a condition".to_string()),
+            LiteralValue::List(items) => LiteralValue::from_bool(!items.borrow().is_empty()),
+            LiteralValue::Map(entries) => LiteralValue::from_bool(!entries.borrow().is_empty()),
+            LiteralValue::Module { name: _, fields: _ } => {
+                return Err("cannot use module as a condition".to_string())
             }
-        }
+            LiteralValue::Closure {
+                closure: _,
+                receiver: _,
+            } => return Err("cannot use callable as a condition".to_string()),
+        };
+        return Ok(value);
     }
 }
 
@@ -248,6 +659,60 @@ impl std::cmp::PartialEq for Expr {
 
 impl std::cmp::Eq for Expr {}
 
+// the left of a match arm, a literal equal to the value whatever its type, a
+// class name followed by the name the instance is bound to, or a bare name
+// that matches anything
+#[derive(Clone)]
+pub enum Pattern {
+    Literal {
+        value: Expr,
+    },
+    Class {
+        class: scanner::Token,
+        name: scanner::Token,
+    },
+    Binding {
+        name: scanner::Token,
+    },
+}
+
+impl Pattern {
+    // the name the arm defines, _ only matches
+    pub fn binding(&self) -> Option<&scanner::Token> {
+        match self {
+            Pattern::Literal { value: _ } => return None,
+            Pattern::Class { class: _, name } | Pattern::Binding { name } => {
+                if name.lexeme == "_" {
+                    return None;
+                }
+                return Some(name);
+            }
+        }
+    }
+
+    pub fn matches(
+        &self,
+        value: &LiteralValue,
+        env: environment::Environment,
+    ) -> Result<bool, RuntimeError> {
+        match self {
+            Pattern::Literal { value: literal } => return Ok(literal.evaluate(env)? == *value),
+            Pattern::Class { class, name: _ } => return Ok(value.is_instance_of(&class.lexeme)),
+            Pattern::Binding { name: _ } => return Ok(true),
+        }
+    }
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Pattern::Literal { value } => write!(f, "{}", value.to_string()),
+            Pattern::Class { class, name } => write!(f, "{} {}", class.lexeme, name.lexeme),
+            Pattern::Binding { name } => write!(f, "{}", name.lexeme),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub enum Expr {
     AnonFunction {
@@ -282,6 +747,24 @@ pub enum Expr {
         id: usize,
         expression: Box<Expr>,
     },
+    Index {
+        id: usize,
+        object: Box<Expr>,
+        bracket: scanner::Token,
+        index: Box<Expr>,
+    },
+    // "a ${b} c", parts alternate between the literal text and the embedded
+    // expressions, starting and ending with text
+    Interpolation {
+        id: usize,
+        quote: scanner::Token,
+        parts: Vec<Expr>,
+    },
+    List {
+        id: usize,
+        bracket: scanner::Token,
+        elements: Vec<Expr>,
+    },
     Literal {
         id: usize,
         value: LiteralValue,
@@ -292,12 +775,40 @@ pub enum Expr {
         operator: scanner::Token,
         right: Box<Expr>,
     },
+    Map {
+        id: usize,
+        brace: scanner::Token,
+        entries: Vec<(Expr, Expr)>,
+    },
+    // arms are tried in order, each body sees its binding in a scope of its own
+    Match {
+        id: usize,
+        keyword: scanner::Token,
+        subject: Box<Expr>,
+        arms: Vec<(Pattern, Expr)>,
+    },
     Set {
         id: usize,
         object: Box<Expr>,
         name: scanner::Token,
         value: Box<Expr>,
     },
+    SetIndex {
+        id: usize,
+        object: Box<Expr>,
+        bracket: scanner::Token,
+        index: Box<Expr>,
+        value: Box<Expr>,
+    },
+    Super {
+        id: usize,
+        keyword: scanner::Token,
+        method: scanner::Token,
+    },
+    This {
+        id: usize,
+        keyword: scanner::Token,
+    },
     Unary {
         id: usize,
         operator: scanner::Token,
@@ -341,6 +852,22 @@ impl Expr {
                 name: _,
             } => *id,
             Expr::Grouping { id, expression: _ } => *id,
+            Expr::Index {
+                id,
+                object: _,
+                bracket: _,
+                index: _,
+            } => *id,
+            Expr::Interpolation {
+                id,
+                quote: _,
+                parts: _,
+            } => *id,
+            Expr::List {
+                id,
+                bracket: _,
+                elements: _,
+            } => *id,
             Expr::Literal { id, value: _ } => *id,
             Expr::Logical {
                 id,
@@ -348,12 +875,36 @@ impl Expr {
                 operator: _,
                 right: _,
             } => *id,
+            Expr::Map {
+                id,
+                brace: _,
+                entries: _,
+            } => *id,
+            Expr::Match {
+                id,
+                keyword: _,
+                subject: _,
+                arms: _,
+            } => *id,
             Expr::Set {
                 id,
                 object: _,
                 name: _,
                 value: _,
             } => *id,
+            Expr::SetIndex {
+                id,
+                object: _,
+                bracket: _,
+                index: _,
+                value: _,
+            } => *id,
+            Expr::Super {
+                id,
+                keyword: _,
+                method: _,
+            } => *id,
+            Expr::This { id, keyword: _ } => *id,
             Expr::Unary {
                 id,
                 operator: _,
@@ -363,6 +914,31 @@ impl Expr {
         }
     }
 
+    // a token to report a runtime error about the whole expression at, none
+    // for a literal
+    pub fn token(&self) -> Option<&scanner::Token> {
+        match self {
+            Expr::AnonFunction { paren, .. } | Expr::Call { paren, .. } => return Some(paren),
+            Expr::Assign { name, .. }
+            | Expr::Get { name, .. }
+            | Expr::Set { name, .. }
+            | Expr::Variable { name, .. } => return Some(name),
+            Expr::Binary { operator, .. }
+            | Expr::Logical { operator, .. }
+            | Expr::Unary { operator, .. } => return Some(operator),
+            Expr::Index { bracket, .. }
+            | Expr::List { bracket, .. }
+            | Expr::SetIndex { bracket, .. } => return Some(bracket),
+            Expr::Grouping { id: _, expression } => return expression.token(),
+            Expr::Interpolation { quote, .. } => return Some(quote),
+            Expr::Map { brace, .. } => return Some(brace),
+            Expr::Match { keyword, .. }
+            | Expr::Super { keyword, .. }
+            | Expr::This { keyword, .. } => return Some(keyword),
+            Expr::Literal { id: _, value: _ } => return None,
+        }
+    }
+
     #[allow(dead_code)]
     pub fn to_string(&self) -> String {
         match self {
@@ -372,7 +948,9 @@ impl Expr {
                 arguments,
                 body: _,
             } => format!("anon/{}", arguments.len()),
-            Expr::Assign { id: _, name, value } => format!("({name:?} = {})", value.to_string()),
+            Expr::Assign { id: _, name, value } => {
+                format!("(assign {} {})", name.lexeme, value.to_string())
+            }
             Expr::Binary {
                 id: _,
                 left,
@@ -398,7 +976,40 @@ impl Expr {
             Expr::Grouping { id: _, expression } => {
                 format!("(group {})", (*expression).to_string())
             }
+            Expr::Index {
+                id: _,
+                object,
+                bracket: _,
+                index,
+            } => format!("(index {} {})", object.to_string(), index.to_string()),
+            Expr::Interpolation {
+                id: _,
+                quote: _,
+                parts,
+            } => format!("(interpolate {:?})", parts),
+            Expr::List {
+                id: _,
+                bracket: _,
+                elements,
+            } => format!("(list {:?})", elements),
+            Expr::Map {
+                id: _,
+                brace: _,
+                entries,
+            } => format!("(map {:?})", entries),
             Expr::Literal { id: _, value } => format!("{}", value.to_string()),
+            Expr::Match {
+                id: _,
+                keyword: _,
+                subject,
+                arms,
+            } => {
+                let arms: String = arms
+                    .iter()
+                    .map(|(pattern, body)| format!(" ({} {})", pattern, body.to_string()))
+                    .collect();
+                format!("(match {}{})", subject.to_string(), arms)
+            }
             Expr::Logical {
                 id: _,
                 left,
@@ -415,7 +1026,30 @@ impl Expr {
                 object,
                 name,
                 value,
-            } => format!("(set {} {} to {:?}", object.to_string(), name.lexeme, value),
+            } => format!(
+                "(set {} {} to {:?})",
+                object.to_string(),
+                name.lexeme,
+                value
+            ),
+            Expr::SetIndex {
+                id: _,
+                object,
+                bracket: _,
+                index,
+                value,
+            } => format!(
+                "(set-index {} {} to {:?})",
+                object.to_string(),
+                index.to_string(),
+                value
+            ),
+            Expr::Super {
+                id: _,
+                keyword: _,
+                method,
+            } => format!("(super {})", method.lexeme),
+            Expr::This { id: _, keyword: _ } => "this".to_string(),
             Expr::Unary {
                 id: _,
                 operator,
@@ -429,7 +1063,7 @@ impl Expr {
         }
     }
 
-    pub fn evaluate(&self, env: environment::Environment) -> Result<LiteralValue, String> {
+    pub fn evaluate(&self, env: environment::Environment) -> Result<LiteralValue, RuntimeError> {
         match self {
             Expr::AnonFunction {
                 id: _,
@@ -441,7 +1075,7 @@ impl Expr {
                 let arguments: Vec<scanner::Token> =
                     arguments.iter().map(|t| (*t).clone()).collect();
                 let body: Vec<Box<stmt::Stmt>> = body.iter().map(|b| (*b).clone()).collect();
-                let paren = paren.clone();
+                let captured = env.clone();
                 let fun_impl = move |args: &Vec<LiteralValue>| {
                     let mut anon_int = interpreter::Interpreter::for_anon(env.clone());
                     for (i, arg) in args.iter().enumerate() {
@@ -450,24 +1084,20 @@ impl Expr {
                             .define(arguments[i].lexeme.clone(), (*arg).clone());
                     }
 
-                    for i in 0..(body.len()) {
-                        anon_int.interpret(vec![&body[i]]).expect(&format!(
-                            "evaluating failed inside anon function at line {}",
-                            paren.line_number
-                        ));
+                    let flow = anon_int.interpret(body.iter().map(|b| b.as_ref()).collect())?;
 
-                        if let Some(value) = anon_int.specials.get("return") {
-                            return value.clone();
-                        }
+                    match flow {
+                        interpreter::ControlFlow::Return(value) => Ok(value),
+                        interpreter::ControlFlow::Normal
+                        | interpreter::ControlFlow::Break
+                        | interpreter::ControlFlow::Continue => Ok(expr::LiteralValue::Nil),
                     }
-
-                    return expr::LiteralValue::Nil;
                 };
 
                 return Ok(LiteralValue::Callable {
-                    name: "anon_functin".to_string(),
+                    name: format!("anon_function@{}", paren.line_number),
                     arity,
-                    fun: Rc::new(fun_impl),
+                    fun: gc::function(Rc::new(fun_impl), Some(&captured), vec![]),
                 });
             }
             Expr::Assign { id: _, name, value } => {
@@ -476,28 +1106,51 @@ impl Expr {
                 if assign_success {
                     return Ok(new_value);
                 } else {
-                    return Err(format!("variable '{}' has not been declared", name.lexeme));
+                    return Err(RuntimeError::at(
+                        name,
+                        format!("variable '{}' has not been declared", name.lexeme),
+                    ));
                 }
             }
             Expr::Variable { id: _, name } => match env.get(&name.lexeme, self.get_id()) {
                 Some(value) => Ok(value.clone()),
-                None => Err(format!("variable '{}' has not been declared", name.lexeme)),
+                None => Err(RuntimeError::at(
+                    name,
+                    format!("variable '{}' has not been declared", name.lexeme),
+                )),
             },
             Expr::Call {
                 id: _,
                 callee,
-                paren: _,
+                paren,
                 arguments,
             } => {
-                let callable: LiteralValue = (*callee).evaluate(env.clone())?;
+                // methods are profiled under their class, like initializers
+                let (callable, class) = match callee.as_ref() {
+                    Expr::Get {
+                        id: _,
+                        object,
+                        name,
+                    } => {
+                        let object = object.evaluate(env.clone())?;
+                        let method = object
+                            .get_property(&name.lexeme)
+                            .map_err(|msg| RuntimeError::at(name, msg))?;
+                        (method, object.method_class(&name.lexeme))
+                    }
+                    callee => (callee.evaluate(env.clone())?, None),
+                };
                 match callable {
                     LiteralValue::Callable { name, arity, fun } => {
                         if arguments.len() != arity {
-                            return Err(format!(
-                                "callable {} expected {} arguments but got {}",
-                                name,
-                                arity,
-                                arguments.len()
+                            return Err(RuntimeError::at(
+                                paren,
+                                format!(
+                                    "callable {} expected {} arguments but got {}",
+                                    name,
+                                    arity,
+                                    arguments.len()
+                                ),
                             ));
                         }
                         let mut arg_vals = vec![];
@@ -505,23 +1158,93 @@ impl Expr {
                             let val = arg.evaluate(env.clone())?;
                             arg_vals.push(val);
                         }
-                        return Ok(fun(&arg_vals));
+                        limits::enter().map_err(|mut err| {
+                            err.locate(paren);
+                            err
+                        })?;
+                        debugger::enter(&name, paren.line_number);
+                        match &class {
+                            Some(class) => profiler::enter(&format!("{}.{}", class, name)),
+                            None => profiler::enter(&name),
+                        }
+                        let result = fun(&arg_vals);
+                        profiler::exit();
+                        debugger::exit();
+                        limits::exit();
+                        return result.map_err(|mut err| {
+                            err.locate(paren);
+                            err.push_frame(&name, paren.line_number);
+                            err
+                        });
                     }
                     LiteralValue::LoxClass {
-                        name: _,
+                        ref name,
                         methods: _,
+                        superclass: _,
                     } => {
-                        if arguments.len() != 0 {
-                            return Err(
-                                "can only call the constructor with zero arguments".to_string()
-                            );
+                        let initializer = callable.find_method("init");
+                        let arity = match &initializer {
+                            Some(LiteralValue::Callable {
+                                name: _,
+                                arity,
+                                fun: _,
+                            }) => *arity,
+                            _ => 0,
+                        };
+                        if arguments.len() != arity {
+                            return Err(RuntimeError::at(
+                                paren,
+                                format!(
+                                    "class {} expected {} arguments but got {}",
+                                    name,
+                                    arity,
+                                    arguments.len()
+                                ),
+                            ));
+                        }
+                        let mut arg_vals = vec![];
+                        for arg in arguments {
+                            let val = arg.evaluate(env.clone())?;
+                            arg_vals.push(val);
                         }
-                        return Ok(LiteralValue::LoxInstance {
+
+                        limits::allocate(limits::VALUE_BYTES).map_err(|mut err| {
+                            err.locate(paren);
+                            err
+                        })?;
+                        let instance = LiteralValue::LoxInstance {
                             class: Box::new(callable.clone()),
-                            fields: Rc::new(RefCell::new(vec![])),
-                        });
+                            fields: gc::fields(),
+                        };
+                        if let Some(LiteralValue::Callable {
+                            name: init_name,
+                            arity: _,
+                            fun,
+                        }) = initializer.map(|init| init.bind(instance.clone()))
+                        {
+                            let init_name = format!("{}.{}", name, init_name);
+                            limits::enter().map_err(|mut err| {
+                                err.locate(paren);
+                                err
+                            })?;
+                            debugger::enter(&init_name, paren.line_number);
+                            profiler::enter(&init_name);
+                            let result = fun(&arg_vals);
+                            profiler::exit();
+                            debugger::exit();
+                            limits::exit();
+                            result.map_err(|mut err| {
+                                err.locate(paren);
+                                err.push_frame(&init_name, paren.line_number);
+                                err
+                            })?;
+                        }
+                        return Ok(instance);
                     }
-                    other => Err(format!("{} is not a callable", other.to_type())),
+                    other => Err(RuntimeError::at(
+                        paren,
+                        format!("{} is not a callable", other.to_type()),
+                    )),
                 }
             }
             Expr::Literal { id: _, value } => Ok((*value).clone()),
@@ -533,7 +1256,9 @@ impl Expr {
             } => match operator.token_type {
                 scanner::TokenType::Or => {
                     let lhs_value = left.evaluate(env.clone())?;
-                    let lhs_true = lhs_value.is_truthy();
+                    let lhs_true = lhs_value
+                        .is_truthy()
+                        .map_err(|msg| RuntimeError::at(operator, msg))?;
                     if lhs_true == LiteralValue::True {
                         return Ok(lhs_value);
                     } else {
@@ -542,14 +1267,19 @@ impl Expr {
                 }
                 scanner::TokenType::And => {
                     let lhs_value = left.evaluate(env.clone())?;
-                    let lhs_true = lhs_value.is_truthy();
+                    let lhs_true = lhs_value
+                        .is_truthy()
+                        .map_err(|msg| RuntimeError::at(operator, msg))?;
                     if lhs_true == LiteralValue::False {
                         return Ok(lhs_true);
                     } else {
                         return right.evaluate(env.clone());
                     }
                 }
-                ttype => Err(format!("Invalid token in logical expression: {}", ttype)),
+                ttype => Err(RuntimeError::at(
+                    operator,
+                    format!("Invalid token in logical expression: {}", ttype),
+                )),
             },
             Expr::Get {
                 id: _,
@@ -557,29 +1287,101 @@ impl Expr {
                 name,
             } => {
                 let obj_value = object.evaluate(env.clone())?;
-                // obj_value should be a LoxInstance
-                if let LiteralValue::LoxInstance { class, fields } = obj_value {
-                    for (field_name, value) in (*fields.borrow()).iter() {
-                        if field_name == &name.lexeme {
-                            return Ok(value.clone());
-                        }
+                obj_value
+                    .get_property(&name.lexeme)
+                    .map_err(|msg| RuntimeError::at(name, msg))
+            }
+            Expr::Grouping { id: _, expression } => expression.evaluate(env.clone()),
+            Expr::Index {
+                id: _,
+                object,
+                bracket,
+                index,
+            } => {
+                let object = object.evaluate(env.clone())?;
+                let index = index.evaluate(env.clone())?;
+                index_get(&object, &index).map_err(|msg| RuntimeError::at(bracket, msg))
+            }
+            Expr::Interpolation {
+                id: _,
+                quote,
+                parts,
+            } => {
+                let mut text = String::new();
+                for part in parts {
+                    text.push_str(&part.evaluate(env.clone())?.display());
+                }
+                limits::allocate(text.len()).map_err(|mut err| {
+                    err.locate(quote);
+                    err
+                })?;
+                Ok(LiteralValue::StringLit(text))
+            }
+            Expr::List {
+                id: _,
+                bracket,
+                elements,
+            } => {
+                let mut items = vec![];
+                for element in elements {
+                    items.push(element.evaluate(env.clone())?);
+                }
+                limits::allocate(items.len() * limits::VALUE_BYTES).map_err(|mut err| {
+                    err.locate(bracket);
+                    err
+                })?;
+                Ok(LiteralValue::List(gc::list(items)))
+            }
+            Expr::Map {
+                id: _,
+                brace,
+                entries,
+            } => {
+                // index_set charges for each entry
+                let map = LiteralValue::Map(gc::map(vec![]));
+                for (key, value) in entries {
+                    let key = key.evaluate(env.clone())?;
+                    let value = value.evaluate(env.clone())?;
+                    index_set(&map, &key, value).map_err(|msg| RuntimeError::at(brace, msg))?;
+                }
+                Ok(map)
+            }
+            Expr::Match {
+                id: _,
+                keyword,
+                subject,
+                arms,
+            } => {
+                let value = subject.evaluate(env.clone())?;
+                for (pattern, body) in arms {
+                    if !pattern.matches(&value, env.clone())? {
+                        continue;
                     }
-                    if let LiteralValue::LoxClass { name: _, methods } = class.as_ref() {
-                        if let Some(method) = methods.get(&name.lexeme) {
-                            return Ok(method.clone());
-                        }
-                    } else {
-                        panic!("the class field on an instance was not a LoxClass");
+                    let arm_env = env.enclose();
+                    if let Some(name) = pattern.binding() {
+                        arm_env.define(name.lexeme.clone(), value.clone());
                     }
-                    Err(format!("no field named {} on this instance", name.lexeme))
-                } else {
-                    Err(format!(
-                        "cannot access property on type {}",
-                        obj_value.to_type()
-                    ))
+                    return body.evaluate(arm_env);
                 }
+                Err(RuntimeError::at(
+                    keyword,
+                    format!("no match arm for {}", value.to_string()),
+                ))
+            }
+            Expr::SetIndex {
+                id: _,
+                object,
+                bracket,
+                index,
+                value,
+            } => {
+                let object = object.evaluate(env.clone())?;
+                let index = index.evaluate(env.clone())?;
+                let value = value.evaluate(env.clone())?;
+                index_set(&object, &index, value.clone())
+                    .map_err(|msg| RuntimeError::at(bracket, msg))?;
+                Ok(value)
             }
-            Expr::Grouping { id: _, expression } => expression.evaluate(env.clone()),
             Expr::Set {
                 id: _,
                 object,
@@ -587,35 +1389,57 @@ impl Expr {
                 value,
             } => {
                 let obj_value = object.evaluate(env.clone())?;
-                if let LiteralValue::LoxInstance { class: _, fields } = obj_value {
+                if let LiteralValue::LoxInstance { .. } = obj_value {
                     let value = value.evaluate(env.clone())?;
-
-                    let mut idx = 0;
-                    let mut found = false;
-                    for i in 0..(*fields.borrow()).len() {
-                        let field_name = &(*fields.borrow())[i].0;
-                        if field_name == &name.lexeme {
-                            //fields[i].1 = value.clone();
-                            idx = i;
-                            found = true;
-                            break;
-                        }
-                    }
-
-                    if found {
-                        (*fields.borrow_mut())[idx].1 = value.clone();
-                    } else {
-                        (*fields.borrow_mut()).push((name.lexeme.clone(), value));
-                    }
-
+                    obj_value
+                        .set_property(&name.lexeme, value)
+                        .map_err(|msg| RuntimeError::at(name, msg))?;
                     return Ok(expr::LiteralValue::Nil);
                 } else {
-                    Err(format!(
-                        "cannot set property on type {}",
-                        obj_value.to_type()
+                    Err(RuntimeError::at(
+                        name,
+                        format!("cannot set property on type {}", obj_value.to_type()),
                     ))
                 }
             }
+            Expr::Super {
+                id: _,
+                keyword,
+                method,
+            } => {
+                let superclass = match env.get("super", self.get_id()) {
+                    Some(superclass) => superclass,
+                    None => {
+                        return Err(RuntimeError::at(
+                            keyword,
+                            "'super' is not defined in this scope",
+                        ))
+                    }
+                };
+                let this = match env.get_this(self.get_id()) {
+                    Some(this) => this,
+                    None => {
+                        return Err(RuntimeError::at(
+                            keyword,
+                            "'this' is not defined in this scope",
+                        ))
+                    }
+                };
+                match superclass.find_method(&method.lexeme) {
+                    Some(method) => Ok(method.bind(this)),
+                    None => Err(RuntimeError::at(
+                        method,
+                        format!("no method named {} on the superclass", method.lexeme),
+                    )),
+                }
+            }
+            Expr::This { id: _, keyword } => match env.get("this", self.get_id()) {
+                Some(value) => Ok(value),
+                None => Err(RuntimeError::at(
+                    keyword,
+                    "'this' is not defined in this scope",
+                )),
+            },
             Expr::Unary {
                 id: _,
                 operator,
@@ -623,17 +1447,7 @@ impl Expr {
             } => {
                 let right = right.evaluate(env.clone())?;
 
-                match (&right, operator.token_type) {
-                    (LiteralValue::Number(x), scanner::TokenType::Minus) => {
-                        Ok(LiteralValue::Number(-x))
-                    }
-                    (_, scanner::TokenType::Minus) => Err(format!(
-                        "minus operation not supported for {}",
-                        right.to_type()
-                    )),
-                    (any, scanner::TokenType::Bang) => Ok(any.is_falsy()),
-                    (_, toktype) => Err(format!("{} is not a valid unary operator", toktype)),
-                }
+                unary_op(operator.token_type, &right).map_err(|msg| RuntimeError::at(operator, msg))
             }
             Expr::Binary {
                 id: _,
@@ -644,82 +1458,15 @@ impl Expr {
                 let left = left.evaluate(env.clone())?;
                 let right = right.evaluate(env.clone())?;
 
-                match (&left, operator.token_type, &right) {
-                    (
-                        LiteralValue::Number(x),
-                        scanner::TokenType::Star,
-                        LiteralValue::Number(y),
-                    ) => Ok(LiteralValue::Number(x * y)),
-                    (
-                        LiteralValue::Number(x),
-                        scanner::TokenType::Slash,
-                        LiteralValue::Number(y),
-                    ) => Ok(LiteralValue::Number(x / y)),
-                    (
-                        LiteralValue::Number(x),
-                        scanner::TokenType::Plus,
-                        LiteralValue::Number(y),
-                    ) => Ok(LiteralValue::Number(x + y)),
-                    (
-                        LiteralValue::Number(x),
-                        scanner::TokenType::Minus,
-                        LiteralValue::Number(y),
-                    ) => Ok(LiteralValue::Number(x - y)),
-
-                    (
-                        LiteralValue::Number(x),
-                        scanner::TokenType::Greater,
-                        LiteralValue::Number(y),
-                    ) => Ok(LiteralValue::from_bool(x > y)),
-                    (
-                        LiteralValue::Number(x),
-                        scanner::TokenType::GreaterEqual,
-                        LiteralValue::Number(y),
-                    ) => Ok(LiteralValue::from_bool(x >= y)),
-                    (
-                        LiteralValue::Number(x),
-                        scanner::TokenType::Less,
-                        LiteralValue::Number(y),
-                    ) => Ok(LiteralValue::from_bool(x < y)),
-                    (
-                        LiteralValue::Number(x),
-                        scanner::TokenType::LessEqual,
-                        LiteralValue::Number(y),
-                    ) => Ok(LiteralValue::from_bool(x <= y)),
-
-                    (LiteralValue::StringLit(_), op, LiteralValue::Number(_)) => Err(format!(
-                        "binary operation {} not supported for inconsistent types",
-                        op
-                    )),
-                    (LiteralValue::Number(_), op, LiteralValue::StringLit(_)) => Err(format!(
-                        "binary operation {} not supported for inconsistent types",
-                        op
-                    )),
-
-                    (
-                        LiteralValue::StringLit(s1),
-                        scanner::TokenType::Plus,
-                        LiteralValue::StringLit(s2),
-                    ) => Ok(LiteralValue::StringLit(format!("{}{}", s1, s2))),
-                    (
-                        LiteralValue::StringLit(s1),
-                        scanner::TokenType::Less,
-                        LiteralValue::StringLit(s2),
-                    ) => Ok(LiteralValue::from_bool(s1 < s2)),
-                    (
-                        LiteralValue::StringLit(s1),
-                        scanner::TokenType::LessEqual,
-                        LiteralValue::StringLit(s2),
-                    ) => Ok(LiteralValue::from_bool(s1 <= s2)),
-
-                    (x, scanner::TokenType::BangEqual, y) => Ok(LiteralValue::from_bool(x != y)),
-                    (x, scanner::TokenType::EqualEqual, y) => Ok(LiteralValue::from_bool(x == y)),
-
-                    (x, toktype, y) => Err(format!(
-                        "binary operator {} not implemented for operands {:?} and {:?}",
-                        toktype, x, y
-                    )),
+                let value = binary_op(&left, operator.token_type, &right)
+                    .map_err(|msg| RuntimeError::at(operator, msg))?;
+                if let LiteralValue::StringLit(text) = &value {
+                    limits::allocate(text.len()).map_err(|mut err| {
+                        err.locate(operator);
+                        err
+                    })?;
                 }
+                Ok(value)
             }
         }
     }
@@ -742,6 +1489,8 @@ mod tests {
             lexeme: "-".to_string(),
             literal: None,
             line_number: 0,
+            column: 0,
+            offset: 0,
         };
         let onetwothree = Box::from(Expr::Literal {
             id: 0,
@@ -759,6 +1508,8 @@ mod tests {
             lexeme: "*".to_string(),
             literal: None,
             line_number: 0,
+            column: 0,
+            offset: 0,
         };
 
         let exp = Expr::Binary {
@@ -784,6 +1535,8 @@ mod tests {
             lexeme: "-".to_string(),
             literal: None,
             line_number: 0,
+            column: 0,
+            offset: 0,
         };
         let onetwothree = Box::from(Expr::Literal {
             id: 6,
@@ -801,6 +1554,8 @@ mod tests {
             lexeme: "*".to_string(),
             literal: None,
             line_number: 0,
+            column: 0,
+            offset: 0,
         };
 
         let exp = Expr::Binary {
@@ -828,6 +1583,8 @@ mod tests {
             lexeme: "-".to_string(),
             literal: None,
             line_number: 0,
+            column: 0,
+            offset: 0,
         };
         let onetwothree = Box::from(Expr::Literal {
             id: 1,
@@ -845,6 +1602,8 @@ mod tests {
             lexeme: "*".to_string(),
             literal: None,
             line_number: 0,
+            column: 0,
+            offset: 0,
         };
 
         let ast = Expr::Binary {
@@ -0,0 +1,804 @@
+use crate::diagnostic::Diagnostic;
+use crate::expr::{Expr, LiteralValue, Pattern};
+use crate::parser::Parser;
+use crate::scanner::{Comment, LiteralValue as ScannedLiteral, Scanner, Token, TokenType};
+use crate::stmt::{annotated, Stmt};
+
+const INDENT: &str = "  ";
+
+// prints a program back as source, the AST gives the layout and the token
+// stream says where the comments were and what the parser desugared
+pub fn format(source: &str) -> Result<String, Vec<Diagnostic>> {
+    let mut scanner = Scanner::new(source);
+    let tokens = scanner.scan_tokens()?;
+    let comments = std::mem::take(&mut scanner.comments);
+    let statements = Parser::new(tokens.clone()).parse()?;
+
+    let mut formatter = Formatter {
+        tokens,
+        comments,
+        pos: 0,
+        next_comment: 0,
+        pending: vec![],
+        out: String::new(),
+        indent: 0,
+        last_line: 0,
+        block_start: true,
+    };
+    for statement in &statements {
+        formatter.statement(statement);
+    }
+    formatter.take_comments(usize::MAX);
+    formatter.own_lines();
+
+    return Ok(formatter.out);
+}
+
+struct Formatter {
+    tokens: Vec<Token>,
+    comments: Vec<Comment>,
+    // the next source token that has not been printed
+    pos: usize,
+    next_comment: usize,
+    // comments already passed in the source but not printed yet
+    pending: Vec<Comment>,
+    out: String,
+    indent: usize,
+    // the source line of the last thing printed, to keep blank lines
+    last_line: usize,
+    block_start: bool,
+}
+
+impl Formatter {
+    fn write(&mut self, text: &str) {
+        if self.out.is_empty() || self.out.ends_with('\n') {
+            self.out.push_str(&INDENT.repeat(self.indent));
+        }
+        self.out.push_str(text);
+    }
+
+    // moves past the next token of this type, the formatter only syncs on tokens
+    // it prints itself so the ones inside expressions never get in the way
+    fn sync(&mut self, token_type: TokenType) {
+        let index = self.find(token_type);
+        self.take_comments(self.tokens[index].offset);
+        self.last_line = self.tokens[index].line_number;
+        self.pos = (index + 1).min(self.tokens.len() - 1);
+    }
+
+    fn find(&self, token_type: TokenType) -> usize {
+        let mut index = self.pos;
+        while index < self.tokens.len() - 1 && self.tokens[index].token_type != token_type {
+            index += 1;
+        }
+        return index;
+    }
+
+    // strings keep the escapes they were written with, the next string token is
+    // the one being printed as long as expressions come out in source order
+    fn string(&mut self, literal: &Expr) -> String {
+        let mut index = self.pos;
+        while index < self.tokens.len() - 1
+            && !matches!(
+                self.tokens[index].token_type,
+                TokenType::StringLit | TokenType::Interpolation
+            )
+        {
+            index += 1;
+        }
+
+        let token = &self.tokens[index];
+        if let (
+            Expr::Literal {
+                id: _,
+                value: LiteralValue::StringLit(text),
+            },
+            Some(ScannedLiteral::StringValue(scanned)),
+        ) = (literal, &token.literal)
+        {
+            if text == scanned {
+                let lexeme = token.lexeme.clone();
+                self.take_comments(token.offset);
+                self.pos = index + 1;
+                return lexeme;
+            }
+            return format!("\"{}\"", escape(text));
+        }
+        panic!("formatter expected a string literal");
+    }
+
+    fn peek(&self, distance: usize) -> TokenType {
+        let index = (self.pos + distance).min(self.tokens.len() - 1);
+        return self.tokens[index].token_type;
+    }
+
+    fn take_comments(&mut self, offset: usize) {
+        while self.next_comment < self.comments.len()
+            && self.comments[self.next_comment].offset < offset
+        {
+            self.pending.push(self.comments[self.next_comment].clone());
+            self.next_comment += 1;
+        }
+    }
+
+    fn own_lines(&mut self) {
+        for comment in std::mem::take(&mut self.pending) {
+            self.blank_line_before(comment.line);
+            self.write(&comment.text);
+            self.out.push('\n');
+            self.last_line = comment.line;
+        }
+    }
+
+    // at most one blank line is kept, and never at the start of a block
+    fn blank_line_before(&mut self, line: usize) {
+        if !self.block_start && line > self.last_line + 1 {
+            self.out.push('\n');
+        }
+        self.block_start = false;
+    }
+
+    // ends the current line, a comment that trailed the code in the source
+    // stays on it and any others move below it
+    fn newline(&mut self) {
+        let line = self.tokens[self.pos.saturating_sub(1)].line_number;
+        while self.next_comment < self.comments.len()
+            && self.comments[self.next_comment].line == line
+        {
+            self.pending.push(self.comments[self.next_comment].clone());
+            self.next_comment += 1;
+        }
+
+        let mut trailing = None;
+        let mut below = vec![];
+        for comment in std::mem::take(&mut self.pending) {
+            if !comment.own_line && trailing.is_none() {
+                trailing = Some(comment);
+            } else {
+                below.push(comment);
+            }
+        }
+
+        if let Some(comment) = trailing {
+            self.out.push(' ');
+            self.out.push_str(&comment.text);
+        }
+        self.out.push('\n');
+        self.pending = below;
+        self.own_lines();
+    }
+
+    fn line_start(&mut self) {
+        let next = self.tokens[self.pos].clone();
+        self.take_comments(next.offset);
+        self.own_lines();
+        self.blank_line_before(next.line_number);
+    }
+
+    fn statement(&mut self, statement: &Stmt) {
+        self.line_start();
+        self.statement_body(statement);
+        self.newline();
+    }
+
+    fn statement_body(&mut self, statement: &Stmt) {
+        if self.peek(0) == TokenType::For || is_for_loop(statement) {
+            return self.for_loop(statement);
+        }
+
+        match statement {
+            Stmt::Expression { expression } => {
+                self.expr(expression);
+                self.sync(TokenType::Semicolon);
+                self.write(";");
+            }
+            Stmt::Print { expression } => {
+                self.sync(TokenType::Print);
+                self.write("print ");
+                self.expr(expression);
+                self.sync(TokenType::Semicolon);
+                self.write(";");
+            }
+            Stmt::Var {
+                name,
+                annotation,
+                initializer,
+            } => self.var(name, annotation, initializer),
+            Stmt::Block { statements } => self.block(statements),
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                self.sync(TokenType::Class);
+                self.write(&format!("class {}", name.lexeme));
+                if let Some(superclass) = superclass {
+                    self.write(" < ");
+                    self.expr(superclass);
+                }
+                self.write(" ");
+
+                if methods.is_empty() && self.is_empty_block() {
+                    return self.empty_block();
+                }
+                self.open_brace();
+                for method in methods {
+                    self.line_start();
+                    match method.as_ref() {
+                        method @ Stmt::Function {
+                            name: _,
+                            params: _,
+                            param_annotations: _,
+                            return_annotation: _,
+                            body: _,
+                        } => self.function(method),
+                        _ => panic!("class method expects function type"),
+                    }
+                    self.newline();
+                }
+                self.close_brace();
+            }
+            Stmt::IfStmt {
+                predicate,
+                then,
+                els,
+            } => {
+                self.sync(TokenType::If);
+                self.write("if (");
+                self.expr(predicate);
+                self.write(")");
+                self.body(then);
+
+                if let Some(els) = els {
+                    if let Stmt::Block { statements: _ } = then.as_ref() {
+                        self.write(" ");
+                    } else {
+                        self.newline();
+                    }
+                    self.sync(TokenType::Else);
+                    self.write("else");
+                    self.body(els);
+                }
+            }
+            Stmt::WhileStmt {
+                condition,
+                body,
+                increment: _,
+            } => {
+                self.sync(TokenType::While);
+                self.write("while (");
+                self.expr(condition);
+                self.write(")");
+                self.body(body);
+            }
+            Stmt::Function {
+                name: _,
+                params: _,
+                param_annotations: _,
+                return_annotation: _,
+                body: _,
+            } => {
+                self.sync(TokenType::Fun);
+                self.write("fun ");
+                self.function(statement);
+            }
+            Stmt::ReturnStmt { keyword: _, value } => {
+                self.sync(TokenType::Return);
+                self.write("return");
+                if let Some(value) = value {
+                    self.write(" ");
+                    self.expr(value);
+                }
+                self.sync(TokenType::Semicolon);
+                self.write(";");
+            }
+            Stmt::Import { path, name } => {
+                self.sync(TokenType::Import);
+                self.write(&format!("import {} as {};", path.lexeme, name.lexeme));
+                self.sync(TokenType::Semicolon);
+            }
+            Stmt::Break { keyword: _ } => {
+                self.sync(TokenType::Break);
+                self.write("break;");
+                self.sync(TokenType::Semicolon);
+            }
+            Stmt::Continue { keyword: _ } => {
+                self.sync(TokenType::Continue);
+                self.write("continue;");
+                self.sync(TokenType::Semicolon);
+            }
+            Stmt::Throw { keyword: _, value } => {
+                self.sync(TokenType::Throw);
+                self.write("throw ");
+                self.expr(value);
+                self.sync(TokenType::Semicolon);
+                self.write(";");
+            }
+            Stmt::Try {
+                keyword: _,
+                body,
+                catch,
+                finally,
+            } => {
+                self.sync(TokenType::Try);
+                self.write("try");
+                self.body(body);
+                if let Some((name, handler)) = catch {
+                    self.write(" ");
+                    self.sync(TokenType::Catch);
+                    self.write(&format!("catch ({})", name.lexeme));
+                    self.body(handler);
+                }
+                if let Some(finally) = finally {
+                    self.write(" ");
+                    self.sync(TokenType::Finally);
+                    self.write("finally");
+                    self.body(finally);
+                }
+            }
+        }
+    }
+
+    // the statement after if, else, while or for, on the same line
+    fn body(&mut self, statement: &Stmt) {
+        self.write(" ");
+        self.statement_body(statement);
+    }
+
+    fn var(&mut self, name: &Token, annotation: &Option<Token>, initializer: &Expr) {
+        self.sync(TokenType::Var);
+        self.write(&format!("var {}", annotated(name, annotation)));
+        // `var a;` is parsed with a nil initializer
+        let end = if annotation.is_some() { 3 } else { 1 };
+        if self.peek(end) != TokenType::Semicolon {
+            self.write(" = ");
+            self.expr(initializer);
+        }
+        self.sync(TokenType::Semicolon);
+        self.write(";");
+    }
+
+    fn for_loop(&mut self, statement: &Stmt) {
+        let (initializer, while_loop) = match statement {
+            Stmt::Block { statements } if statements.len() == 2 => {
+                (Some(statements[0].as_ref()), statements[1].as_ref())
+            }
+            _ => (None, statement),
+        };
+        let (condition, body, increment) = match while_loop {
+            Stmt::WhileStmt {
+                condition,
+                body,
+                increment,
+            } => (condition, body, increment),
+            _ => panic!("for loop was not parsed into a while loop"),
+        };
+
+        self.sync(TokenType::For);
+        self.write("for (");
+        match initializer {
+            Some(Stmt::Var {
+                name,
+                annotation,
+                initializer,
+            }) => self.var(name, annotation, initializer),
+            Some(Stmt::Expression { expression }) => {
+                self.expr(expression);
+                self.sync(TokenType::Semicolon);
+                self.write(";");
+            }
+            _ => {
+                self.sync(TokenType::Semicolon);
+                self.write(";");
+            }
+        }
+
+        // a missing condition is parsed as `true`
+        if self.peek(0) != TokenType::Semicolon {
+            self.write(" ");
+            self.expr(condition);
+        }
+        self.sync(TokenType::Semicolon);
+        self.write(";");
+
+        if let Some(increment) = increment {
+            self.write(" ");
+            self.expr(increment);
+        }
+        self.write(")");
+        self.body(body);
+    }
+
+    fn function(&mut self, function: &Stmt) {
+        let (name, params, param_annotations, return_annotation, body) = match function {
+            Stmt::Function {
+                name,
+                params,
+                param_annotations,
+                return_annotation,
+                body,
+            } => (name, params, param_annotations, return_annotation, body),
+            _ => panic!("expected a function declaration"),
+        };
+        let params: Vec<String> = params
+            .iter()
+            .zip(param_annotations)
+            .map(|(param, annotation)| annotated(param, annotation))
+            .collect();
+        self.write(&format!("{}({}) ", name.lexeme, params.join(", ")));
+        if let Some(annotation) = return_annotation {
+            self.write(&format!("-> {} ", annotation.lexeme));
+        }
+        self.block(body);
+    }
+
+    fn block(&mut self, statements: &[Box<Stmt>]) {
+        if statements.is_empty() && self.is_empty_block() {
+            return self.empty_block();
+        }
+
+        self.open_brace();
+        for statement in statements {
+            self.statement(statement);
+        }
+        self.close_brace();
+    }
+
+    // `{}` with nothing in it, not even a comment
+    fn is_empty_block(&self) -> bool {
+        let open = self.find(TokenType::LeftBrace);
+        let close = (open + 1).min(self.tokens.len() - 1);
+        return self.tokens[close].token_type == TokenType::RightBrace
+            && !self.comments[self.next_comment..]
+                .iter()
+                .any(|comment| comment.offset < self.tokens[close].offset);
+    }
+
+    fn empty_block(&mut self) {
+        self.sync(TokenType::LeftBrace);
+        self.sync(TokenType::RightBrace);
+        self.write("{}");
+    }
+
+    fn open_brace(&mut self) {
+        self.sync(TokenType::LeftBrace);
+        self.write("{");
+        self.indent += 1;
+        self.newline();
+        self.block_start = true;
+    }
+
+    // comments after the last statement stay inside the block
+    fn close_brace(&mut self) {
+        let close = self.find(TokenType::RightBrace);
+        self.take_comments(self.tokens[close].offset);
+        self.own_lines();
+        self.indent -= 1;
+        self.sync(TokenType::RightBrace);
+        self.write("}");
+        self.block_start = false;
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) {
+        for (i, expr) in exprs.iter().enumerate() {
+            if i > 0 {
+                self.write(", ");
+            }
+            self.expr(expr);
+        }
+    }
+
+    // groupings are kept in the AST, so the tree prints back without
+    // adding any parentheses of its own
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::AnonFunction {
+                id: _,
+                paren: _,
+                arguments,
+                body,
+            } => {
+                self.sync(TokenType::Fun);
+                self.write(&format!("fun ({}) ", join_lexemes(arguments)));
+                self.block(body);
+            }
+            Expr::Assign { id: _, name, value } => {
+                self.write(&format!("{} = ", name.lexeme));
+                self.expr(value);
+            }
+            Expr::Binary {
+                id: _,
+                left,
+                operator,
+                right,
+            }
+            | Expr::Logical {
+                id: _,
+                left,
+                operator,
+                right,
+            } => {
+                self.expr(left);
+                self.write(&format!(" {} ", operator.lexeme));
+                self.expr(right);
+            }
+            Expr::Call {
+                id: _,
+                callee,
+                paren,
+                arguments,
+            } => {
+                // `a |> f` is parsed as a call of f with the pipe as its paren
+                if paren.token_type == TokenType::Pipe {
+                    self.exprs(arguments);
+                    self.write(" |> ");
+                    self.expr(callee);
+                } else {
+                    self.expr(callee);
+                    self.write("(");
+                    self.exprs(arguments);
+                    self.write(")");
+                }
+            }
+            Expr::Get {
+                id: _,
+                object,
+                name,
+            } => {
+                self.expr(object);
+                self.write(&format!(".{}", name.lexeme));
+            }
+            Expr::Grouping { id: _, expression } => {
+                self.write("(");
+                self.expr(expression);
+                self.write(")");
+            }
+            Expr::Index {
+                id: _,
+                object,
+                bracket: _,
+                index,
+            } => {
+                self.expr(object);
+                self.write("[");
+                self.expr(index);
+                self.write("]");
+            }
+            Expr::Interpolation {
+                id: _,
+                quote: _,
+                parts,
+            } => {
+                // the text segments come with their quotes and braces attached
+                for (i, part) in parts.iter().enumerate() {
+                    if i % 2 == 0 {
+                        let text = self.string(part);
+                        self.write(&text);
+                    } else {
+                        self.expr(part);
+                    }
+                }
+            }
+            Expr::List {
+                id: _,
+                bracket: _,
+                elements,
+            } => {
+                self.write("[");
+                self.exprs(elements);
+                self.write("]");
+            }
+            Expr::Literal {
+                id: _,
+                value: LiteralValue::StringLit(_),
+            } => {
+                let text = self.string(expr);
+                self.write(&text);
+            }
+            Expr::Literal { id: _, value } => self.write(&literal(value)),
+            Expr::Map {
+                id: _,
+                brace: _,
+                entries,
+            } => {
+                self.sync(TokenType::LeftBrace);
+                self.write("{");
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        self.write(", ");
+                    }
+                    self.expr(key);
+                    self.write(": ");
+                    self.expr(value);
+                }
+                self.sync(TokenType::RightBrace);
+                self.write("}");
+            }
+            Expr::Match {
+                id: _,
+                keyword: _,
+                subject,
+                arms,
+            } => {
+                // kept on one line like a map literal
+                self.sync(TokenType::Match);
+                self.write("match (");
+                self.expr(subject);
+                self.sync(TokenType::LeftBrace);
+                self.write(") {");
+                for (i, (pattern, body)) in arms.iter().enumerate() {
+                    if i > 0 {
+                        self.write(",");
+                    }
+                    self.write(" ");
+                    match pattern {
+                        Pattern::Literal { value } => self.expr(value),
+                        pattern => self.write(&pattern.to_string()),
+                    }
+                    self.write(" => ");
+                    self.expr(body);
+                }
+                self.sync(TokenType::RightBrace);
+                self.write(" }");
+            }
+            Expr::Set {
+                id: _,
+                object,
+                name,
+                value,
+            } => {
+                self.expr(object);
+                self.write(&format!(".{} = ", name.lexeme));
+                self.expr(value);
+            }
+            Expr::SetIndex {
+                id: _,
+                object,
+                bracket: _,
+                index,
+                value,
+            } => {
+                self.expr(object);
+                self.write("[");
+                self.expr(index);
+                self.write("] = ");
+                self.expr(value);
+            }
+            Expr::Super {
+                id: _,
+                keyword: _,
+                method,
+            } => self.write(&format!("super.{}", method.lexeme)),
+            Expr::This { id: _, keyword: _ } => self.write("this"),
+            Expr::Unary {
+                id: _,
+                operator,
+                right,
+            } => {
+                self.write(&operator.lexeme);
+                self.expr(right);
+            }
+            Expr::Variable { id: _, name } => self.write(&name.lexeme),
+        }
+    }
+}
+
+// for loops are parsed into a while loop, in a block when there is an
+// initializer, only a for loop gives the while an increment
+fn is_for_loop(statement: &Stmt) -> bool {
+    match statement {
+        Stmt::Block { statements } if statements.len() == 2 => {
+            return is_for_loop(&statements[1]);
+        }
+        Stmt::WhileStmt {
+            condition: _,
+            body: _,
+            increment,
+        } => return increment.is_some(),
+        _ => return false,
+    }
+}
+
+fn literal(value: &LiteralValue) -> String {
+    match value {
+        LiteralValue::StringLit(s) => return format!("\"{}\"", escape(s)),
+        value => return value.to_string(),
+    }
+}
+
+// the text of a string literal as it has to be written to scan back the same,
+// line breaks and tabs stay as they were typed
+fn escape(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '$' if chars.peek() == Some(&'{') => out.push_str("\\$"),
+            '\n' | '\t' => out.push(c),
+            '\r' => out.push_str("\\r"),
+            '\0' => out.push_str("\\0"),
+            c if c.is_control() || !c.is_ascii() => {
+                out.push_str(&format!("\\u{{{:X}}}", c as u32));
+            }
+            c => out.push(c),
+        }
+    }
+    return out;
+}
+
+fn join_lexemes(tokens: &[Token]) -> String {
+    return tokens
+        .iter()
+        .map(|token| token.lexeme.clone())
+        .collect::<Vec<String>>()
+        .join(", ");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_formats(source: &str, expected: &str) {
+        let formatted = format(source).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(format(&formatted).unwrap(), formatted, "not idempotent");
+    }
+
+    #[test]
+    fn formats_statements() {
+        assert_formats(
+            "var a;var b=1+(2*3);\nfun f(x,y){if(x)return y;else{print -x;}}\nclass B<A{init(){this.v=[1,2];}}",
+            "var a;\nvar b = 1 + (2 * 3);\nfun f(x, y) {\n  if (x) return y;\n  else {\n    print -x;\n  }\n}\nclass B < A {\n  init() {\n    this.v = [1, 2];\n  }\n}\n",
+        );
+    }
+
+    #[test]
+    fn formats_exceptions() {
+        assert_formats(
+            "try{throw   \"x\";}catch(e){print e;}finally{}",
+            "try {\n  throw \"x\";\n} catch (e) {\n  print e;\n} finally {}\n",
+        );
+    }
+
+    #[test]
+    fn formats_annotations() {
+        assert_formats(
+            "var a:Number;var b : String=\"s\";fun f(x:Number,y)->Bool{return x>y;}class P{init(x:Number){}}",
+            "var a: Number;\nvar b: String = \"s\";\nfun f(x: Number, y) -> Bool {\n  return x > y;\n}\nclass P {\n  init(x: Number) {}\n}\n",
+        );
+    }
+
+    #[test]
+    fn formats_match_expressions() {
+        assert_formats(
+            "print match(p){-1=>\"neg\",Point q=>q.x,\n_=>nil,};",
+            "print match (p) { -1 => \"neg\", Point q => q.x, _ => nil };\n",
+        );
+    }
+
+    #[test]
+    fn keeps_for_loops_and_pipes() {
+        assert_formats(
+            "for(var i=0;i<3;i=i+1)print i;\nfor(;;){break;}\nvar m={\"a\":1}|>f;",
+            "for (var i = 0; i < 3; i = i + 1) print i;\nfor (;;) {\n  break;\n}\nvar m = {\"a\": 1} |> f;\n",
+        );
+    }
+
+    #[test]
+    fn keeps_escapes_and_interpolations() {
+        assert_formats(
+            "print \"a\\t${x+1}\\\"\\$\\u{e9}${ {\"k\":2}[\"k\"] }\";",
+            "print \"a\\t${x + 1}\\\"\\$\\u{e9}${{\"k\": 2}[\"k\"]}\";\n",
+        );
+    }
+
+    #[test]
+    fn keeps_comments_and_blank_lines() {
+        assert_formats(
+            "// header\n\n\n\nfun f() { // opens\n  // inside\n  return 1; // one\n  // last\n}\n\nprint f(); // done\n// end\n",
+            "// header\n\nfun f() { // opens\n  // inside\n  return 1; // one\n  // last\n}\n\nprint f(); // done\n// end\n",
+        );
+    }
+}
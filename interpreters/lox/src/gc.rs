@@ -0,0 +1,434 @@
+use crate::environment::Environment;
+use crate::error::RuntimeError;
+use crate::expr::LiteralValue;
+use crate::vm::{Closure, Upvalue};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+pub type Scope = RefCell<HashMap<String, LiteralValue>>;
+pub type Fields = RefCell<Vec<(String, LiteralValue)>>;
+pub type List = RefCell<Vec<LiteralValue>>;
+pub type Map = RefCell<Vec<(LiteralValue, LiteralValue)>>;
+pub type Function = dyn Fn(&Vec<LiteralValue>) -> Result<LiteralValue, RuntimeError>;
+
+// the heap size that triggers the first collection, later ones wait for the
+// heap to double from what the previous collection left behind
+const INITIAL_THRESHOLD: usize = 1024;
+
+// A cycle collector over Rc, not a heap that owns its objects. Values keep
+// sharing objects through Rc, which frees everything acyclic as soon as the last
+// reference goes. The heap only keeps a weak handle to every object that can be
+// part of a cycle, and collect() finds the cycles nothing else refers to and
+// breaks them. Its limits:
+// - a Rust closure cannot be looked into, so a function is only seen to refer to
+//   what it registered as captures. cycles running through a closure that did
+//   not, like one a host passed to Lox::define_native, are never found
+// - every collection visits every tracked object that is still alive, so its
+//   cost follows the size of the live heap rather than the garbage
+#[derive(Clone)]
+enum Handle {
+    Scope(Weak<Scope>),
+    Fields(Weak<Fields>),
+    List(Weak<List>),
+    Map(Weak<Map>),
+    Function(Weak<Function>),
+    Closure(Weak<Closure>),
+    Upvalue(Weak<RefCell<Upvalue>>),
+}
+
+impl Handle {
+    fn address(&self) -> usize {
+        match self {
+            Handle::Scope(weak) => return weak.as_ptr() as *const () as usize,
+            Handle::Fields(weak) => return weak.as_ptr() as *const () as usize,
+            Handle::List(weak) => return weak.as_ptr() as *const () as usize,
+            Handle::Map(weak) => return weak.as_ptr() as *const () as usize,
+            Handle::Function(weak) => return weak.as_ptr() as *const () as usize,
+            Handle::Closure(weak) => return weak.as_ptr() as *const () as usize,
+            Handle::Upvalue(weak) => return weak.as_ptr() as *const () as usize,
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Handle::Scope(weak) => return weak.strong_count(),
+            Handle::Fields(weak) => return weak.strong_count(),
+            Handle::List(weak) => return weak.strong_count(),
+            Handle::Map(weak) => return weak.strong_count(),
+            Handle::Function(weak) => return weak.strong_count(),
+            Handle::Closure(weak) => return weak.strong_count(),
+            Handle::Upvalue(weak) => return weak.strong_count(),
+        }
+    }
+}
+
+struct Object {
+    handle: Handle,
+    // what a function closed over, its Rust closure cannot be looked into
+    captures: Vec<Handle>,
+}
+
+struct Heap {
+    objects: Vec<Object>,
+    threshold: usize,
+    allocated: usize,
+    collections: usize,
+    freed: usize,
+}
+
+thread_local! {
+    static HEAP: RefCell<Heap> = const {
+        RefCell::new(Heap {
+            objects: vec![],
+            threshold: INITIAL_THRESHOLD,
+            allocated: 0,
+            collections: 0,
+            freed: 0,
+        })
+    };
+}
+
+pub struct Stats {
+    pub collections: usize,
+    pub allocated: usize,
+    pub freed: usize,
+    pub objects: usize,
+    pub threshold: usize,
+}
+
+pub fn stats() -> Stats {
+    return HEAP.with(|heap| {
+        let heap = heap.borrow();
+        return Stats {
+            collections: heap.collections,
+            allocated: heap.allocated,
+            freed: heap.freed,
+            objects: heap.objects.len(),
+            threshold: heap.threshold,
+        };
+    });
+}
+
+pub fn scope(values: HashMap<String, LiteralValue>) -> Rc<Scope> {
+    let scope = Rc::new(RefCell::new(values));
+    track(Handle::Scope(Rc::downgrade(&scope)), vec![]);
+    return scope;
+}
+
+pub fn fields() -> Rc<Fields> {
+    let fields = Rc::new(RefCell::new(vec![]));
+    track(Handle::Fields(Rc::downgrade(&fields)), vec![]);
+    return fields;
+}
+
+pub fn list(items: Vec<LiteralValue>) -> Rc<List> {
+    let list = Rc::new(RefCell::new(items));
+    track(Handle::List(Rc::downgrade(&list)), vec![]);
+    return list;
+}
+
+pub fn map(entries: Vec<(LiteralValue, LiteralValue)>) -> Rc<Map> {
+    let map = Rc::new(RefCell::new(entries));
+    track(Handle::Map(Rc::downgrade(&map)), vec![]);
+    return map;
+}
+
+// the environment and values must be exactly what the closure captured,
+// every reference listed here is one the collector discounts
+pub fn function(
+    fun: Rc<Function>,
+    environment: Option<&Environment>,
+    values: Vec<&LiteralValue>,
+) -> Rc<Function> {
+    let mut captures = vec![];
+    if let Some(environment) = environment {
+        for scope in environment.scopes() {
+            captures.push(Handle::Scope(Rc::downgrade(&scope)));
+        }
+    }
+    for value in values {
+        references(value, &mut captures);
+    }
+    track(Handle::Function(Rc::downgrade(&fun)), captures);
+    return fun;
+}
+
+pub fn closure(closure: Closure) -> Rc<Closure> {
+    let closure = Rc::new(closure);
+    track(Handle::Closure(Rc::downgrade(&closure)), vec![]);
+    return closure;
+}
+
+pub fn upvalue(upvalue: Upvalue) -> Rc<RefCell<Upvalue>> {
+    let upvalue = Rc::new(RefCell::new(upvalue));
+    track(Handle::Upvalue(Rc::downgrade(&upvalue)), vec![]);
+    return upvalue;
+}
+
+fn track(handle: Handle, captures: Vec<Handle>) {
+    let full = HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.objects.push(Object { handle, captures });
+        heap.allocated += 1;
+        return heap.objects.len() >= heap.threshold;
+    });
+    if full {
+        collect();
+    }
+}
+
+// the objects a value refers to directly, values nested inline like an
+// instance's class are looked through
+fn references(value: &LiteralValue, out: &mut Vec<Handle>) {
+    match value {
+        LiteralValue::Number(_)
+        | LiteralValue::StringLit(_)
+        | LiteralValue::True
+        | LiteralValue::False
+        | LiteralValue::Nil => (),
+        LiteralValue::Callable {
+            name: _,
+            arity: _,
+            fun,
+        } => out.push(Handle::Function(Rc::downgrade(fun))),
+        LiteralValue::LoxClass {
+            name: _,
+            methods,
+            superclass,
+        } => {
+            for method in methods.values() {
+                references(method, out);
+            }
+            if let Some(superclass) = superclass {
+                references(superclass, out);
+            }
+        }
+        LiteralValue::LoxInstance { class, fields } => {
+            references(class, out);
+            out.push(Handle::Fields(Rc::downgrade(fields)));
+        }
+        LiteralValue::List(items) => out.push(Handle::List(Rc::downgrade(items))),
+        LiteralValue::Map(entries) => out.push(Handle::Map(Rc::downgrade(entries))),
+        // modules stay in the import cache, so their globals are never collected
+        LiteralValue::Module { name: _, fields: _ } => (),
+        LiteralValue::Closure { closure, receiver } => {
+            out.push(Handle::Closure(Rc::downgrade(closure)));
+            if let Some(receiver) = receiver {
+                references(receiver, out);
+            }
+        }
+    }
+}
+
+// None when the object is being modified right now, it is in use then
+fn children(object: &Object) -> Option<Vec<Handle>> {
+    let mut out = object.captures.clone();
+    match &object.handle {
+        Handle::Scope(weak) => {
+            for value in weak.upgrade()?.try_borrow().ok()?.values() {
+                references(value, &mut out);
+            }
+        }
+        Handle::Fields(weak) => {
+            for (_, value) in weak.upgrade()?.try_borrow().ok()?.iter() {
+                references(value, &mut out);
+            }
+        }
+        Handle::List(weak) => {
+            for item in weak.upgrade()?.try_borrow().ok()?.iter() {
+                references(item, &mut out);
+            }
+        }
+        Handle::Map(weak) => {
+            for (key, value) in weak.upgrade()?.try_borrow().ok()?.iter() {
+                references(key, &mut out);
+                references(value, &mut out);
+            }
+        }
+        Handle::Function(_) => (),
+        Handle::Closure(weak) => {
+            let closure = weak.upgrade()?;
+            for upvalue in &closure.upvalues {
+                out.push(Handle::Upvalue(Rc::downgrade(upvalue)));
+            }
+            out.push(Handle::Scope(Rc::downgrade(&closure.globals)));
+        }
+        Handle::Upvalue(weak) => {
+            if let Upvalue::Closed(value) = &*weak.upgrade()?.try_borrow().ok()? {
+                references(value, &mut out);
+            }
+        }
+    }
+    return Some(out);
+}
+
+// Trial deletion. There is no root set to walk from, the roots are found by
+// subtracting the references tracked objects hold on each other from their
+// strong counts: whatever is left comes from outside the heap, the environment
+// chain being executed, the vm stack and frames, values held by Rust code in
+// the middle of an operation. Everything reachable from those is kept, the
+// rest can only be reached through cycles and is emptied, which lets Rc free
+// the cycle. Returns how many objects were emptied.
+pub fn collect() -> usize {
+    let garbage = HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.objects
+            .retain(|object| object.handle.strong_count() > 0);
+
+        let index: HashMap<usize, usize> = heap
+            .objects
+            .iter()
+            .enumerate()
+            .map(|(i, object)| (object.handle.address(), i))
+            .collect();
+        let mut external: Vec<usize> = heap
+            .objects
+            .iter()
+            .map(|object| object.handle.strong_count())
+            .collect();
+        let children: Vec<Option<Vec<Handle>>> = heap.objects.iter().map(children).collect();
+
+        for edges in children.iter().flatten() {
+            for edge in edges {
+                if let Some(&target) = index.get(&edge.address()) {
+                    external[target] -= 1;
+                }
+            }
+        }
+
+        let mut marked = vec![false; heap.objects.len()];
+        let mut pending: Vec<usize> = (0..heap.objects.len())
+            .filter(|&i| external[i] > 0 || children[i].is_none())
+            .collect();
+        while let Some(i) = pending.pop() {
+            if marked[i] {
+                continue;
+            }
+            marked[i] = true;
+            for edge in children[i].iter().flatten() {
+                if let Some(&target) = index.get(&edge.address()) {
+                    pending.push(target);
+                }
+            }
+        }
+
+        let mut garbage = vec![];
+        let mut freed = 0;
+        for (object, marked) in heap.objects.iter().zip(marked) {
+            if !marked {
+                freed += 1;
+                garbage.push(object.handle.clone());
+            }
+        }
+
+        heap.collections += 1;
+        heap.freed += freed;
+        heap.threshold = INITIAL_THRESHOLD.max((heap.objects.len() - freed) * 2);
+        return garbage;
+    });
+
+    // emptied outside the heap's borrow, dropping the contents frees the cycles
+    let mut contents = vec![];
+    for handle in &garbage {
+        match handle {
+            Handle::Scope(weak) => {
+                if let Some(Ok(mut scope)) = weak.upgrade().as_ref().map(|s| s.try_borrow_mut()) {
+                    contents.extend(scope.drain().map(|(_, value)| value));
+                }
+            }
+            Handle::Fields(weak) => {
+                if let Some(Ok(mut fields)) = weak.upgrade().as_ref().map(|f| f.try_borrow_mut()) {
+                    contents.extend(fields.drain(..).map(|(_, value)| value));
+                }
+            }
+            Handle::List(weak) => {
+                if let Some(Ok(mut items)) = weak.upgrade().as_ref().map(|l| l.try_borrow_mut()) {
+                    contents.extend(items.drain(..));
+                }
+            }
+            Handle::Map(weak) => {
+                if let Some(Ok(mut entries)) = weak.upgrade().as_ref().map(|m| m.try_borrow_mut()) {
+                    for (key, value) in entries.drain(..) {
+                        contents.push(key);
+                        contents.push(value);
+                    }
+                }
+            }
+            Handle::Upvalue(weak) => {
+                if let Some(Ok(mut upvalue)) = weak.upgrade().as_ref().map(|u| u.try_borrow_mut()) {
+                    if let Upvalue::Closed(value) = &mut *upvalue {
+                        contents.push(std::mem::replace(value, LiteralValue::Nil));
+                    }
+                }
+            }
+            // freed along with the objects they captured
+            Handle::Function(_) | Handle::Closure(_) => (),
+        }
+    }
+    drop(contents);
+
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.objects
+            .retain(|object| object.handle.strong_count() > 0);
+    });
+    return garbage.len();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lox;
+
+    #[test]
+    fn frees_cycles_and_keeps_reachable_objects() {
+        let cycle = list(vec![]);
+        cycle.borrow_mut().push(LiteralValue::List(cycle.clone()));
+        let weak = Rc::downgrade(&cycle);
+        drop(cycle);
+
+        let kept = list(vec![]);
+        let inner = list(vec![LiteralValue::Number(1.0)]);
+        kept.borrow_mut().push(LiteralValue::List(inner));
+        kept.borrow_mut().push(LiteralValue::List(kept.clone()));
+
+        collect();
+        assert!(weak.upgrade().is_none());
+        assert_eq!(kept.borrow().len(), 2);
+        let first = kept.borrow()[0].clone();
+        match first {
+            LiteralValue::List(inner) => assert_eq!(inner.borrow().len(), 1),
+            _ => panic!("expected the inner list"),
+        }
+    }
+
+    #[test]
+    fn frees_closures_stored_in_their_own_scope() {
+        for mut lox in [Lox::new(), Lox::with_vm()] {
+            lox.run(
+                "class Node { init() { this.self = this; } }
+                fun make() {
+                  var f;
+                  fun g() { return f; }
+                  f = g;
+                  return Node();
+                }
+                var kept = make();",
+            )
+            .unwrap();
+            collect();
+            let before = stats().objects;
+            for _ in 0..10 {
+                lox.run("make();").unwrap();
+            }
+            collect();
+
+            assert!(stats().objects <= before);
+            assert!(matches!(
+                lox.eval("kept.self == kept").unwrap(),
+                LiteralValue::True
+            ));
+        }
+    }
+}
@@ -1,19 +1,40 @@
+use crate::debugger;
 use crate::environment;
+use crate::error::RuntimeError;
 use crate::expr;
+use crate::gc;
+use crate::limits;
+use crate::module;
+use crate::output;
+use crate::profiler;
 use crate::scanner;
 use crate::stmt;
 use std::collections::HashMap;
 use std::rc::Rc;
 
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionKind {
+    Function,
+    Method,
+    Initializer,
+}
+
+// how a statement finished executing, anything other than Normal unwinds
+// through the enclosing statements until something handles it
+pub enum ControlFlow {
+    Normal,
+    Return(expr::LiteralValue),
+    Break,
+    Continue,
+}
+
 pub struct Interpreter {
-    pub specials: HashMap<String, expr::LiteralValue>,
     pub environment: environment::Environment,
 }
 
 impl Interpreter {
     pub fn new() -> Self {
         return Self {
-            specials: HashMap::new(),
             environment: environment::Environment::new(HashMap::new()),
         };
     }
@@ -22,34 +43,45 @@ impl Interpreter {
         self.environment.resolve(locals);
     }
 
+    pub fn globals(&self) -> HashMap<String, expr::LiteralValue> {
+        return self.environment.values();
+    }
+
+    pub fn global_scope(&self) -> Rc<gc::Scope> {
+        return self.environment.scope();
+    }
+
     fn for_closure(parent: environment::Environment) -> Self {
         let environment = parent.enclose();
 
-        return Self {
-            specials: HashMap::new(),
-            environment,
-        };
+        return Self { environment };
     }
 
     pub fn for_anon(parent: environment::Environment) -> Self {
         let env = parent.enclose();
-        return Self {
-            specials: HashMap::new(),
-            environment: env,
-        };
+        return Self { environment: env };
     }
 
-    pub fn interpret(&mut self, stmts: Vec<&stmt::Stmt>) -> Result<(), String> {
+    pub fn interpret(&mut self, stmts: Vec<&stmt::Stmt>) -> Result<ControlFlow, RuntimeError> {
         for stmt in stmts {
+            debugger::on_statement(stmt, &self.environment)?;
+            profiler::on_statement(stmt, &self.environment);
+            if !limits::step() {
+                return Err(limits::steps_exceeded());
+            }
             match stmt {
                 stmt::Stmt::Expression { expression } => {
                     expression.evaluate(self.environment.clone())?;
                 }
                 stmt::Stmt::Print { expression } => {
                     let value = expression.evaluate(self.environment.clone())?;
-                    println!("{}", value.to_string());
+                    output::print_line(&value.display())?;
                 }
-                stmt::Stmt::Var { name, initializer } => {
+                stmt::Stmt::Var {
+                    name,
+                    annotation: _,
+                    initializer,
+                } => {
                     let value = initializer.evaluate(self.environment.clone())?;
 
                     self.environment.define(name.lexeme.clone(), value);
@@ -63,34 +95,80 @@ impl Interpreter {
                         self.interpret((*statements).iter().map(|b| b.as_ref()).collect());
                     self.environment = old_environment;
 
-                    block_result?; // compiler complains if return keyword is used here
+                    match block_result? {
+                        ControlFlow::Normal => (),
+                        flow => return Ok(flow),
+                    }
                 }
-                stmt::Stmt::Class { name, methods } => {
+                stmt::Stmt::Class {
+                    name,
+                    superclass,
+                    methods,
+                } => {
+                    let superclass_value = match superclass {
+                        Some(superclass) => {
+                            let value = superclass.evaluate(self.environment.clone())?;
+                            if let expr::LiteralValue::LoxClass {
+                                name: _,
+                                methods: _,
+                                superclass: _,
+                            } = value
+                            {
+                                Some(Box::new(value))
+                            } else {
+                                return Err(RuntimeError::at(
+                                    name,
+                                    format!(
+                                        "superclass of {} must be a class, got {}",
+                                        name.lexeme,
+                                        value.to_type()
+                                    ),
+                                ));
+                            }
+                        }
+                        None => None,
+                    };
+
                     self.environment
                         .define(name.lexeme.clone(), expr::LiteralValue::Nil);
 
+                    let old_environment = self.environment.clone();
+                    if let Some(superclass) = &superclass_value {
+                        self.environment = self.environment.enclose();
+                        self.environment
+                            .define("super".to_string(), superclass.as_ref().clone());
+                    }
+
                     let mut methods_map = HashMap::new();
                     for method in methods {
                         if let stmt::Stmt::Function {
                             name,
                             params: _,
+                            param_annotations: _,
+                            return_annotation: _,
                             body: _,
                         } = method.as_ref()
                         {
-                            let function = self.make_function(method);
+                            let kind = if name.lexeme == "init" {
+                                FunctionKind::Initializer
+                            } else {
+                                FunctionKind::Method
+                            };
+                            let function = self.make_function(method, kind);
                             methods_map.insert(name.lexeme.clone(), function);
                         } else {
                             panic!("class method expects function type");
                         }
                     }
 
+                    self.environment = old_environment;
+
                     let klass = expr::LiteralValue::LoxClass {
                         name: name.lexeme.clone(),
                         methods: methods_map,
+                        superclass: superclass_value,
                     };
-                    if !self.environment.assign_global(&name.lexeme, klass) {
-                        return Err(format!("class definition failed for {}", name.lexeme));
-                    }
+                    self.environment.define(name.lexeme.clone(), klass);
                 }
                 stmt::Stmt::IfStmt {
                     predicate,
@@ -98,30 +176,90 @@ impl Interpreter {
                     els,
                 } => {
                     let truth_value = predicate.evaluate(self.environment.clone())?;
-                    if truth_value.is_truthy() == expr::LiteralValue::True {
+                    let truth_value = truth(predicate, &truth_value)?;
+                    let flow = if truth_value == expr::LiteralValue::True {
                         let statements = vec![then.as_ref()];
-                        self.interpret(statements)?;
+                        self.interpret(statements)?
                     } else if let Some(els_stmt) = els {
                         let statements = vec![els_stmt.as_ref()];
-                        self.interpret(statements)?;
+                        self.interpret(statements)?
+                    } else {
+                        ControlFlow::Normal
+                    };
+
+                    match flow {
+                        ControlFlow::Normal => (),
+                        flow => return Ok(flow),
                     }
                 }
-                stmt::Stmt::WhileStmt { condition, body } => {
+                stmt::Stmt::WhileStmt {
+                    condition,
+                    body,
+                    increment,
+                } => {
                     let mut flag = condition.evaluate(self.environment.clone())?;
-                    while flag.is_truthy() == expr::LiteralValue::True {
+                    while truth(condition, &flag)? == expr::LiteralValue::True {
                         let statements = vec![body.as_ref()];
-                        self.interpret(statements)?;
+                        match self.interpret(statements)? {
+                            ControlFlow::Normal | ControlFlow::Continue => (),
+                            ControlFlow::Break => break,
+                            flow => return Ok(flow),
+                        }
+                        if let Some(increment) = increment {
+                            increment.evaluate(self.environment.clone())?;
+                        }
                         flag = condition.evaluate(self.environment.clone())?;
                     }
                 }
+                stmt::Stmt::Throw { keyword, value } => {
+                    let value = value.evaluate(self.environment.clone())?;
+                    return Err(RuntimeError::thrown(keyword, value));
+                }
+                stmt::Stmt::Try {
+                    keyword: _,
+                    body,
+                    catch,
+                    finally,
+                } => {
+                    let mut result = self.interpret(vec![body.as_ref()]);
+
+                    if let (Err(err), Some((name, handler))) = (&result, catch) {
+                        let old_environment = self.environment.clone();
+                        self.environment = self.environment.enclose();
+                        self.environment.define(name.lexeme.clone(), err.value());
+                        result = self.interpret(vec![handler.as_ref()]);
+                        self.environment = old_environment;
+                    }
+
+                    // leaving the finally block early replaces whatever the try was doing
+                    if let Some(finally) = finally {
+                        match self.interpret(vec![finally.as_ref()])? {
+                            ControlFlow::Normal => (),
+                            flow => return Ok(flow),
+                        }
+                    }
+
+                    match result? {
+                        ControlFlow::Normal => (),
+                        flow => return Ok(flow),
+                    }
+                }
+                stmt::Stmt::Break { keyword: _ } => return Ok(ControlFlow::Break),
+                stmt::Stmt::Continue { keyword: _ } => return Ok(ControlFlow::Continue),
                 stmt::Stmt::Function {
                     name,
                     params: _,
+                    param_annotations: _,
+                    return_annotation: _,
                     body: _,
                 } => {
-                    let callable = self.make_function(stmt);
+                    let callable = self.make_function(stmt, FunctionKind::Function);
                     self.environment.define(name.lexeme.clone(), callable);
                 }
+                stmt::Stmt::Import { path, name } => {
+                    let module = module::import(path, false)?;
+                    self.environment.define(name.lexeme.clone(), module);
+                }
                 stmt::Stmt::ReturnStmt { keyword: _, value } => {
                     let eval_val;
                     if let Some(value) = value {
@@ -129,28 +267,47 @@ impl Interpreter {
                     } else {
                         eval_val = expr::LiteralValue::Nil;
                     }
-                    self.specials.insert("return".to_string(), eval_val);
+                    return Ok(ControlFlow::Return(eval_val));
                 }
             };
         }
 
-        return Ok(());
+        return Ok(ControlFlow::Normal);
     }
 
-    fn make_function(&self, fn_stmt: &stmt::Stmt) -> expr::LiteralValue {
-        if let stmt::Stmt::Function { name, params, body } = fn_stmt {
+    fn make_function(&self, fn_stmt: &stmt::Stmt, kind: FunctionKind) -> expr::LiteralValue {
+        if let stmt::Stmt::Function {
+            name,
+            params,
+            param_annotations: _,
+            return_annotation: _,
+            body,
+        } = fn_stmt
+        {
             let arity = params.len();
 
             let params: Vec<scanner::Token> = params.iter().map(|t| (*t).clone()).collect();
 
             let body: Vec<Box<stmt::Stmt>> = body.iter().map(|b| (*b).clone()).collect();
 
-            let name_clone = name.lexeme.clone();
-
             let parent_env = self.environment.clone();
             // let parent_locals = self.locals.clone();
             let fun_impl = move |args: &Vec<expr::LiteralValue>| {
-                let mut clos_int = Interpreter::for_closure(parent_env.clone());
+                // methods receive their instance as the first argument, see LiteralValue::bind
+                let (this, args) = if kind == FunctionKind::Function {
+                    (None, &args[..])
+                } else {
+                    (Some(args[0].clone()), &args[1..])
+                };
+
+                let mut clos_int = match &this {
+                    Some(this) => {
+                        let this_env = parent_env.enclose();
+                        this_env.define("this".to_string(), this.clone());
+                        Interpreter::for_closure(this_env)
+                    }
+                    None => Interpreter::for_closure(parent_env.clone()),
+                };
 
                 for (i, arg) in args.iter().enumerate() {
                     clos_int
@@ -158,26 +315,39 @@ impl Interpreter {
                         .define(params[i].lexeme.clone(), (*arg).clone());
                 }
 
-                for i in 0..(body.len()) {
-                    clos_int
-                        .interpret(vec![body[i].as_ref()])
-                        .expect(&format!("evaluating failed inside {}", name_clone));
+                let flow = clos_int.interpret(body.iter().map(|b| b.as_ref()).collect())?;
 
-                    if let Some(value) = clos_int.specials.get("return") {
-                        return value.clone();
-                    }
+                if let (FunctionKind::Initializer, Some(this)) = (kind, this) {
+                    return Ok(this);
                 }
 
-                return expr::LiteralValue::Nil;
+                // the resolver keeps break and continue from leaving a function body
+                match flow {
+                    ControlFlow::Return(value) => Ok(value),
+                    ControlFlow::Normal | ControlFlow::Break | ControlFlow::Continue => {
+                        Ok(expr::LiteralValue::Nil)
+                    }
+                }
             };
 
             return expr::LiteralValue::Callable {
                 name: name.lexeme.clone(),
                 arity,
-                fun: Rc::new(fun_impl),
+                fun: gc::function(Rc::new(fun_impl), Some(&self.environment), vec![]),
             };
         } else {
             panic!("trie to make a function from a non-function statement");
         }
     }
 }
+
+// the truth of a condition's value, located at the condition when it has none
+fn truth(
+    condition: &expr::Expr,
+    value: &expr::LiteralValue,
+) -> Result<expr::LiteralValue, RuntimeError> {
+    return value.is_truthy().map_err(|msg| match condition.token() {
+        Some(token) => RuntimeError::at(token, msg),
+        None => RuntimeError::new(msg),
+    });
+}
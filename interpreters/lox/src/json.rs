@@ -0,0 +1,327 @@
+// a small JSON reader and writer, enough for the language server's messages
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    // fields keep their order so output is predictable
+    Object(Vec<(String, Json)>),
+}
+
+static NULL: Json = Json::Null;
+
+pub fn object(fields: Vec<(&str, Json)>) -> Json {
+    return Json::Object(
+        fields
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    );
+}
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut reader = Reader {
+            chars: text.chars().collect(),
+            current: 0,
+        };
+        let value = reader.value()?;
+        reader.skip_whitespace();
+        if reader.current < reader.chars.len() {
+            return Err(format!("unexpected data at offset {}", reader.current));
+        }
+        return Ok(value);
+    }
+
+    // a missing field reads as null, so lookups can be chained
+    pub fn get(&self, key: &str) -> &Json {
+        if let Json::Object(fields) = self {
+            for (name, value) in fields {
+                if name == key {
+                    return value;
+                }
+            }
+        }
+        return &NULL;
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => return Some(s),
+            _ => return None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(x) => return Some(*x),
+            _ => return None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Json>> {
+        match self {
+            Json::Array(items) => return Some(items),
+            _ => return None,
+        }
+    }
+}
+
+impl std::fmt::Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(x) if x.fract() == 0.0 && x.abs() < 1e15 => write!(f, "{}", *x as i64),
+            Json::Number(x) => write!(f, "{}", x),
+            Json::String(s) => write!(f, "{}", string(s)),
+            Json::Array(items) => {
+                let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+                write!(f, "[{}]", items.join(","))
+            }
+            Json::Object(fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(key, value)| format!("{}:{}", string(key), value))
+                    .collect();
+                write!(f, "{{{}}}", fields.join(","))
+            }
+        }
+    }
+}
+
+// a JSON string literal, quotes included
+pub fn string(s: &str) -> String {
+    let mut out = "\"".to_string();
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    return out;
+}
+
+struct Reader {
+    chars: Vec<char>,
+    current: usize,
+}
+
+impl Reader {
+    fn peek(&self) -> Option<char> {
+        return self.chars.get(self.current).copied();
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.current += 1;
+        return c;
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+            self.current += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.advance() {
+            Some(c) if c == expected => return Ok(()),
+            _ => {
+                return Err(format!(
+                    "expected '{}' at offset {}",
+                    expected,
+                    self.current - 1
+                ))
+            }
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        for expected in word.chars() {
+            if self.advance() != Some(expected) {
+                return Err(format!("invalid literal at offset {}", self.current - 1));
+            }
+        }
+        return Ok(value);
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('n') => return self.keyword("null", Json::Null),
+            Some('t') => return self.keyword("true", Json::Bool(true)),
+            Some('f') => return self.keyword("false", Json::Bool(false)),
+            Some('"') => return Ok(Json::String(self.string()?)),
+            Some('[') => {
+                self.current += 1;
+                let mut items = vec![];
+                self.skip_whitespace();
+                if self.peek() == Some(']') {
+                    self.current += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.advance() {
+                        Some(',') => (),
+                        Some(']') => return Ok(Json::Array(items)),
+                        _ => {
+                            return Err(format!(
+                                "expected ',' or ']' at offset {}",
+                                self.current - 1
+                            ))
+                        }
+                    }
+                }
+            }
+            Some('{') => {
+                self.current += 1;
+                let mut fields = vec![];
+                self.skip_whitespace();
+                if self.peek() == Some('}') {
+                    self.current += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.expect(':')?;
+                    fields.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.advance() {
+                        Some(',') => (),
+                        Some('}') => return Ok(Json::Object(fields)),
+                        _ => {
+                            return Err(format!(
+                                "expected ',' or '}}' at offset {}",
+                                self.current - 1
+                            ))
+                        }
+                    }
+                }
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => return self.number(),
+            Some(c) => return Err(format!("unexpected '{}' at offset {}", c, self.current)),
+            None => return Err("unexpected end of input".to_string()),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.current;
+        while let Some(c) = self.peek() {
+            if c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E') {
+                self.current += 1;
+            } else {
+                break;
+            }
+        }
+        let text: String = self.chars[start..self.current].iter().collect();
+        return text
+            .parse::<f64>()
+            .map(Json::Number)
+            .map_err(|_| format!("invalid number '{}' at offset {}", text, start));
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            match self.advance() {
+                None => return Err("unterminated string".to_string()),
+                Some('"') => return Ok(out),
+                Some('\\') => match self.advance() {
+                    Some('"') => out.push('"'),
+                    Some('\\') => out.push('\\'),
+                    Some('/') => out.push('/'),
+                    Some('b') => out.push('\u{8}'),
+                    Some('f') => out.push('\u{c}'),
+                    Some('n') => out.push('\n'),
+                    Some('r') => out.push('\r'),
+                    Some('t') => out.push('\t'),
+                    Some('u') => {
+                        let high = self.hex()?;
+                        // characters outside the basic plane come as a surrogate pair
+                        let code = if (0xD800..0xDC00).contains(&high) {
+                            self.expect('\\')?;
+                            self.expect('u')?;
+                            let low = self.hex()?;
+                            0x10000 + ((high - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF)
+                        } else {
+                            high
+                        };
+                        out.push(char::from_u32(code).unwrap_or('\u{FFFD}'));
+                    }
+                    _ => return Err(format!("invalid escape at offset {}", self.current - 1)),
+                },
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    fn hex(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self.advance().and_then(|c| c.to_digit(16)).ok_or(format!(
+                "invalid unicode escape at offset {}",
+                self.current - 1
+            ))?;
+            code = code * 16 + digit;
+        }
+        return Ok(code);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nested_values() {
+        let value =
+            Json::parse(r#" {"a": [1, -2.5e1, true, null], "b": {"c": "x\"é😀"}} "#).unwrap();
+        assert_eq!(
+            value.get("a"),
+            &Json::Array(vec![
+                Json::Number(1.0),
+                Json::Number(-25.0),
+                Json::Bool(true),
+                Json::Null
+            ])
+        );
+        assert_eq!(value.get("b").get("c").as_str(), Some("x\"é😀"));
+        assert_eq!(value.get("missing").get("deeper"), &Json::Null);
+    }
+
+    #[test]
+    fn writes_compact_json() {
+        let value = object(vec![
+            ("id", Json::Number(3.0)),
+            ("text", Json::String("a\nb".to_string())),
+            ("list", Json::Array(vec![Json::Number(0.5), Json::Null])),
+        ]);
+        assert_eq!(
+            value.to_string(),
+            r#"{"id":3,"text":"a\nb","list":[0.5,null]}"#
+        );
+        assert_eq!(Json::parse(&value.to_string()).unwrap(), value);
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        assert!(Json::parse("{\"a\" 1}").is_err());
+        assert!(Json::parse("[1,]").is_err());
+        assert!(Json::parse("\"open").is_err());
+        assert!(Json::parse("1 2").is_err());
+    }
+}
@@ -0,0 +1,163 @@
+mod checker;
+mod chunk;
+mod compiler;
+pub mod debugger;
+pub mod diagnostic;
+mod embed;
+mod environment;
+pub mod error;
+pub mod expr;
+pub mod formatter;
+mod gc;
+mod interpreter;
+mod json;
+mod limits;
+pub mod lsp;
+pub mod module;
+mod natives;
+mod optimizer;
+mod output;
+mod parser;
+pub mod profiler;
+pub mod repl;
+mod resolver;
+mod scanner;
+mod stmt;
+mod tests;
+mod vm;
+
+pub use diagnostic::{Diagnostic, DiagnosticKind};
+pub use embed::Lox;
+pub use error::RuntimeError;
+pub use expr::LiteralValue;
+pub use limits::Limits;
+use std::fs;
+
+pub fn report(diagnostics: Vec<Diagnostic>, source: &str, file: &str, json: bool) {
+    for diagnostic in diagnostics {
+        let diagnostic = diagnostic.with_file(file);
+        if json {
+            eprintln!("{}", diagnostic.to_json());
+        } else {
+            eprintln!("{}", diagnostic.render(source));
+        }
+    }
+}
+
+// the tree-walking interpreter is the default, the bytecode vm is opt in with --vm
+enum Backend {
+    Tree(interpreter::Interpreter),
+    Vm(vm::VM),
+}
+
+impl Backend {
+    fn new(use_vm: bool) -> Self {
+        if use_vm {
+            return Backend::Vm(vm::VM::new());
+        }
+        return Backend::Tree(interpreter::Interpreter::new());
+    }
+
+    fn globals(&self) -> std::collections::HashMap<String, expr::LiteralValue> {
+        match self {
+            Backend::Tree(interp) => return interp.globals(),
+            Backend::Vm(vm) => return vm.globals(),
+        }
+    }
+
+    fn define(&mut self, name: &str, value: expr::LiteralValue) {
+        match self {
+            Backend::Tree(interp) => interp.environment.define(name.to_string(), value),
+            Backend::Vm(vm) => vm.define_global(name, value),
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<expr::LiteralValue> {
+        match self {
+            Backend::Tree(interp) => return interp.environment.lookup(name),
+            Backend::Vm(vm) => return vm.global(name),
+        }
+    }
+
+    fn remove(&mut self, name: &str) -> Option<expr::LiteralValue> {
+        match self {
+            Backend::Tree(interp) => return interp.environment.remove(name),
+            Backend::Vm(vm) => return vm.remove_global(name),
+        }
+    }
+}
+
+pub fn run_file(path: &str, use_vm: bool) -> Result<(), Vec<Diagnostic>> {
+    match fs::read_to_string(path) {
+        Err(msg) => {
+            return Err(vec![Diagnostic::new(
+                DiagnosticKind::Runtime,
+                msg.to_string(),
+                None,
+            )])
+        }
+        Ok(contents) => return run_string(&contents, use_vm),
+    }
+}
+
+pub fn run_string(contents: &str, use_vm: bool) -> Result<(), Vec<Diagnostic>> {
+    let mut backend = Backend::new(use_vm);
+    return run(&mut backend, contents);
+}
+
+fn run(backend: &mut Backend, contents: &str) -> Result<(), Vec<Diagnostic>> {
+    let (statements, _) = parse(contents, 0)?;
+    return execute(backend, statements);
+}
+
+// the statements as they run after optimizing, one s-expression per line
+pub fn dump_ast(contents: &str) -> Result<String, Vec<Diagnostic>> {
+    let (statements, _) = parse(contents, 0)?;
+    return Ok(optimizer::optimize(statements)
+        .iter()
+        .map(|statement| format!("{}\n", statement.tostring()))
+        .collect());
+}
+
+// the lint warnings for a program, without running it
+pub fn check(contents: &str) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+    let (statements, _) = parse(contents, 0)?;
+    let warnings = resolver::Resolver::new()
+        .check(&statements.iter().collect())
+        .map_err(|diagnostic| vec![diagnostic])?;
+    checker::Checker::new().check(&statements.iter().collect())?;
+    return Ok(warnings);
+}
+
+// returns the statements and the first expression id left unused
+fn parse(contents: &str, first_id: usize) -> Result<(Vec<stmt::Stmt>, usize), Vec<Diagnostic>> {
+    let mut scanner = scanner::Scanner::new(contents);
+    let tokens = scanner.scan_tokens()?;
+
+    let mut parser = parser::Parser::new(tokens).starting_at(first_id);
+    let statements = parser.parse()?;
+    return Ok((statements, parser.next_id()));
+}
+
+fn execute(backend: &mut Backend, statements: Vec<stmt::Stmt>) -> Result<(), Vec<Diagnostic>> {
+    let statements = optimizer::optimize(statements);
+    let resolver = resolver::Resolver::new();
+    let locals = resolver
+        .resolve(&statements.iter().collect())
+        .map_err(|diagnostic| vec![diagnostic])?;
+    checker::Checker::new().check(&statements.iter().collect())?;
+
+    match backend {
+        Backend::Tree(interp) => {
+            interp.resolve(locals);
+            interp
+                .interpret(statements.iter().collect())
+                .map_err(|err| vec![Diagnostic::from(err)])?;
+        }
+        Backend::Vm(vm) => {
+            vm.interpret(&statements.iter().collect())
+                .map_err(|diagnostic| vec![diagnostic])?;
+        }
+    }
+    return Ok(());
+}
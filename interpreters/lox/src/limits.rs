@@ -0,0 +1,120 @@
+use crate::error::RuntimeError;
+use crate::expr::LiteralValue;
+use std::cell::Cell;
+
+// what one list element, map entry half or instance is charged, the strings
+// they hold are charged when they are built
+pub const VALUE_BYTES: usize = std::mem::size_of::<LiteralValue>();
+
+// the native stack a limited run may use on the tree-walker, which needs far
+// more of it per call than a vm frame. reaching it is a call depth error
+// whatever max_depth is, so a deep limit cannot crash the host
+const STACK_BYTES: usize = 1 << 20;
+
+// caps for running scripts that are not trusted, none means unlimited. each
+// one is a runtime error of its own that try/catch sees like any other, but
+// the budget is not given back, so a handler cannot use it to keep running
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Limits {
+    // statements on the tree-walker, instructions on the vm
+    pub max_steps: Option<usize>,
+    // calls active at the same time
+    pub max_depth: Option<usize>,
+    // a rough count of the bytes in strings built at runtime and in new
+    // instances, lists and maps, memory that is freed again still counts
+    pub max_memory: Option<usize>,
+}
+
+thread_local! {
+    // plain cells, the vm checks them on every instruction
+    static LIMITS: Cell<Limits> = const {
+        Cell::new(Limits {
+            max_steps: None,
+            max_depth: None,
+            max_memory: None,
+        })
+    };
+    static STEPS_LEFT: Cell<Option<usize>> = const { Cell::new(None) };
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+    static MEMORY: Cell<usize> = const { Cell::new(0) };
+    // where the native stack was when the limits were installed
+    static STACK_BASE: Cell<usize> = const { Cell::new(0) };
+}
+
+// installs the limits with a fresh budget and hands back the ones they replace
+pub fn install(limits: Limits) -> Limits {
+    STEPS_LEFT.with(|steps| steps.set(limits.max_steps));
+    DEPTH.with(|depth| depth.set(0));
+    MEMORY.with(|memory| memory.set(0));
+    STACK_BASE.with(|base| base.set(stack_position()));
+    return LIMITS.with(|installed| installed.replace(limits));
+}
+
+// takes a step from the budget, false when none are left
+pub fn step() -> bool {
+    return STEPS_LEFT.with(|steps| match steps.get() {
+        None => return true,
+        Some(0) => return false,
+        Some(left) => {
+            steps.set(Some(left - 1));
+            return true;
+        }
+    });
+}
+
+pub fn steps_exceeded() -> RuntimeError {
+    return RuntimeError::new("step limit exceeded");
+}
+
+// a call on the tree-walker, every successful enter is matched by an exit. the
+// stack is guarded under any limits, a step limit alone must not let deep
+// recursion crash the host
+pub fn enter() -> Result<(), RuntimeError> {
+    if LIMITS.with(|limits| limits.get() == Limits::default()) {
+        return Ok(());
+    }
+    let depth = DEPTH.with(|depth| depth.get()) + 1;
+    check_depth(depth)?;
+    let stack_used = STACK_BASE.with(|base| base.get().saturating_sub(stack_position()));
+    if stack_used > STACK_BYTES {
+        return Err(depth_exceeded());
+    }
+    DEPTH.with(|current| current.set(depth));
+    return Ok(());
+}
+
+pub fn exit() {
+    DEPTH.with(|depth| depth.set(depth.get().saturating_sub(1)));
+}
+
+pub fn allocate(bytes: usize) -> Result<(), RuntimeError> {
+    let max = match LIMITS.with(|limits| limits.get().max_memory) {
+        Some(max) => max,
+        None => return Ok(()),
+    };
+    let used = MEMORY.with(|memory| memory.get()) + bytes;
+    MEMORY.with(|memory| memory.set(used));
+    if used > max {
+        return Err(RuntimeError::new("memory limit exceeded"));
+    }
+    return Ok(());
+}
+
+// the vm keeps its own frames, it asks before pushing one
+pub fn check_depth(depth: usize) -> Result<(), RuntimeError> {
+    match LIMITS.with(|limits| limits.get().max_depth) {
+        Some(max) if depth > max => return Err(depth_exceeded()),
+        _ => return Ok(()),
+    }
+}
+
+fn depth_exceeded() -> RuntimeError {
+    return RuntimeError::new("call depth limit exceeded");
+}
+
+// the stack grows down on every platform we run on, so the distance from the
+// base is what a run has used
+fn stack_position() -> usize {
+    let marker = 0u8;
+    return std::hint::black_box(&marker) as *const u8 as usize;
+}
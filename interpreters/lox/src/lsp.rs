@@ -0,0 +1,709 @@
+use crate::checker::Checker;
+use crate::diagnostic::{Diagnostic, DiagnosticKind};
+use crate::environment;
+use crate::expr::LiteralValue;
+use crate::json::{object, Json};
+use crate::parser::Parser;
+use crate::resolver::{Reference, Resolver};
+use crate::scanner::{Scanner, Token, TokenType};
+use crate::stmt::{annotated, Stmt};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+// LSP symbol kinds
+const SYMBOL_MODULE: f64 = 2.0;
+const SYMBOL_CLASS: f64 = 5.0;
+const SYMBOL_METHOD: f64 = 6.0;
+const SYMBOL_CONSTRUCTOR: f64 = 9.0;
+const SYMBOL_FUNCTION: f64 = 12.0;
+const SYMBOL_VARIABLE: f64 = 13.0;
+
+const METHOD_NOT_FOUND: f64 = -32601.0;
+
+pub fn run() -> Result<(), String> {
+    return serve(&mut io::stdin().lock(), &mut io::stdout());
+}
+
+// answers messages until the client sends exit or closes the input
+pub fn serve(input: &mut dyn BufRead, output: &mut dyn Write) -> Result<(), String> {
+    let mut server = Server {
+        documents: HashMap::new(),
+        exited: false,
+    };
+
+    while let Some(body) = read_message(input)? {
+        let message = Json::parse(&body)?;
+        for reply in server.handle(&message) {
+            write_message(output, &reply)?;
+        }
+        if server.exited {
+            break;
+        }
+    }
+    return Ok(());
+}
+
+fn read_message(input: &mut dyn BufRead) -> Result<Option<String>, String> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line).map_err(|err| err.to_string())? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = Some(
+                value
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|err| format!("invalid Content-Length: {}", err))?,
+            );
+        }
+    }
+
+    let length = length.ok_or("message without a Content-Length header")?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body).map_err(|err| err.to_string())?;
+    return String::from_utf8(body)
+        .map(Some)
+        .map_err(|err| err.to_string());
+}
+
+fn write_message(output: &mut dyn Write, message: &Json) -> Result<(), String> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)
+        .and_then(|_| output.flush())
+        .map_err(|err| err.to_string())?;
+    return Ok(());
+}
+
+struct Server {
+    // open documents by uri, always the full text
+    documents: HashMap<String, String>,
+    exited: bool,
+}
+
+impl Server {
+    fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let id = message.get("id").clone();
+        let params = message.get("params");
+        let uri = params
+            .get("textDocument")
+            .get("uri")
+            .as_str()
+            .unwrap_or("")
+            .to_string();
+
+        match message.get("method").as_str().unwrap_or("") {
+            "initialize" => {
+                let capabilities = object(vec![
+                    ("textDocumentSync", Json::Number(1.0)),
+                    ("definitionProvider", Json::Bool(true)),
+                    ("hoverProvider", Json::Bool(true)),
+                    ("documentSymbolProvider", Json::Bool(true)),
+                ]);
+                let result = object(vec![
+                    ("capabilities", capabilities),
+                    (
+                        "serverInfo",
+                        object(vec![("name", Json::String("lox".to_string()))]),
+                    ),
+                ]);
+                return vec![response(id, result)];
+            }
+            "shutdown" => return vec![response(id, Json::Null)],
+            "exit" => {
+                self.exited = true;
+                return vec![];
+            }
+            "textDocument/didOpen" => {
+                let text = params.get("textDocument").get("text");
+                self.documents
+                    .insert(uri.clone(), text.as_str().unwrap_or("").to_string());
+                return vec![self.publish_diagnostics(&uri)];
+            }
+            "textDocument/didChange" => {
+                // full sync, so the last change holds the whole document
+                let changes = params.get("contentChanges").as_array();
+                if let Some(text) = changes.and_then(|c| c.last()).map(|c| c.get("text")) {
+                    self.documents
+                        .insert(uri.clone(), text.as_str().unwrap_or("").to_string());
+                }
+                return vec![self.publish_diagnostics(&uri)];
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                return vec![notification(
+                    "textDocument/publishDiagnostics",
+                    object(vec![
+                        ("uri", Json::String(uri)),
+                        ("diagnostics", Json::Array(vec![])),
+                    ]),
+                )];
+            }
+            "textDocument/definition" => {
+                let result = match self.reference_at(&uri, params) {
+                    Some(reference) => object(vec![
+                        ("uri", Json::String(uri.clone())),
+                        (
+                            "range",
+                            token_range(self.text(&uri), &reference.declaration),
+                        ),
+                    ]),
+                    None => Json::Null,
+                };
+                return vec![response(id, result)];
+            }
+            "textDocument/hover" => {
+                let result = match self.hover(&uri, params) {
+                    Some((range, text)) => object(vec![
+                        (
+                            "contents",
+                            object(vec![
+                                ("kind", Json::String("markdown".to_string())),
+                                ("value", Json::String(text)),
+                            ]),
+                        ),
+                        ("range", range),
+                    ]),
+                    None => Json::Null,
+                };
+                return vec![response(id, result)];
+            }
+            "textDocument/documentSymbol" => {
+                let symbols = match self.statements(&uri) {
+                    Some(statements) => statements
+                        .iter()
+                        .filter_map(|statement| symbol(self.text(&uri), statement))
+                        .collect(),
+                    None => vec![],
+                };
+                return vec![response(id, Json::Array(symbols))];
+            }
+            _ if id != Json::Null => {
+                let error = object(vec![
+                    ("code", Json::Number(METHOD_NOT_FOUND)),
+                    ("message", Json::String("method not found".to_string())),
+                ]);
+                return vec![object(vec![
+                    ("jsonrpc", Json::String("2.0".to_string())),
+                    ("id", id),
+                    ("error", error),
+                ])];
+            }
+            // notifications we do not handle are ignored, as the protocol asks
+            _ => return vec![],
+        }
+    }
+
+    fn text(&self, uri: &str) -> &str {
+        return self.documents.get(uri).map(String::as_str).unwrap_or("");
+    }
+
+    fn publish_diagnostics(&self, uri: &str) -> Json {
+        let text = self.text(uri);
+        let diagnostics = match analyze(text) {
+            Ok(warnings) => warnings,
+            Err(errors) => errors,
+        };
+
+        return notification(
+            "textDocument/publishDiagnostics",
+            object(vec![
+                ("uri", Json::String(uri.to_string())),
+                (
+                    "diagnostics",
+                    Json::Array(
+                        diagnostics
+                            .iter()
+                            .map(|diagnostic| lsp_diagnostic(text, diagnostic))
+                            .collect(),
+                    ),
+                ),
+            ]),
+        );
+    }
+
+    fn statements(&self, uri: &str) -> Option<Vec<Stmt>> {
+        let text = self.documents.get(uri)?;
+        let tokens = Scanner::new(text).scan_tokens().ok()?;
+        return Parser::new(tokens).parse().ok();
+    }
+
+    fn reference_at(&self, uri: &str, params: &Json) -> Option<Reference> {
+        let statements = self.statements(uri)?;
+        let references = Resolver::new()
+            .references(&statements.iter().collect())
+            .ok()?;
+
+        let offset = position(self.text(uri), params)?;
+        return references
+            .into_iter()
+            .find(|reference| covers(&reference.name, offset));
+    }
+
+    fn hover(&self, uri: &str, params: &Json) -> Option<(Json, String)> {
+        if let Some(reference) = self.reference_at(uri, params) {
+            let mut details = HashMap::new();
+            describe(&self.statements(uri)?.iter().collect(), &mut details);
+            let detail = details
+                .remove(&reference.declaration.offset)
+                .unwrap_or(code(&reference.declaration.lexeme));
+            return Some((token_range(self.text(uri), &reference.name), detail));
+        }
+
+        // natives have no declaration in the document
+        let offset = position(self.text(uri), params)?;
+        let tokens = Scanner::new(self.documents.get(uri)?).scan_tokens().ok()?;
+        let token = tokens
+            .into_iter()
+            .find(|token| token.token_type == TokenType::Identifier && covers(token, offset))?;
+        match environment::get_globals().borrow().get(&token.lexeme) {
+            Some(LiteralValue::Callable {
+                name,
+                arity,
+                fun: _,
+            }) => {
+                let detail = format!("{}\narity {}", code(&format!("native fun {}", name)), arity);
+                return Some((token_range(self.text(uri), &token), detail));
+            }
+            _ => return None,
+        }
+    }
+}
+
+// the byte offset a request points at, LSP characters are UTF-16 code units
+fn position(text: &str, params: &Json) -> Option<usize> {
+    let line = params.get("position").get("line").as_f64()? as usize;
+    let character = params.get("position").get("character").as_f64()? as usize;
+
+    let line_start = match line {
+        0 => 0,
+        _ => text.match_indices('\n').nth(line - 1)?.0 + 1,
+    };
+    let mut units = 0;
+    for (offset, c) in text[line_start..].char_indices() {
+        if units >= character || c == '\n' {
+            return Some(line_start + offset);
+        }
+        units += c.len_utf16();
+    }
+    return Some(text.len());
+}
+
+// the position right after a name still counts, that is where the cursor sits after typing it
+fn covers(token: &Token, offset: usize) -> bool {
+    return token.offset <= offset && offset <= token.offset + token.lexeme.len();
+}
+
+// parse, resolve and type errors, or the lint warnings when there are none
+fn analyze(text: &str) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+    let tokens = Scanner::new(text).scan_tokens()?;
+    let statements = Parser::new(tokens).parse()?;
+    let warnings = Resolver::new()
+        .check(&statements.iter().collect())
+        .map_err(|diagnostic| vec![diagnostic])?;
+    Checker::new().check(&statements.iter().collect())?;
+    return Ok(warnings);
+}
+
+// hover text for every declaration, by the offset of its name
+fn describe(statements: &Vec<&Stmt>, details: &mut HashMap<usize, String>) {
+    for statement in statements {
+        match statement {
+            Stmt::Var {
+                name,
+                annotation,
+                initializer: _,
+            } => {
+                details.insert(
+                    name.offset,
+                    code(&format!("var {}", annotated(name, annotation))),
+                );
+            }
+            Stmt::Function {
+                name,
+                params,
+                param_annotations,
+                return_annotation,
+                body,
+            } => {
+                let signature: Vec<String> = params
+                    .iter()
+                    .zip(param_annotations)
+                    .map(|(param, annotation)| annotated(param, annotation))
+                    .collect();
+                let returns = match return_annotation {
+                    Some(annotation) => format!(" -> {}", annotation.lexeme),
+                    None => "".to_string(),
+                };
+                details.insert(
+                    name.offset,
+                    format!(
+                        "{}\narity {}",
+                        code(&format!(
+                            "fun {}({}){}",
+                            name.lexeme,
+                            signature.join(", "),
+                            returns
+                        )),
+                        params.len()
+                    ),
+                );
+                for (param, annotation) in params.iter().zip(param_annotations) {
+                    details.insert(
+                        param.offset,
+                        code(&format!(
+                            "parameter {} of {}",
+                            annotated(param, annotation),
+                            name.lexeme
+                        )),
+                    );
+                }
+                describe(&body.iter().map(|b| b.as_ref()).collect(), details);
+            }
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                let superclass = match superclass {
+                    Some(superclass) => format!(" < {}", superclass.to_string()),
+                    None => "".to_string(),
+                };
+                let arity = methods
+                    .iter()
+                    .find_map(|method| match method.as_ref() {
+                        Stmt::Function {
+                            name,
+                            params,
+                            param_annotations: _,
+                            return_annotation: _,
+                            body: _,
+                        } if name.lexeme == "init" => Some(params.len()),
+                        _ => None,
+                    })
+                    .unwrap_or(0);
+                details.insert(
+                    name.offset,
+                    format!(
+                        "{}\narity {}",
+                        code(&format!("class {}{}", name.lexeme, superclass)),
+                        arity
+                    ),
+                );
+                describe(&methods.iter().map(|m| m.as_ref()).collect(), details);
+            }
+            Stmt::Import { path, name } => {
+                details.insert(
+                    name.offset,
+                    code(&format!("import {} as {}", path.lexeme, name.lexeme)),
+                );
+            }
+            Stmt::Block { statements } => {
+                describe(&statements.iter().map(|s| s.as_ref()).collect(), details);
+            }
+            Stmt::IfStmt {
+                predicate: _,
+                then,
+                els,
+            } => {
+                describe(&vec![then.as_ref()], details);
+                if let Some(els) = els {
+                    describe(&vec![els.as_ref()], details);
+                }
+            }
+            Stmt::WhileStmt {
+                condition: _,
+                body,
+                increment: _,
+            } => describe(&vec![body.as_ref()], details),
+            Stmt::Try {
+                keyword: _,
+                body,
+                catch,
+                finally,
+            } => {
+                describe(&vec![body.as_ref()], details);
+                if let Some((name, handler)) = catch {
+                    details.insert(name.offset, code(&format!("catch ({})", name.lexeme)));
+                    describe(&vec![handler.as_ref()], details);
+                }
+                if let Some(finally) = finally {
+                    describe(&vec![finally.as_ref()], details);
+                }
+            }
+            Stmt::Expression { expression: _ }
+            | Stmt::Print { expression: _ }
+            | Stmt::ReturnStmt {
+                keyword: _,
+                value: _,
+            }
+            | Stmt::Throw {
+                keyword: _,
+                value: _,
+            }
+            | Stmt::Break { keyword: _ }
+            | Stmt::Continue { keyword: _ } => (),
+        }
+    }
+}
+
+// top-level declarations, with the methods of a class as its children
+fn symbol(text: &str, statement: &Stmt) -> Option<Json> {
+    match statement {
+        Stmt::Var {
+            name,
+            annotation: _,
+            initializer: _,
+        } => return Some(document_symbol(text, name, SYMBOL_VARIABLE, vec![])),
+        Stmt::Function {
+            name,
+            params: _,
+            param_annotations: _,
+            return_annotation: _,
+            body: _,
+        } => return Some(document_symbol(text, name, SYMBOL_FUNCTION, vec![])),
+        Stmt::Import { path: _, name } => {
+            return Some(document_symbol(text, name, SYMBOL_MODULE, vec![]));
+        }
+        Stmt::Class {
+            name,
+            superclass: _,
+            methods,
+        } => {
+            let children = methods
+                .iter()
+                .filter_map(|method| match method.as_ref() {
+                    Stmt::Function {
+                        name,
+                        params: _,
+                        param_annotations: _,
+                        return_annotation: _,
+                        body: _,
+                    } => {
+                        let kind = if name.lexeme == "init" {
+                            SYMBOL_CONSTRUCTOR
+                        } else {
+                            SYMBOL_METHOD
+                        };
+                        Some(document_symbol(text, name, kind, vec![]))
+                    }
+                    _ => None,
+                })
+                .collect();
+            return Some(document_symbol(text, name, SYMBOL_CLASS, children));
+        }
+        _ => return None,
+    }
+}
+
+fn document_symbol(text: &str, name: &Token, kind: f64, children: Vec<Json>) -> Json {
+    return object(vec![
+        ("name", Json::String(name.lexeme.clone())),
+        ("kind", Json::Number(kind)),
+        ("range", token_range(text, name)),
+        ("selectionRange", token_range(text, name)),
+        ("children", Json::Array(children)),
+    ]);
+}
+
+fn lsp_diagnostic(text: &str, diagnostic: &Diagnostic) -> Json {
+    let range = match diagnostic.span {
+        Some(span) => range(text, span.start, span.end),
+        None => range(text, 0, 0),
+    };
+    let severity = match diagnostic.kind {
+        DiagnosticKind::Lint => 2.0,
+        _ => 1.0,
+    };
+
+    return object(vec![
+        ("range", range),
+        ("severity", Json::Number(severity)),
+        ("source", Json::String("lox".to_string())),
+        ("message", Json::String(diagnostic.message.clone())),
+    ]);
+}
+
+fn token_range(text: &str, token: &Token) -> Json {
+    return range(text, token.offset, token.offset + token.lexeme.len());
+}
+
+// LSP positions are zero-based lines and UTF-16 code units into the line,
+// spans and tokens are byte offsets
+fn range(text: &str, start: usize, end: usize) -> Json {
+    let position = |offset: usize| {
+        let mut offset = offset.min(text.len());
+        while !text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let before = &text[..offset];
+        let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+        let character = before[line_start..].encode_utf16().count();
+        object(vec![
+            ("line", Json::Number(before.matches('\n').count() as f64)),
+            ("character", Json::Number(character as f64)),
+        ])
+    };
+    return object(vec![("start", position(start)), ("end", position(end))]);
+}
+
+fn response(id: Json, result: Json) -> Json {
+    return object(vec![
+        ("jsonrpc", Json::String("2.0".to_string())),
+        ("id", id),
+        ("result", result),
+    ]);
+}
+
+fn notification(method: &str, params: Json) -> Json {
+    return object(vec![
+        ("jsonrpc", Json::String("2.0".to_string())),
+        ("method", Json::String(method.to_string())),
+        ("params", params),
+    ]);
+}
+
+fn code(text: &str) -> String {
+    return format!("```lox\n{}\n```", text);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(body: &str) -> String {
+        return format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+    }
+
+    fn exchange(messages: Vec<&str>) -> Vec<Json> {
+        let input: String = messages.into_iter().map(frame).collect();
+        let mut output = vec![];
+        serve(&mut io::Cursor::new(input), &mut output).unwrap();
+
+        let mut output = io::Cursor::new(output);
+        let mut replies = vec![];
+        while let Some(body) = read_message(&mut output).unwrap() {
+            replies.push(Json::parse(&body).unwrap());
+        }
+        return replies;
+    }
+
+    fn open(text: &str) -> String {
+        return format!(
+            r#"{{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{{"textDocument":{{"uri":"file:///a.lox","text":{}}}}}}}"#,
+            crate::json::string(text)
+        );
+    }
+
+    fn at(id: usize, method: &str, line: usize, character: usize) -> String {
+        return format!(
+            r#"{{"jsonrpc":"2.0","id":{},"method":"{}","params":{{"textDocument":{{"uri":"file:///a.lox"}},"position":{{"line":{},"character":{}}}}}}}"#,
+            id, method, line, character
+        );
+    }
+
+    #[test]
+    fn publishes_errors_and_warnings() {
+        let bad = open("print 1 +;");
+        let lint = open("fun f() {\n  var unused = 1;\n}");
+        // scanning used to panic on the first character outside ascii
+        let accented = open("// café\nprint \"é\" +;");
+        let replies = exchange(vec![
+            &bad,
+            &lint,
+            &accented,
+            r#"{"jsonrpc":"2.0","method":"exit"}"#,
+        ]);
+
+        assert_eq!(replies.len(), 3);
+        let error = &replies[0]
+            .get("params")
+            .get("diagnostics")
+            .as_array()
+            .unwrap()[0];
+        assert_eq!(error.get("severity").as_f64(), Some(1.0));
+        let warning = &replies[1]
+            .get("params")
+            .get("diagnostics")
+            .as_array()
+            .unwrap()[0];
+        assert_eq!(warning.get("severity").as_f64(), Some(2.0));
+        assert_eq!(
+            warning.get("range").get("start").get("line").as_f64(),
+            Some(1.0)
+        );
+        let accented = &replies[2]
+            .get("params")
+            .get("diagnostics")
+            .as_array()
+            .unwrap()[0];
+        let start = accented.get("range").get("start");
+        assert_eq!(start.get("line").as_f64(), Some(1.0));
+        assert_eq!(start.get("character").as_f64(), Some(11.0));
+    }
+
+    #[test]
+    fn counts_characters_in_utf16() {
+        // the emoji takes two UTF-16 code units and four bytes
+        let bad = open("print \"😀\" + é;");
+        let good = open("var s = \"😀\"; print s;");
+        let definition = at(1, "textDocument/definition", 0, 20);
+        let replies = exchange(vec![&bad, &good, &definition]);
+
+        let error = &replies[0]
+            .get("params")
+            .get("diagnostics")
+            .as_array()
+            .unwrap()[0];
+        assert_eq!(error.get("message").as_str(), Some("unrecognized char: é"));
+        let range = error.get("range");
+        assert_eq!(range.get("start").get("character").as_f64(), Some(13.0));
+        assert_eq!(range.get("end").get("character").as_f64(), Some(14.0));
+
+        let range = replies[2].get("result").get("range");
+        assert_eq!(range.get("start").get("character").as_f64(), Some(4.0));
+        assert_eq!(range.get("end").get("character").as_f64(), Some(5.0));
+    }
+
+    #[test]
+    fn answers_definition_hover_and_symbols() {
+        let text = open("fun add(a, b) {\n  return a + b;\n}\nclass P {\n  init(x) {}\n}\nprint add(1, clock());");
+        let definition = at(1, "textDocument/definition", 6, 7);
+        let hover = at(2, "textDocument/hover", 6, 7);
+        let native = at(3, "textDocument/hover", 6, 14);
+        let symbols = r#"{"jsonrpc":"2.0","id":4,"method":"textDocument/documentSymbol","params":{"textDocument":{"uri":"file:///a.lox"}}}"#;
+        let unknown = r#"{"jsonrpc":"2.0","id":5,"method":"textDocument/rename","params":{}}"#;
+        let replies = exchange(vec![&text, &definition, &hover, &native, symbols, unknown]);
+
+        let start = replies[1].get("result").get("range").get("start");
+        assert_eq!(start.get("line").as_f64(), Some(0.0));
+        assert_eq!(start.get("character").as_f64(), Some(4.0));
+
+        let hover = replies[2].get("result").get("contents").get("value");
+        assert_eq!(hover.as_str(), Some("```lox\nfun add(a, b)\n```\narity 2"));
+        let native = replies[3].get("result").get("contents").get("value");
+        assert_eq!(
+            native.as_str(),
+            Some("```lox\nnative fun clock\n```\narity 0")
+        );
+
+        let symbols = replies[4].get("result").as_array().unwrap();
+        let names: Vec<&str> = symbols
+            .iter()
+            .map(|symbol| symbol.get("name").as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["add", "P"]);
+        let init = &symbols[1].get("children").as_array().unwrap()[0];
+        assert_eq!(init.get("kind").as_f64(), Some(SYMBOL_CONSTRUCTOR));
+
+        assert_eq!(
+            replies[5].get("error").get("code").as_f64(),
+            Some(METHOD_NOT_FOUND)
+        );
+    }
+}
@@ -1,38 +1,101 @@
-mod environment;
-mod expr;
-mod interpreter;
-mod parser;
-mod resolver;
-mod scanner;
-mod stmt;
-mod tests;
-
+use lox::{check, debugger, dump_ast, formatter, lsp, module, profiler, repl, report, run_string};
 use std::env;
 use std::fs;
-use std::io::{self, BufRead, Write};
 use std::process;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    let json = take_flag(&mut args, "--json");
+    let use_vm = take_flag(&mut args, "--vm");
+    let deny_warnings = take_flag(&mut args, "--deny-warnings");
+    let check_only = take_flag(&mut args, "--check");
+    let show_ast = take_flag(&mut args, "--dump-ast");
+    let stacks = take_value(&mut args, "--profile-stacks");
+    let profile = take_flag(&mut args, "--profile") || stacks.is_some();
+    if profile && use_vm {
+        println!("ERROR: the profiler runs on the tree-walking interpreter, drop --vm");
+        process::exit(64);
+    }
 
-    if args.len() == 2 {
-        match run_file(&args[1]) {
-            Ok(_) => process::exit(0),
+    if args.len() == 2 && args[1] == "lsp" {
+        if let Err(msg) = lsp::run() {
+            eprintln!("ERROR: {}", msg);
+            process::exit(1);
+        }
+        process::exit(0);
+    } else if args.len() == 3 && args[1] == "debug" {
+        let contents = match fs::read_to_string(&args[2]) {
+            Ok(contents) => contents,
             Err(msg) => {
                 println!("ERROR: {}", msg);
                 process::exit(1);
             }
+        };
+        module::set_entry(&args[2]);
+        if let Err(diagnostics) = debugger::run(&contents) {
+            report(diagnostics, &contents, &args[2], json);
+            process::exit(1);
         }
-    } else if args.len() == 3 && args[1] == "e" {
-        match run_string(&args[2]) {
-            Ok(_) => process::exit(0),
+        process::exit(0);
+    } else if args.len() >= 3 && args[1] == "fmt" {
+        process::exit(format_files(&args[2..], check_only, json));
+    } else if args.len() == 3 && args[1] == "check" {
+        let contents = match fs::read_to_string(&args[2]) {
+            Ok(contents) => contents,
             Err(msg) => {
                 println!("ERROR: {}", msg);
                 process::exit(1);
             }
+        };
+        match check(&contents) {
+            Ok(warnings) => {
+                let failed = deny_warnings && !warnings.is_empty();
+                report(warnings, &contents, &args[2], json);
+                process::exit(if failed { 1 } else { 0 });
+            }
+            Err(diagnostics) => {
+                report(diagnostics, &contents, &args[2], json);
+                process::exit(1);
+            }
+        }
+    } else if args.len() == 2 {
+        let contents = match fs::read_to_string(&args[1]) {
+            Ok(contents) => contents,
+            Err(msg) => {
+                println!("ERROR: {}", msg);
+                process::exit(1);
+            }
+        };
+        if show_ast {
+            process::exit(print_ast(&contents, &args[1], json));
+        }
+        module::set_entry(&args[1]);
+        if profile {
+            process::exit(profile_script(&contents, &args[1], json, stacks));
+        }
+        match run_string(&contents, use_vm) {
+            Ok(_) => process::exit(0),
+            Err(diagnostics) => {
+                report(diagnostics, &contents, &args[1], json);
+                process::exit(1);
+            }
+        }
+    } else if args.len() == 3 && args[1] == "e" {
+        if show_ast {
+            process::exit(print_ast(&args[2], "<string>", json));
+        }
+        if profile {
+            process::exit(profile_script(&args[2], "<string>", json, stacks));
+        }
+        match run_string(&args[2], use_vm) {
+            Ok(_) => process::exit(0),
+            Err(diagnostics) => {
+                report(diagnostics, &args[2], "<string>", json);
+                process::exit(1);
+            }
         }
     } else if args.len() == 1 {
-        match run_prompt() {
+        match repl::run_prompt(json, use_vm) {
             Ok(_) => process::exit(0),
             Err(msg) => {
                 println!("ERROR: {}", msg);
@@ -40,67 +103,100 @@ fn main() {
             }
         }
     } else {
-        println!("Usage: jlox [script]");
+        println!("Usage: jlox [--json] [--vm] [script]");
+        println!("       jlox --dump-ast <script>");
+        println!("       jlox --profile [--profile-stacks <file>] <script>");
+        println!("       jlox [--json] [--deny-warnings] check <script>");
+        println!("       jlox [--check] fmt <script>...");
+        println!("       jlox debug <script>");
+        println!("       jlox lsp");
         process::exit(64);
     }
 }
 
-pub fn run_file(path: &str) -> Result<(), String> {
-    match fs::read_to_string(path) {
-        Err(msg) => return Err(msg.to_string()),
-        Ok(contents) => return run_string(&contents),
-    }
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let before = args.len();
+    args.retain(|arg| arg != flag);
+    return args.len() != before;
 }
 
-pub fn run_string(contents: &str) -> Result<(), String> {
-    let mut interpreter = interpreter::Interpreter::new();
-    return run(&mut interpreter, contents);
+// the flag and the argument after it, when both are present
+fn take_value(args: &mut Vec<String>, flag: &str) -> Option<String> {
+    let position = args.iter().position(|arg| arg == flag)?;
+    if position + 1 >= args.len() {
+        return None;
+    }
+    let value = args.remove(position + 1);
+    args.remove(position);
+    return Some(value);
 }
 
-fn run_prompt() -> Result<(), String> {
-    let mut interp = interpreter::Interpreter::new();
-    let mut buffer = String::new();
-    loop {
-        print!("> ");
-        match io::stdout().flush() {
-            Ok(_) => (),
-            Err(_) => return Err("could not flush stdout".to_string()),
+// runs the script, then prints where its time went and optionally writes the
+// call stacks in the collapsed format flamegraph tools read
+fn profile_script(contents: &str, file: &str, json: bool, stacks: Option<String>) -> i32 {
+    let profile = match profiler::run(contents) {
+        Ok(profile) => profile,
+        Err(diagnostics) => {
+            report(diagnostics, contents, file, json);
+            return 1;
         }
+    };
 
-        let stdin = io::stdin();
-        let mut handle = stdin.lock();
-        let current_length = buffer.len();
-        match handle.read_line(&mut buffer) {
-            Ok(n) => {
-                if n == 0 {
-                    println!("");
-                    return Ok(());
-                } else if n == 1 {
-                    continue;
-                }
-            }
-            Err(_) => return Err("count not read line".to_string()),
+    print!("\n{}", profile.table());
+    if let Some(path) = stacks {
+        if let Err(msg) = fs::write(&path, profile.collapsed()) {
+            println!("ERROR: {}: {}", path, msg);
+            return 1;
         }
+    }
+    return 0;
+}
 
-        println!("got: {}", &buffer[current_length..]);
-        match run(&mut interp, &buffer[current_length..]) {
-            Ok(_) => (),
-            Err(msg) => println!("{}", msg),
+// prints the optimized syntax tree instead of running the script
+fn print_ast(contents: &str, file: &str, json: bool) -> i32 {
+    match dump_ast(contents) {
+        Ok(ast) => {
+            print!("{}", ast);
+            return 0;
+        }
+        Err(diagnostics) => {
+            report(diagnostics, contents, file, json);
+            return 1;
         }
     }
 }
 
-fn run(interp: &mut interpreter::Interpreter, contents: &str) -> Result<(), String> {
-    let mut scanner = scanner::Scanner::new(contents);
-    let tokens = scanner.scan_tokens()?;
-
-    let mut parser = parser::Parser::new(tokens);
-    let statements = parser.parse()?;
-    let resolver = resolver::Resolver::new();
-    let locals = resolver.resolve(&statements.iter().collect())?;
-
-    interp.resolve(locals);
+// formats the files in place, or with --check lists the ones that would change
+fn format_files(paths: &[String], check_only: bool, json: bool) -> i32 {
+    let mut status = 0;
+    for path in paths {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(msg) => {
+                println!("ERROR: {}: {}", path, msg);
+                status = 1;
+                continue;
+            }
+        };
+        let formatted = match formatter::format(&contents) {
+            Ok(formatted) => formatted,
+            Err(diagnostics) => {
+                report(diagnostics, &contents, path, json);
+                status = 1;
+                continue;
+            }
+        };
 
-    interp.interpret(statements.iter().collect())?;
-    return Ok(());
+        if formatted == contents {
+            continue;
+        }
+        if check_only {
+            println!("would reformat {}", path);
+            status = 1;
+        } else if let Err(msg) = fs::write(path, formatted) {
+            println!("ERROR: {}: {}", path, msg);
+            status = 1;
+        }
+    }
+    return status;
 }
@@ -0,0 +1,126 @@
+use crate::checker::Checker;
+use crate::diagnostic::Diagnostic;
+use crate::error::RuntimeError;
+use crate::expr::LiteralValue;
+use crate::gc;
+use crate::interpreter;
+use crate::optimizer;
+use crate::parser;
+use crate::resolver;
+use crate::scanner;
+use crate::vm;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+thread_local! {
+    // every module runs once per process, later imports share the same value
+    static CACHE: RefCell<HashMap<PathBuf, LiteralValue>> = RefCell::new(HashMap::new());
+    // the scripts currently being loaded, outermost first
+    static LOADING: RefCell<Vec<PathBuf>> = const { RefCell::new(vec![]) };
+}
+
+// imports are relative to the importing script, the entry script is registered
+// here so its imports resolve against its own directory instead of the cwd
+pub fn set_entry(path: &str) {
+    if let Ok(path) = fs::canonicalize(path) {
+        LOADING.with(|loading| loading.borrow_mut().push(path));
+    }
+}
+
+pub fn import(path: &scanner::Token, use_vm: bool) -> Result<LiteralValue, RuntimeError> {
+    let relative = match &path.literal {
+        Some(scanner::LiteralValue::StringValue(s)) => s.clone(),
+        _ => panic!("import path must be a string literal"),
+    };
+
+    let base = LOADING.with(|loading| {
+        loading
+            .borrow()
+            .last()
+            .and_then(|script| script.parent().map(Path::to_path_buf))
+    });
+    let canonical = fs::canonicalize(base.unwrap_or_default().join(&relative))
+        .map_err(|err| RuntimeError::at(path, format!("cannot import '{}': {}", relative, err)))?;
+
+    if let Some(module) = CACHE.with(|cache| cache.borrow().get(&canonical).cloned()) {
+        return Ok(module);
+    }
+
+    let cycle = LOADING.with(|loading| {
+        let loading = loading.borrow();
+        let start = loading.iter().position(|script| *script == canonical)?;
+        let mut chain: Vec<String> = loading[start..].iter().map(|p| file_name(p)).collect();
+        chain.push(file_name(&canonical));
+        return Some(chain.join(" -> "));
+    });
+    if let Some(chain) = cycle {
+        return Err(RuntimeError::at(path, format!("import cycle: {}", chain)));
+    }
+
+    let source = fs::read_to_string(&canonical)
+        .map_err(|err| RuntimeError::at(path, format!("cannot import '{}': {}", relative, err)))?;
+
+    LOADING.with(|loading| loading.borrow_mut().push(canonical.clone()));
+    let result = execute(&source, use_vm);
+    LOADING.with(|loading| loading.borrow_mut().pop());
+
+    // the first error inside the module is reported at the import, with a note
+    // for each module it passed through
+    let fields = result.map_err(|mut diagnostics| {
+        let diagnostic = diagnostics.swap_remove(0);
+        let location = match diagnostic.span {
+            Some(span) => format!(" at {}:{}", span.line, span.column),
+            None => "".to_string(),
+        };
+        let mut err = RuntimeError::at(path, diagnostic.message);
+        err.notes = diagnostic.notes;
+        err.notes.push(format!(
+            "{} error in module '{}'{}",
+            diagnostic.kind, relative, location
+        ));
+        err
+    })?;
+
+    let name = canonical
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or(relative);
+    let module = LiteralValue::Module { name, fields };
+    CACHE.with(|cache| cache.borrow_mut().insert(canonical, module.clone()));
+
+    return Ok(module);
+}
+
+// runs a module with a fresh backend and returns its global scope, natives
+// included since its own functions still call them
+fn execute(source: &str, use_vm: bool) -> Result<Rc<gc::Scope>, Vec<Diagnostic>> {
+    let tokens = scanner::Scanner::new(source).scan_tokens()?;
+    let statements = optimizer::optimize(parser::Parser::new(tokens).parse()?);
+    let locals = resolver::Resolver::new()
+        .resolve(&statements.iter().collect())
+        .map_err(|diagnostic| vec![diagnostic])?;
+    Checker::new().check(&statements.iter().collect())?;
+
+    if use_vm {
+        let mut vm = vm::VM::new();
+        vm.interpret(&statements.iter().collect())
+            .map_err(|diagnostic| vec![diagnostic])?;
+        return Ok(vm.global_scope());
+    }
+    let mut interp = interpreter::Interpreter::new();
+    interp.resolve(locals);
+    interp
+        .interpret(statements.iter().collect())
+        .map_err(|err| vec![Diagnostic::from(err)])?;
+    return Ok(interp.global_scope());
+}
+
+fn file_name(path: &Path) -> String {
+    return path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+}
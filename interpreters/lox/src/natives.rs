@@ -0,0 +1,446 @@
+use crate::error::RuntimeError;
+use crate::expr::LiteralValue;
+use crate::gc;
+use crate::limits;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::io::{self, BufRead};
+use std::rc::Rc;
+
+type NativeFn = fn(&[LiteralValue]) -> Result<LiteralValue, RuntimeError>;
+
+// name, arity and implementation, the caller checks the arity before calling
+const NATIVES: [(&str, usize, NativeFn); 20] = [
+    ("clock", 0, clock_impl),
+    // collections
+    ("len", 1, len_impl),
+    ("push", 2, push_impl),
+    ("pop", 1, pop_impl),
+    ("keys", 1, keys_impl),
+    // strings
+    ("substr", 3, substr_impl),
+    ("split", 2, split_impl),
+    ("upper", 1, upper_impl),
+    ("index_of", 2, index_of_impl),
+    ("to_number", 1, to_number_impl),
+    ("to_string", 1, to_string_impl),
+    // math
+    ("sqrt", 1, sqrt_impl),
+    ("floor", 1, floor_impl),
+    ("pow", 2, pow_impl),
+    ("random", 0, random_impl),
+    ("seed", 1, seed_impl),
+    // misc
+    ("type_of", 1, type_of_impl),
+    ("input", 0, input_impl),
+    ("assert", 2, assert_impl),
+    ("gc_stats", 0, gc_stats_impl),
+];
+
+thread_local! {
+    // built once so every global scope holds the very same values, which is how
+    // a native is told apart from a function that took its name
+    static VALUES: Vec<(&'static str, LiteralValue)> = NATIVES
+        .iter()
+        .map(|&(name, arity, fun)| {
+            let value = LiteralValue::Callable {
+                name: name.to_string(),
+                arity,
+                fun: Rc::new(move |args: &Vec<LiteralValue>| fun(args)),
+            };
+            (name, value)
+        })
+        .collect();
+}
+
+pub fn register(env: &mut HashMap<String, LiteralValue>) {
+    VALUES.with(|values| {
+        for (name, value) in values {
+            env.insert(name.to_string(), value.clone());
+        }
+    });
+}
+
+// a string built at runtime, charged against the memory limit
+fn new_string(text: String) -> Result<LiteralValue, RuntimeError> {
+    limits::allocate(text.len())?;
+    return Ok(LiteralValue::StringLit(text));
+}
+
+// natives are global everywhere already, so modules do not export them. a
+// module that defines its own global under a native's name exports that instead
+pub fn is_native(name: &str, value: &LiteralValue) -> bool {
+    let fun = match value {
+        LiteralValue::Callable {
+            name: _,
+            arity: _,
+            fun,
+        } => fun,
+        _ => return false,
+    };
+    return VALUES.with(|values| {
+        values.iter().any(|(native, value)| match value {
+            LiteralValue::Callable {
+                name: _,
+                arity: _,
+                fun: native_fun,
+            } => *native == name && Rc::ptr_eq(fun, native_fun),
+            _ => false,
+        })
+    });
+}
+
+fn number_arg(args: &[LiteralValue], i: usize, native: &str) -> Result<f64, RuntimeError> {
+    match &args[i] {
+        LiteralValue::Number(x) => return Ok(*x),
+        other => {
+            return Err(RuntimeError::new(format!(
+                "{} expects a Number as argument {}, got {}",
+                native,
+                i + 1,
+                other.to_type()
+            )))
+        }
+    }
+}
+
+fn string_arg<'a>(
+    args: &'a [LiteralValue],
+    i: usize,
+    native: &str,
+) -> Result<&'a str, RuntimeError> {
+    match &args[i] {
+        LiteralValue::StringLit(s) => return Ok(s),
+        other => {
+            return Err(RuntimeError::new(format!(
+                "{} expects a String as argument {}, got {}",
+                native,
+                i + 1,
+                other.to_type()
+            )))
+        }
+    }
+}
+
+fn clock_impl(_args: &[LiteralValue]) -> Result<LiteralValue, RuntimeError> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .expect("could not get system time")
+        .as_millis();
+
+    return Ok(LiteralValue::Number(now as f64 / 1000.0));
+}
+
+fn len_impl(args: &[LiteralValue]) -> Result<LiteralValue, RuntimeError> {
+    let length = match &args[0] {
+        LiteralValue::List(items) => items.borrow().len(),
+        LiteralValue::Map(entries) => entries.borrow().len(),
+        LiteralValue::StringLit(s) => s.chars().count(),
+        other => {
+            return Err(RuntimeError::new(format!(
+                "len expects a List, Map or String, got {}",
+                other.to_type()
+            )))
+        }
+    };
+
+    return Ok(LiteralValue::Number(length as f64));
+}
+
+fn push_impl(args: &[LiteralValue]) -> Result<LiteralValue, RuntimeError> {
+    match &args[0] {
+        LiteralValue::List(items) => {
+            limits::allocate(limits::VALUE_BYTES)?;
+            items.borrow_mut().push(args[1].clone());
+            return Ok(LiteralValue::Nil);
+        }
+        other => {
+            return Err(RuntimeError::new(format!(
+                "push expects a List, got {}",
+                other.to_type()
+            )))
+        }
+    }
+}
+
+fn pop_impl(args: &[LiteralValue]) -> Result<LiteralValue, RuntimeError> {
+    match &args[0] {
+        LiteralValue::List(items) => match items.borrow_mut().pop() {
+            Some(item) => return Ok(item),
+            None => return Err(RuntimeError::new("cannot pop from an empty list")),
+        },
+        other => {
+            return Err(RuntimeError::new(format!(
+                "pop expects a List, got {}",
+                other.to_type()
+            )))
+        }
+    }
+}
+
+fn keys_impl(args: &[LiteralValue]) -> Result<LiteralValue, RuntimeError> {
+    match &args[0] {
+        LiteralValue::Map(entries) => {
+            let keys: Vec<LiteralValue> = entries.borrow().iter().map(|(k, _)| k.clone()).collect();
+            limits::allocate(keys.len() * limits::VALUE_BYTES)?;
+            return Ok(LiteralValue::List(gc::list(keys)));
+        }
+        other => {
+            return Err(RuntimeError::new(format!(
+                "keys expects a Map, got {}",
+                other.to_type()
+            )))
+        }
+    }
+}
+
+// substr(s, start, end) with character indices, end is exclusive
+fn substr_impl(args: &[LiteralValue]) -> Result<LiteralValue, RuntimeError> {
+    let s = string_arg(args, 0, "substr")?;
+    let start = number_arg(args, 1, "substr")?;
+    let end = number_arg(args, 2, "substr")?;
+    let length = s.chars().count();
+
+    if start < 0.0 || end < start || end > length as f64 {
+        return Err(RuntimeError::new(format!(
+            "substr range {}..{} out of bounds for length {}",
+            start, end, length
+        )));
+    }
+
+    let result: String = s
+        .chars()
+        .skip(start as usize)
+        .take(end as usize - start as usize)
+        .collect();
+    return new_string(result);
+}
+
+fn split_impl(args: &[LiteralValue]) -> Result<LiteralValue, RuntimeError> {
+    let s = string_arg(args, 0, "split")?;
+    let separator = string_arg(args, 1, "split")?;
+
+    let parts: Vec<LiteralValue> = if separator.is_empty() {
+        s.chars()
+            .map(|c| LiteralValue::StringLit(c.to_string()))
+            .collect()
+    } else {
+        s.split(separator)
+            .map(|part| LiteralValue::StringLit(part.to_string()))
+            .collect()
+    };
+    // the parts hold about as many bytes as the string
+    limits::allocate(s.len() + parts.len() * limits::VALUE_BYTES)?;
+    return Ok(LiteralValue::List(gc::list(parts)));
+}
+
+fn upper_impl(args: &[LiteralValue]) -> Result<LiteralValue, RuntimeError> {
+    let s = string_arg(args, 0, "upper")?;
+    return new_string(s.to_uppercase());
+}
+
+// character index of the first match in a string, or element index in a list, -1 if absent
+fn index_of_impl(args: &[LiteralValue]) -> Result<LiteralValue, RuntimeError> {
+    let index = match &args[0] {
+        LiteralValue::List(items) => items.borrow().iter().position(|item| *item == args[1]),
+        LiteralValue::StringLit(s) => {
+            let needle = string_arg(args, 1, "index_of")?;
+            s.find(needle).map(|byte| s[..byte].chars().count())
+        }
+        other => {
+            return Err(RuntimeError::new(format!(
+                "index_of expects a String or List, got {}",
+                other.to_type()
+            )))
+        }
+    };
+
+    return Ok(LiteralValue::Number(match index {
+        Some(i) => i as f64,
+        None => -1.0,
+    }));
+}
+
+// nil when the string is not a number, so scripts can validate input
+fn to_number_impl(args: &[LiteralValue]) -> Result<LiteralValue, RuntimeError> {
+    match &args[0] {
+        LiteralValue::Number(x) => return Ok(LiteralValue::Number(*x)),
+        LiteralValue::StringLit(s) => match s.trim().parse::<f64>() {
+            Ok(x) => return Ok(LiteralValue::Number(x)),
+            Err(_) => return Ok(LiteralValue::Nil),
+        },
+        other => {
+            return Err(RuntimeError::new(format!(
+                "to_number expects a String or Number, got {}",
+                other.to_type()
+            )))
+        }
+    }
+}
+
+fn to_string_impl(args: &[LiteralValue]) -> Result<LiteralValue, RuntimeError> {
+    match &args[0] {
+        LiteralValue::StringLit(s) => return new_string(s.clone()),
+        other => return new_string(other.to_string()),
+    }
+}
+
+fn sqrt_impl(args: &[LiteralValue]) -> Result<LiteralValue, RuntimeError> {
+    let x = number_arg(args, 0, "sqrt")?;
+    return Ok(LiteralValue::Number(x.sqrt()));
+}
+
+fn floor_impl(args: &[LiteralValue]) -> Result<LiteralValue, RuntimeError> {
+    let x = number_arg(args, 0, "floor")?;
+    return Ok(LiteralValue::Number(x.floor()));
+}
+
+fn pow_impl(args: &[LiteralValue]) -> Result<LiteralValue, RuntimeError> {
+    let base = number_arg(args, 0, "pow")?;
+    let exponent = number_arg(args, 1, "pow")?;
+    return Ok(LiteralValue::Number(base.powf(exponent)));
+}
+
+thread_local! {
+    // xorshift state, seeded from the clock until a script calls seed()
+    static RANDOM_STATE: Cell<u64> = Cell::new(
+        std::time::SystemTime::now()
+            .duration_since(std::time::SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0)
+            | 1
+    );
+}
+
+// a number in [0, 1)
+fn random_impl(_args: &[LiteralValue]) -> Result<LiteralValue, RuntimeError> {
+    let value = RANDOM_STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        return x;
+    });
+
+    return Ok(LiteralValue::Number(
+        (value >> 11) as f64 / (1u64 << 53) as f64,
+    ));
+}
+
+fn seed_impl(args: &[LiteralValue]) -> Result<LiteralValue, RuntimeError> {
+    let seed = number_arg(args, 0, "seed")?;
+    RANDOM_STATE.with(|state| state.set(mix_seed(seed as i64 as u64)));
+    return Ok(LiteralValue::Nil);
+}
+
+// splitmix64, a bijection so different seeds give different states. xorshift
+// never leaves the zero state, the one seed that mixes to it gets a constant
+fn mix_seed(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    if z == 0 {
+        return 0x9e37_79b9_7f4a_7c15;
+    }
+    return z;
+}
+
+fn type_of_impl(args: &[LiteralValue]) -> Result<LiteralValue, RuntimeError> {
+    return Ok(LiteralValue::StringLit(args[0].to_type().to_string()));
+}
+
+// reads one line from stdin without the newline, nil at end of input
+fn input_impl(_args: &[LiteralValue]) -> Result<LiteralValue, RuntimeError> {
+    let mut line = String::new();
+    match io::stdin().lock().read_line(&mut line) {
+        Ok(0) => return Ok(LiteralValue::Nil),
+        Ok(_) => {
+            let line = line.trim_end_matches(['\n', '\r']).to_string();
+            return new_string(line);
+        }
+        Err(err) => return Err(RuntimeError::new(format!("could not read input: {}", err))),
+    }
+}
+
+fn assert_impl(args: &[LiteralValue]) -> Result<LiteralValue, RuntimeError> {
+    if args[0].is_truthy().map_err(RuntimeError::new)? == LiteralValue::True {
+        return Ok(LiteralValue::Nil);
+    }
+
+    let message = match &args[1] {
+        LiteralValue::StringLit(s) => s.clone(),
+        other => other.to_string(),
+    };
+    return Err(RuntimeError::new(format!("assertion failed: {}", message)));
+}
+
+// collector counters as a map, allocated and freed count objects since the start
+fn gc_stats_impl(_args: &[LiteralValue]) -> Result<LiteralValue, RuntimeError> {
+    let stats = gc::stats();
+    let entries = [
+        ("collections", stats.collections),
+        ("allocated", stats.allocated),
+        ("freed", stats.freed),
+        ("objects", stats.objects),
+        ("threshold", stats.threshold),
+    ];
+    let entries = entries
+        .into_iter()
+        .map(|(name, count)| {
+            (
+                LiteralValue::StringLit(name.to_string()),
+                LiteralValue::Number(count as f64),
+            )
+        })
+        .collect();
+    return Ok(LiteralValue::Map(gc::map(entries)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> LiteralValue {
+        return LiteralValue::StringLit(s.to_string());
+    }
+
+    #[test]
+    fn substr_uses_character_indices() {
+        let result = substr_impl(&[
+            string("héllo"),
+            LiteralValue::Number(1.0),
+            LiteralValue::Number(3.0),
+        ]);
+        assert_eq!(result.unwrap(), string("él"));
+
+        let result = substr_impl(&[
+            string("abc"),
+            LiteralValue::Number(2.0),
+            LiteralValue::Number(5.0),
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn seeded_random_is_repeatable() {
+        seed_impl(&[LiteralValue::Number(42.0)]).unwrap();
+        let first = random_impl(&[]).unwrap();
+        seed_impl(&[LiteralValue::Number(42.0)]).unwrap();
+        let second = random_impl(&[]).unwrap();
+
+        assert_eq!(first, second);
+        for (a, b) in [(42.0, 43.0), (0.0, 1.0)] {
+            seed_impl(&[LiteralValue::Number(a)]).unwrap();
+            let from_a = random_impl(&[]).unwrap();
+            seed_impl(&[LiteralValue::Number(b)]).unwrap();
+            let from_b = random_impl(&[]).unwrap();
+            assert_ne!(from_a, from_b, "seeds {} and {} gave the same stream", a, b);
+        }
+        match first {
+            LiteralValue::Number(x) => assert!((0.0..1.0).contains(&x)),
+            _ => panic!("random should return a number"),
+        }
+    }
+}
@@ -0,0 +1,404 @@
+use crate::expr::{self, Expr, LiteralValue, Pattern};
+use crate::scanner::TokenType;
+use crate::stmt::Stmt;
+
+// folds constant expressions and drops branches that can never run, between
+// parsing and resolving. a folded expression keeps the id of the expression it
+// replaces, and expressions that would fail at runtime are left for the
+// interpreter to report
+pub fn optimize(statements: Vec<Stmt>) -> Vec<Stmt> {
+    return statements.into_iter().filter_map(statement).collect();
+}
+
+// for the boxed statements of blocks and bodies
+fn boxed(stm: Box<Stmt>) -> Option<Box<Stmt>> {
+    return statement(*stm).map(Box::new);
+}
+
+// none when the statement has no effect at all
+fn statement(stm: Stmt) -> Option<Stmt> {
+    let optimized = match stm {
+        Stmt::Expression { expression } => Stmt::Expression {
+            expression: expr(expression),
+        },
+        Stmt::Print { expression } => Stmt::Print {
+            expression: expr(expression),
+        },
+        Stmt::Var {
+            name,
+            annotation,
+            initializer,
+        } => Stmt::Var {
+            name,
+            annotation,
+            initializer: expr(initializer),
+        },
+        Stmt::Block { statements: stms } => Stmt::Block {
+            statements: stms.into_iter().filter_map(boxed).collect(),
+        },
+        Stmt::Class {
+            name,
+            superclass,
+            methods,
+        } => Stmt::Class {
+            name,
+            superclass: superclass.map(expr),
+            methods: methods.into_iter().filter_map(boxed).collect(),
+        },
+        Stmt::IfStmt {
+            predicate,
+            then,
+            els,
+        } => {
+            let predicate = condition(expr(predicate));
+            if let Some(value) = constant(&predicate) {
+                return match (is_truthy(value), els) {
+                    (true, _) => statement(*then),
+                    (false, Some(els)) => statement(*els),
+                    (false, None) => None,
+                };
+            }
+            Stmt::IfStmt {
+                predicate,
+                then: Box::new(nested(*then)),
+                els: els.and_then(|els| statement(*els)).map(Box::new),
+            }
+        }
+        Stmt::WhileStmt {
+            condition: cond,
+            body,
+            increment,
+        } => {
+            let cond = condition(expr(cond));
+            if constant(&cond).is_some_and(|value| !is_truthy(value)) {
+                return None;
+            }
+            Stmt::WhileStmt {
+                condition: cond,
+                body: Box::new(nested(*body)),
+                increment: increment.map(expr),
+            }
+        }
+        Stmt::Function {
+            name,
+            params,
+            param_annotations,
+            return_annotation,
+            body,
+        } => Stmt::Function {
+            name,
+            params,
+            param_annotations,
+            return_annotation,
+            body: body.into_iter().filter_map(boxed).collect(),
+        },
+        Stmt::ReturnStmt { keyword, value } => Stmt::ReturnStmt {
+            keyword,
+            value: value.map(expr),
+        },
+        Stmt::Throw { keyword, value } => Stmt::Throw {
+            keyword,
+            value: expr(value),
+        },
+        Stmt::Try {
+            keyword,
+            body,
+            catch,
+            finally,
+        } => Stmt::Try {
+            keyword,
+            body: Box::new(nested(*body)),
+            catch: catch.map(|(name, handler)| (name, Box::new(nested(*handler)))),
+            finally: finally.map(|finally| Box::new(nested(*finally))),
+        },
+        stm @ (Stmt::Import { path: _, name: _ }
+        | Stmt::Break { keyword: _ }
+        | Stmt::Continue { keyword: _ }) => stm,
+    };
+    return Some(optimized);
+}
+
+// a negative number pattern is folded to its literal
+fn pattern(pattern: Pattern) -> Pattern {
+    match pattern {
+        Pattern::Literal { value } => return Pattern::Literal { value: expr(value) },
+        pattern => return pattern,
+    }
+}
+
+// a statement that has to stay in place, such as the body of a loop
+fn nested(stm: Stmt) -> Stmt {
+    return statement(stm).unwrap_or(Stmt::Block { statements: vec![] });
+}
+
+fn expr(exp: Expr) -> Expr {
+    match exp {
+        Expr::Binary {
+            id,
+            left,
+            operator,
+            right,
+        } => {
+            let left = expr(*left);
+            let right = expr(*right);
+            if let (Some(l), Some(r)) = (constant(&left), constant(&right)) {
+                if let Ok(value) = expr::binary_op(l, operator.token_type, r) {
+                    return Expr::Literal { id, value };
+                }
+            }
+            return Expr::Binary {
+                id,
+                left: Box::new(left),
+                operator,
+                right: Box::new(right),
+            };
+        }
+        Expr::Unary {
+            id,
+            operator,
+            right,
+        } => {
+            let mut right = expr(*right);
+            // only the truthiness of the operand of ! matters
+            if operator.token_type == TokenType::Bang {
+                right = condition(right);
+            }
+            if let Some(value) = constant(&right) {
+                if let Ok(value) = expr::unary_op(operator.token_type, value) {
+                    return Expr::Literal { id, value };
+                }
+            }
+            return Expr::Unary {
+                id,
+                operator,
+                right: Box::new(right),
+            };
+        }
+        Expr::Grouping { id, expression } => {
+            let expression = expr(*expression);
+            if let Expr::Literal { id: _, value } = expression {
+                return Expr::Literal { id, value };
+            }
+            return Expr::Grouping {
+                id,
+                expression: Box::new(expression),
+            };
+        }
+        Expr::AnonFunction {
+            id,
+            paren,
+            arguments,
+            body,
+        } => Expr::AnonFunction {
+            id,
+            paren,
+            arguments,
+            body: body.into_iter().filter_map(boxed).collect(),
+        },
+        Expr::Assign { id, name, value } => Expr::Assign {
+            id,
+            name,
+            value: Box::new(expr(*value)),
+        },
+        Expr::Call {
+            id,
+            callee,
+            paren,
+            arguments,
+        } => Expr::Call {
+            id,
+            callee: Box::new(expr(*callee)),
+            paren,
+            arguments: arguments.into_iter().map(expr).collect(),
+        },
+        Expr::Get { id, object, name } => Expr::Get {
+            id,
+            object: Box::new(expr(*object)),
+            name,
+        },
+        Expr::Index {
+            id,
+            object,
+            bracket,
+            index,
+        } => Expr::Index {
+            id,
+            object: Box::new(expr(*object)),
+            bracket,
+            index: Box::new(expr(*index)),
+        },
+        Expr::Interpolation { id, quote, parts } => Expr::Interpolation {
+            id,
+            quote,
+            parts: parts.into_iter().map(expr).collect(),
+        },
+        Expr::List {
+            id,
+            bracket,
+            elements,
+        } => Expr::List {
+            id,
+            bracket,
+            elements: elements.into_iter().map(expr).collect(),
+        },
+        Expr::Logical {
+            id,
+            left,
+            operator,
+            right,
+        } => Expr::Logical {
+            id,
+            left: Box::new(expr(*left)),
+            operator,
+            right: Box::new(expr(*right)),
+        },
+        Expr::Map { id, brace, entries } => Expr::Map {
+            id,
+            brace,
+            entries: entries
+                .into_iter()
+                .map(|(key, value)| (expr(key), expr(value)))
+                .collect(),
+        },
+        Expr::Match {
+            id,
+            keyword,
+            subject,
+            arms,
+        } => Expr::Match {
+            id,
+            keyword,
+            subject: Box::new(expr(*subject)),
+            arms: arms
+                .into_iter()
+                .map(|(test, body)| (pattern(test), expr(body)))
+                .collect(),
+        },
+        Expr::Set {
+            id,
+            object,
+            name,
+            value,
+        } => Expr::Set {
+            id,
+            object: Box::new(expr(*object)),
+            name,
+            value: Box::new(expr(*value)),
+        },
+        Expr::SetIndex {
+            id,
+            object,
+            bracket,
+            index,
+            value,
+        } => Expr::SetIndex {
+            id,
+            object: Box::new(expr(*object)),
+            bracket,
+            index: Box::new(expr(*index)),
+            value: Box::new(expr(*value)),
+        },
+        exp @ (Expr::Literal { id: _, value: _ }
+        | Expr::Super {
+            id: _,
+            keyword: _,
+            method: _,
+        }
+        | Expr::This { id: _, keyword: _ }
+        | Expr::Variable { id: _, name: _ }) => exp,
+    }
+}
+
+// an expression that is only tested for truthiness, where !!x means x
+fn condition(exp: Expr) -> Expr {
+    match exp {
+        Expr::Unary {
+            id,
+            operator,
+            right,
+        } if operator.token_type == TokenType::Bang => match *right {
+            Expr::Unary {
+                id: _,
+                operator: inner,
+                right,
+            } if inner.token_type == TokenType::Bang => return condition(*right),
+            right => {
+                return Expr::Unary {
+                    id,
+                    operator,
+                    right: Box::new(right),
+                }
+            }
+        },
+        exp => return exp,
+    }
+}
+
+fn constant(exp: &Expr) -> Option<&LiteralValue> {
+    match exp {
+        Expr::Literal { id: _, value } => return Some(value),
+        _ => return None,
+    }
+}
+
+fn is_truthy(value: &LiteralValue) -> bool {
+    return value.is_truthy() == Ok(LiteralValue::True);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn optimized(source: &str) -> Vec<String> {
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        return optimize(statements)
+            .iter()
+            .map(|statement| statement.tostring())
+            .collect();
+    }
+
+    #[test]
+    fn folds_constants() {
+        assert_eq!(
+            optimized("print 1 + 2 * 3; print \"a\" + \"b\"; print (1 < 2) == !nil; print -(4);"),
+            vec!["(print 7)", "(print \"ab\")", "(print true)", "(print -4)"]
+        );
+    }
+
+    #[test]
+    fn keeps_expressions_that_fail() {
+        assert_eq!(
+            optimized("print 1 + \"a\"; print x + 1 * 2;"),
+            vec!["(print (+ 1 \"a\"))", "(print (+ (var x) 2))"]
+        );
+    }
+
+    #[test]
+    fn removes_dead_branches() {
+        assert_eq!(
+            optimized("if (false) print 1; else print 2; while (1 > 2) print 3; if (!!x) print 4; while (true) if (nil) print 5;"),
+            vec![
+                "(print 2)",
+                "(if (var x) (print 4))",
+                "(while true (block))"
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_ids() {
+        let tokens = Scanner::new("print (1 + 2);").scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        let id = match &statements[0] {
+            Stmt::Print { expression } => expression.get_id(),
+            _ => panic!("expected a print statement"),
+        };
+        match &optimize(statements)[0] {
+            Stmt::Print { expression } => assert_eq!(expression.get_id(), id),
+            _ => panic!("expected a print statement"),
+        }
+    }
+}
@@ -0,0 +1,22 @@
+use crate::error::RuntimeError;
+use std::cell::RefCell;
+use std::io::{self, Write};
+
+thread_local! {
+    // where print statements write, stdout unless a host installed a writer
+    static OUTPUT: RefCell<Option<Box<dyn Write>>> = const { RefCell::new(None) };
+}
+
+// installs the writer for print statements and hands back the one it replaces,
+// None goes back to stdout
+pub fn redirect(writer: Option<Box<dyn Write>>) -> Option<Box<dyn Write>> {
+    return OUTPUT.with(|output| output.replace(writer));
+}
+
+pub fn print_line(text: &str) -> Result<(), RuntimeError> {
+    let result = OUTPUT.with(|output| match output.borrow_mut().as_mut() {
+        Some(writer) => writeln!(writer, "{}", text),
+        None => writeln!(io::stdout(), "{}", text),
+    });
+    return result.map_err(|err| RuntimeError::new(format!("could not print: {}", err)));
+}
@@ -1,6 +1,8 @@
+use crate::diagnostic::{Diagnostic, DiagnosticKind};
 use crate::expr;
 use crate::scanner;
 use crate::stmt;
+use std::collections::HashMap;
 
 #[derive(Debug)]
 enum FunctionKind {
@@ -13,6 +15,8 @@ pub struct Parser {
     tokens: Vec<scanner::Token>,
     current: usize,
     next_id: usize,
+    // the line each full expression starts on, by expression id
+    lines: HashMap<usize, usize>,
 }
 
 impl Parser {
@@ -21,9 +25,25 @@ impl Parser {
             tokens: tokens,
             current: 0,
             next_id: 0,
+            lines: HashMap::new(),
         }
     }
 
+    // expression ids key the resolver's results, so programs that share one
+    // interpreter, like REPL entries, must not reuse each other's ids
+    pub fn starting_at(mut self, first_id: usize) -> Self {
+        self.next_id = first_id;
+        return self;
+    }
+
+    pub fn next_id(&self) -> usize {
+        return self.next_id;
+    }
+
+    pub fn lines(&self) -> &HashMap<usize, usize> {
+        return &self.lines;
+    }
+
     fn get_id(&mut self) -> usize {
         let id = self.next_id;
         self.next_id += 1;
@@ -31,7 +51,7 @@ impl Parser {
         return id;
     }
 
-    pub fn parse(&mut self) -> Result<Vec<stmt::Stmt>, String> {
+    pub fn parse(&mut self) -> Result<Vec<stmt::Stmt>, Vec<Diagnostic>> {
         let mut stmts = vec![];
         let mut errs = vec![];
 
@@ -49,27 +69,41 @@ impl Parser {
         if errs.len() == 0 {
             return Ok(stmts);
         } else {
-            return Err(errs.join("\n"));
+            return Err(errs);
         }
     }
 
-    fn declaration(&mut self) -> Result<stmt::Stmt, String> {
+    fn declaration(&mut self) -> Result<stmt::Stmt, Diagnostic> {
         if self.match_token(scanner::TokenType::Var) {
             return self.var_declaration();
         } else if self.match_token(scanner::TokenType::Fun) {
             self.function(FunctionKind::Function)
         } else if self.match_token(scanner::TokenType::Class) {
             self.class_declaration()
+        } else if self.match_token(scanner::TokenType::Import) {
+            self.import_declaration()
         } else {
             return self.statement();
         }
     }
 
-    fn class_declaration(&mut self) -> Result<stmt::Stmt, String> {
+    fn class_declaration(&mut self) -> Result<stmt::Stmt, Diagnostic> {
         let name = self.consume(
             scanner::TokenType::Identifier,
             "expected name after 'class' keyword",
         )?;
+
+        let superclass = if self.match_token(scanner::TokenType::Less) {
+            let super_name =
+                self.consume(scanner::TokenType::Identifier, "expected superclass name")?;
+            Some(expr::Expr::Variable {
+                id: self.get_id(),
+                name: super_name,
+            })
+        } else {
+            None
+        };
+
         self.consume(
             scanner::TokenType::LeftBrace,
             "expected '{' before class body",
@@ -86,10 +120,14 @@ impl Parser {
             "expected '}' after class body",
         )?;
 
-        return Ok(stmt::Stmt::Class { name, methods });
+        return Ok(stmt::Stmt::Class {
+            name,
+            superclass,
+            methods,
+        });
     }
 
-    fn function(&mut self, kind: FunctionKind) -> Result<stmt::Stmt, String> {
+    fn function(&mut self, kind: FunctionKind) -> Result<stmt::Stmt, Diagnostic> {
         let name = self.consume(
             scanner::TokenType::Identifier,
             &format!("expected {kind:?} name"),
@@ -101,18 +139,17 @@ impl Parser {
         )?;
 
         let mut params = vec![];
+        let mut param_annotations = vec![];
         if !self.check(scanner::TokenType::RightParen) {
             loop {
                 if params.len() >= 255 {
-                    let location = self.peek().line_number;
-                    return Err(format!(
-                        "line {location}: cannot have more 255 or more arguments"
-                    ));
+                    return Err(self.error("cannot have 255 or more arguments"));
                 }
 
                 let param =
                     self.consume(scanner::TokenType::Identifier, "expected parameter name")?;
                 params.push(param);
+                param_annotations.push(self.annotation()?);
 
                 if !self.match_token(scanner::TokenType::Comma) {
                     break;
@@ -124,6 +161,14 @@ impl Parser {
             scanner::TokenType::RightParen,
             "expected ')' after parameters",
         )?;
+        let return_annotation = if self.match_token(scanner::TokenType::Arrow) {
+            Some(self.consume(
+                scanner::TokenType::Identifier,
+                "expected a return type after '->'",
+            )?)
+        } else {
+            None
+        };
 
         self.consume(
             scanner::TokenType::LeftBrace,
@@ -135,11 +180,34 @@ impl Parser {
             _ => panic!("block statement parsed something that was not a block"),
         };
 
-        return Ok(stmt::Stmt::Function { name, params, body });
+        return Ok(stmt::Stmt::Function {
+            name,
+            params,
+            param_annotations,
+            return_annotation,
+            body,
+        });
+    }
+
+    fn import_declaration(&mut self) -> Result<stmt::Stmt, Diagnostic> {
+        // import "path.jlox" as name;
+        let path = self.consume(
+            scanner::TokenType::StringLit,
+            "expected a path string after 'import'",
+        )?;
+        self.consume(scanner::TokenType::As, "expected 'as' after import path")?;
+        let name = self.consume(scanner::TokenType::Identifier, "expected module name")?;
+        self.consume(
+            scanner::TokenType::Semicolon,
+            "expected ';' after import declaration",
+        )?;
+
+        return Ok(stmt::Stmt::Import { path, name });
     }
 
-    fn var_declaration(&mut self) -> Result<stmt::Stmt, String> {
+    fn var_declaration(&mut self) -> Result<stmt::Stmt, Diagnostic> {
         let token = self.consume(scanner::TokenType::Identifier, "expected variable name")?;
+        let annotation = self.annotation()?;
 
         let initializer;
         if self.match_token(scanner::TokenType::Equal) {
@@ -158,11 +226,24 @@ impl Parser {
 
         return Ok(stmt::Stmt::Var {
             name: token,
+            annotation,
             initializer: initializer,
         });
     }
 
-    fn statement(&mut self) -> Result<stmt::Stmt, String> {
+    // an optional `: Type` after a variable or parameter name
+    fn annotation(&mut self) -> Result<Option<scanner::Token>, Diagnostic> {
+        if !self.match_token(scanner::TokenType::Colon) {
+            return Ok(None);
+        }
+        let type_name = self.consume(
+            scanner::TokenType::Identifier,
+            "expected a type name after ':'",
+        )?;
+        return Ok(Some(type_name));
+    }
+
+    fn statement(&mut self) -> Result<stmt::Stmt, Diagnostic> {
         if self.match_token(scanner::TokenType::Print) {
             return self.print_statement();
         } else if self.match_token(scanner::TokenType::LeftBrace) {
@@ -175,12 +256,27 @@ impl Parser {
             return self.for_statement();
         } else if self.match_token(scanner::TokenType::Return) {
             return self.return_statement();
+        } else if self.match_token(scanner::TokenType::Throw) {
+            return self.throw_statement();
+        } else if self.match_token(scanner::TokenType::Try) {
+            return self.try_statement();
+        } else if self.match_token(scanner::TokenType::Break) {
+            let keyword = self.previous();
+            self.consume(scanner::TokenType::Semicolon, "expected ';' after 'break'")?;
+            return Ok(stmt::Stmt::Break { keyword });
+        } else if self.match_token(scanner::TokenType::Continue) {
+            let keyword = self.previous();
+            self.consume(
+                scanner::TokenType::Semicolon,
+                "expected ';' after 'continue'",
+            )?;
+            return Ok(stmt::Stmt::Continue { keyword });
         } else {
             return self.expression_statement();
         }
     }
 
-    fn return_statement(&mut self) -> Result<stmt::Stmt, String> {
+    fn return_statement(&mut self) -> Result<stmt::Stmt, Diagnostic> {
         let keyword = self.previous();
         let value;
         if !self.check(scanner::TokenType::Semicolon) {
@@ -196,7 +292,7 @@ impl Parser {
         return Ok(stmt::Stmt::ReturnStmt { keyword, value });
     }
 
-    fn for_statement(&mut self) -> Result<stmt::Stmt, String> {
+    fn for_statement(&mut self) -> Result<stmt::Stmt, Diagnostic> {
         self.consume(scanner::TokenType::LeftParen, "expected '(' after 'for'")?;
 
         let initializer;
@@ -239,15 +335,6 @@ impl Parser {
 
         let mut body = self.statement()?;
 
-        if let Some(incr) = increment {
-            body = stmt::Stmt::Block {
-                statements: vec![
-                    Box::new(body),
-                    Box::new(stmt::Stmt::Expression { expression: incr }),
-                ],
-            };
-        }
-
         let cond;
 
         match condition {
@@ -263,6 +350,7 @@ impl Parser {
         body = stmt::Stmt::WhileStmt {
             condition: cond,
             body: Box::new(body),
+            increment,
         };
 
         if let Some(init) = initializer {
@@ -274,7 +362,61 @@ impl Parser {
         return Ok(body);
     }
 
-    fn while_statement(&mut self) -> Result<stmt::Stmt, String> {
+    fn throw_statement(&mut self) -> Result<stmt::Stmt, Diagnostic> {
+        let keyword = self.previous();
+        let value = self.expression()?;
+        self.consume(
+            scanner::TokenType::Semicolon,
+            "expected ';' after thrown value",
+        )?;
+
+        return Ok(stmt::Stmt::Throw { keyword, value });
+    }
+
+    fn try_statement(&mut self) -> Result<stmt::Stmt, Diagnostic> {
+        let keyword = self.previous();
+        self.consume(scanner::TokenType::LeftBrace, "expected '{' after 'try'")?;
+        let body = Box::new(self.block_statement()?);
+
+        let catch = if self.match_token(scanner::TokenType::Catch) {
+            self.consume(scanner::TokenType::LeftParen, "expected '(' after 'catch'")?;
+            let name = self.consume(
+                scanner::TokenType::Identifier,
+                "expected a variable name in 'catch'",
+            )?;
+            self.consume(
+                scanner::TokenType::RightParen,
+                "expected ')' after catch variable",
+            )?;
+            self.consume(scanner::TokenType::LeftBrace, "expected '{' after 'catch'")?;
+            Some((name, Box::new(self.block_statement()?)))
+        } else {
+            None
+        };
+
+        let finally = if self.match_token(scanner::TokenType::Finally) {
+            self.consume(
+                scanner::TokenType::LeftBrace,
+                "expected '{' after 'finally'",
+            )?;
+            Some(Box::new(self.block_statement()?))
+        } else {
+            None
+        };
+
+        if catch.is_none() && finally.is_none() {
+            return Err(self.error("expected 'catch' or 'finally' after 'try' block"));
+        }
+
+        return Ok(stmt::Stmt::Try {
+            keyword,
+            body,
+            catch,
+            finally,
+        });
+    }
+
+    fn while_statement(&mut self) -> Result<stmt::Stmt, Diagnostic> {
         self.consume(scanner::TokenType::LeftParen, "expected '(' after 'while'")?;
         let condition = self.expression()?;
         self.consume(scanner::TokenType::RightParen, "expected ')' after 'while'")?;
@@ -283,10 +425,11 @@ impl Parser {
         return Ok(stmt::Stmt::WhileStmt {
             condition,
             body: Box::new(body),
+            increment: None,
         });
     }
 
-    fn if_statement(&mut self) -> Result<stmt::Stmt, String> {
+    fn if_statement(&mut self) -> Result<stmt::Stmt, Diagnostic> {
         self.consume(scanner::TokenType::LeftParen, "expected '(' after 'if'")?;
         let predicate = self.expression()?;
         self.consume(
@@ -310,7 +453,7 @@ impl Parser {
         });
     }
 
-    fn block_statement(&mut self) -> Result<stmt::Stmt, String> {
+    fn block_statement(&mut self) -> Result<stmt::Stmt, Diagnostic> {
         let mut statements = vec![];
 
         while !self.check(scanner::TokenType::RightBrace) && !self.is_at_end() {
@@ -323,13 +466,13 @@ impl Parser {
         return Ok(stmt::Stmt::Block { statements });
     }
 
-    fn print_statement(&mut self) -> Result<stmt::Stmt, String> {
+    fn print_statement(&mut self) -> Result<stmt::Stmt, Diagnostic> {
         let value = self.expression()?;
         self.consume(scanner::TokenType::Semicolon, "Expected a ';' after value.")?;
         return Ok(stmt::Stmt::Print { expression: value });
     }
 
-    fn expression_statement(&mut self) -> Result<stmt::Stmt, String> {
+    fn expression_statement(&mut self) -> Result<stmt::Stmt, Diagnostic> {
         let exp = self.expression()?;
         self.consume(
             scanner::TokenType::Semicolon,
@@ -338,11 +481,14 @@ impl Parser {
         return Ok(stmt::Stmt::Expression { expression: exp });
     }
 
-    fn expression(&mut self) -> Result<expr::Expr, String> {
-        return self.assignment();
+    fn expression(&mut self) -> Result<expr::Expr, Diagnostic> {
+        let line = self.tokens[self.current].line_number;
+        let exp = self.assignment()?;
+        self.lines.insert(exp.get_id(), line);
+        return Ok(exp);
     }
 
-    fn function_expression(&mut self) -> Result<expr::Expr, String> {
+    fn function_expression(&mut self) -> Result<expr::Expr, Diagnostic> {
         let paren = self.consume(
             scanner::TokenType::LeftParen,
             "expected '(' after anonymous function",
@@ -352,10 +498,7 @@ impl Parser {
         if !self.check(scanner::TokenType::RightParen) {
             loop {
                 if parameters.len() >= 255 {
-                    let location = self.peek().line_number;
-                    return Err(format!(
-                        "line {location}: cannot have more 255 or more arguments"
-                    ));
+                    return Err(self.error("cannot have 255 or more arguments"));
                 }
 
                 let param =
@@ -390,10 +533,11 @@ impl Parser {
         });
     }
 
-    fn assignment(&mut self) -> Result<expr::Expr, String> {
+    fn assignment(&mut self) -> Result<expr::Expr, Diagnostic> {
         let exp = self.pipe()?;
 
         if self.match_token(scanner::TokenType::Equal) {
+            let equals = self.previous();
             let value = self.expression()?;
 
             match exp {
@@ -414,14 +558,30 @@ impl Parser {
                     name,
                     value: Box::new(value),
                 }),
-                _ => Err("invalid assignment target.".to_string()),
+                expr::Expr::Index {
+                    id: _,
+                    object,
+                    bracket,
+                    index,
+                } => Ok(expr::Expr::SetIndex {
+                    id: self.get_id(),
+                    object,
+                    bracket,
+                    index,
+                    value: Box::new(value),
+                }),
+                _ => Err(Diagnostic::at_token(
+                    DiagnosticKind::Parse,
+                    "invalid assignment target",
+                    &equals,
+                )),
             }
         } else {
             return Ok(exp);
         }
     }
 
-    fn pipe(&mut self) -> Result<expr::Expr, String> {
+    fn pipe(&mut self) -> Result<expr::Expr, Diagnostic> {
         // exp |> f
         // exp |> f1 |> f2 |> ...
         // exp |> fun(a) { return a + 1; }
@@ -441,7 +601,7 @@ impl Parser {
         return Ok(exp);
     }
 
-    fn or(&mut self) -> Result<expr::Expr, String> {
+    fn or(&mut self) -> Result<expr::Expr, Diagnostic> {
         let mut exp = self.and()?;
 
         while self.match_token(scanner::TokenType::Or) {
@@ -459,7 +619,7 @@ impl Parser {
         return Ok(exp);
     }
 
-    fn and(&mut self) -> Result<expr::Expr, String> {
+    fn and(&mut self) -> Result<expr::Expr, Diagnostic> {
         let mut exp = self.equality()?;
 
         while self.match_token(scanner::TokenType::And) {
@@ -476,7 +636,7 @@ impl Parser {
         return Ok(exp);
     }
 
-    fn equality(&mut self) -> Result<expr::Expr, String> {
+    fn equality(&mut self) -> Result<expr::Expr, Diagnostic> {
         let mut exp = self.comparison()?;
 
         while self.match_tokens(&[
@@ -495,7 +655,7 @@ impl Parser {
         return Ok(exp);
     }
 
-    fn comparison(&mut self) -> Result<expr::Expr, String> {
+    fn comparison(&mut self) -> Result<expr::Expr, Diagnostic> {
         let mut exp = self.term()?;
 
         while self.match_tokens(&[
@@ -517,7 +677,7 @@ impl Parser {
         return Ok(exp);
     }
 
-    fn term(&mut self) -> Result<expr::Expr, String> {
+    fn term(&mut self) -> Result<expr::Expr, Diagnostic> {
         let mut exp = self.factor()?;
 
         while self.match_tokens(&[scanner::TokenType::Minus, scanner::TokenType::Plus]) {
@@ -534,7 +694,7 @@ impl Parser {
         return Ok(exp);
     }
 
-    fn factor(&mut self) -> Result<expr::Expr, String> {
+    fn factor(&mut self) -> Result<expr::Expr, Diagnostic> {
         let mut exp = self.unary()?;
         while self.match_tokens(&[scanner::TokenType::Slash, scanner::TokenType::Star]) {
             let op = self.previous();
@@ -550,7 +710,7 @@ impl Parser {
         return Ok(exp);
     }
 
-    fn unary(&mut self) -> Result<expr::Expr, String> {
+    fn unary(&mut self) -> Result<expr::Expr, Diagnostic> {
         if self.match_tokens(&[scanner::TokenType::Bang, scanner::TokenType::Minus]) {
             let op = self.previous();
             let rhs = self.unary()?;
@@ -564,7 +724,7 @@ impl Parser {
         }
     }
 
-    fn call(&mut self) -> Result<expr::Expr, String> {
+    fn call(&mut self) -> Result<expr::Expr, Diagnostic> {
         let mut exp = self.primary()?;
 
         loop {
@@ -580,6 +740,16 @@ impl Parser {
                     object: Box::new(exp),
                     name,
                 };
+            } else if self.match_token(scanner::TokenType::LeftBracket) {
+                let bracket = self.previous();
+                let index = self.expression()?;
+                self.consume(scanner::TokenType::RightBracket, "expected ']' after index")?;
+                exp = expr::Expr::Index {
+                    id: self.get_id(),
+                    object: Box::new(exp),
+                    bracket,
+                    index: Box::new(index),
+                };
             } else {
                 break;
             }
@@ -588,7 +758,7 @@ impl Parser {
         return Ok(exp);
     }
 
-    fn finish_call(&mut self, callee: expr::Expr) -> Result<expr::Expr, String> {
+    fn finish_call(&mut self, callee: expr::Expr) -> Result<expr::Expr, Diagnostic> {
         let mut arguments = vec![];
 
         if !self.check(scanner::TokenType::RightParen) {
@@ -596,10 +766,7 @@ impl Parser {
                 let arg = self.expression()?;
                 arguments.push(arg);
                 if arguments.len() >= 255 {
-                    let location = self.peek().line_number;
-                    return Err(format!(
-                        "line {location}: cannot have more 255 or more arguments"
-                    ));
+                    return Err(self.error("cannot have 255 or more arguments"));
                 }
 
                 if !self.match_token(scanner::TokenType::Comma) {
@@ -621,7 +788,7 @@ impl Parser {
         });
     }
 
-    fn primary(&mut self) -> Result<expr::Expr, String> {
+    fn primary(&mut self) -> Result<expr::Expr, Diagnostic> {
         let token = self.peek();
 
         let result;
@@ -646,6 +813,10 @@ impl Parser {
                     value: expr::LiteralValue::from_token(token),
                 };
             }
+            scanner::TokenType::Interpolation => {
+                self.advance();
+                result = self.interpolation()?;
+            }
             scanner::TokenType::Identifier => {
                 self.advance();
                 result = expr::Expr::Variable {
@@ -657,27 +828,251 @@ impl Parser {
                 self.advance();
                 result = self.function_expression()?;
             }
-            _ => return Err("expected expression".to_string()),
+            scanner::TokenType::This => {
+                self.advance();
+                result = expr::Expr::This {
+                    id: self.get_id(),
+                    keyword: self.previous(),
+                };
+            }
+            scanner::TokenType::LeftBracket => {
+                self.advance();
+                result = self.list_literal()?;
+            }
+            scanner::TokenType::LeftBrace => {
+                self.advance();
+                result = self.map_literal()?;
+            }
+            scanner::TokenType::Match => {
+                self.advance();
+                result = self.match_expression()?;
+            }
+            scanner::TokenType::Super => {
+                self.advance();
+                let keyword = self.previous();
+                self.consume(scanner::TokenType::Dot, "expected '.' after 'super'")?;
+                let method = self.consume(
+                    scanner::TokenType::Identifier,
+                    "expected superclass method name",
+                )?;
+                result = expr::Expr::Super {
+                    id: self.get_id(),
+                    keyword,
+                    method,
+                };
+            }
+            _ => return Err(self.error("expected expression")),
         }
 
         return Ok(result);
     }
 
+    fn interpolation(&mut self) -> Result<expr::Expr, Diagnostic> {
+        // "a ${b} c" is scanned as Interpolation("a "), b, StringLit(" c")
+        let quote = self.previous();
+        let mut parts = vec![];
+        let mut segment = quote.clone();
+
+        loop {
+            parts.push(expr::Expr::Literal {
+                id: self.get_id(),
+                value: expr::LiteralValue::from_token(segment),
+            });
+            if self.previous().token_type == scanner::TokenType::StringLit {
+                break;
+            }
+
+            // the segment after an interpolation starts at its closing brace
+            if self.peek().lexeme.starts_with('}') {
+                return Err(self.error("expected an expression inside '${}'"));
+            }
+            parts.push(self.expression()?);
+            if !self.match_token(scanner::TokenType::Interpolation) {
+                self.consume(
+                    scanner::TokenType::StringLit,
+                    "expected '}' after interpolated expression",
+                )?;
+            }
+            segment = self.previous();
+        }
+
+        return Ok(expr::Expr::Interpolation {
+            id: self.get_id(),
+            quote,
+            parts,
+        });
+    }
+
+    fn list_literal(&mut self) -> Result<expr::Expr, Diagnostic> {
+        // [a, b, c]
+        let bracket = self.previous();
+        let mut elements = vec![];
+
+        if !self.check(scanner::TokenType::RightBracket) {
+            loop {
+                elements.push(self.expression()?);
+                if !self.match_token(scanner::TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(
+            scanner::TokenType::RightBracket,
+            "expected ']' after list elements",
+        )?;
+
+        return Ok(expr::Expr::List {
+            id: self.get_id(),
+            bracket,
+            elements,
+        });
+    }
+
+    fn map_literal(&mut self) -> Result<expr::Expr, Diagnostic> {
+        // {key: value, ...}
+        let brace = self.previous();
+        let mut entries = vec![];
+
+        if !self.check(scanner::TokenType::RightBrace) {
+            loop {
+                let key = self.expression()?;
+                self.consume(scanner::TokenType::Colon, "expected ':' after map key")?;
+                let value = self.expression()?;
+                entries.push((key, value));
+                if !self.match_token(scanner::TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(
+            scanner::TokenType::RightBrace,
+            "expected '}' after map entries",
+        )?;
+
+        return Ok(expr::Expr::Map {
+            id: self.get_id(),
+            brace,
+            entries,
+        });
+    }
+
+    fn match_expression(&mut self) -> Result<expr::Expr, Diagnostic> {
+        // match (subject) { pattern => body, ... }
+        let keyword = self.previous();
+        self.consume(scanner::TokenType::LeftParen, "expected '(' after 'match'")?;
+        let subject = self.expression()?;
+        self.consume(
+            scanner::TokenType::RightParen,
+            "expected ')' after match subject",
+        )?;
+        self.consume(
+            scanner::TokenType::LeftBrace,
+            "expected '{' before match arms",
+        )?;
+        let mut arms = vec![];
+
+        // a trailing comma is allowed, arms are often one per line
+        while !self.check(scanner::TokenType::RightBrace) {
+            let pattern = self.pattern()?;
+            self.consume(
+                scanner::TokenType::FatArrow,
+                "expected '=>' after match pattern",
+            )?;
+            let body = self.expression()?;
+            arms.push((pattern, body));
+            if !self.match_token(scanner::TokenType::Comma) {
+                break;
+            }
+        }
+        self.consume(
+            scanner::TokenType::RightBrace,
+            "expected '}' after match arms",
+        )?;
+        if arms.is_empty() {
+            return Err(Diagnostic::at_token(
+                DiagnosticKind::Parse,
+                "expected at least one match arm",
+                &keyword,
+            ));
+        }
+
+        return Ok(expr::Expr::Match {
+            id: self.get_id(),
+            keyword,
+            subject: Box::new(subject),
+            arms,
+        });
+    }
+
+    fn pattern(&mut self) -> Result<expr::Pattern, Diagnostic> {
+        if self.match_token(scanner::TokenType::Identifier) {
+            let name = self.previous();
+            if self.match_token(scanner::TokenType::Identifier) {
+                return Ok(expr::Pattern::Class {
+                    class: name,
+                    name: self.previous(),
+                });
+            }
+            return Ok(expr::Pattern::Binding { name });
+        }
+
+        if self.match_token(scanner::TokenType::Minus) {
+            let operator = self.previous();
+            let number = self.consume(
+                scanner::TokenType::NumberLit,
+                "expected a number after '-' in a pattern",
+            )?;
+            let right = expr::Expr::Literal {
+                id: self.get_id(),
+                value: expr::LiteralValue::from_token(number),
+            };
+            return Ok(expr::Pattern::Literal {
+                value: expr::Expr::Unary {
+                    id: self.get_id(),
+                    operator,
+                    right: Box::new(right),
+                },
+            });
+        }
+
+        if self.match_tokens(&[
+            scanner::TokenType::False,
+            scanner::TokenType::True,
+            scanner::TokenType::Nil,
+            scanner::TokenType::NumberLit,
+            scanner::TokenType::StringLit,
+        ]) {
+            return Ok(expr::Pattern::Literal {
+                value: expr::Expr::Literal {
+                    id: self.get_id(),
+                    value: expr::LiteralValue::from_token(self.previous()),
+                },
+            });
+        }
+
+        return Err(self.error("expected a pattern"));
+    }
+
     fn consume(
         &mut self,
         token_type: scanner::TokenType,
         msg: &str,
-    ) -> Result<scanner::Token, String> {
+    ) -> Result<scanner::Token, Diagnostic> {
         let token = self.peek();
         if token.token_type == token_type {
             self.advance();
             let token = self.previous();
             return Ok(token);
         } else {
-            return Err(format!("line {}: {}", token.line_number, msg));
+            return Err(Diagnostic::at_token(DiagnosticKind::Parse, msg, &token));
         }
     }
 
+    fn error(&mut self, msg: &str) -> Diagnostic {
+        let token = self.peek();
+        return Diagnostic::at_token(DiagnosticKind::Parse, msg, &token);
+    }
+
     fn check(&mut self, typ: scanner::TokenType) -> bool {
         return self.peek().token_type == typ;
     }
@@ -736,11 +1131,14 @@ impl Parser {
             match self.peek().token_type {
                 scanner::TokenType::Class
                 | scanner::TokenType::Fun
+                | scanner::TokenType::Import
                 | scanner::TokenType::Var
                 | scanner::TokenType::For
                 | scanner::TokenType::If
                 | scanner::TokenType::While
                 | scanner::TokenType::Print
+                | scanner::TokenType::Throw
+                | scanner::TokenType::Try
                 | scanner::TokenType::Return => return,
                 _ => (),
             }
@@ -762,6 +1160,8 @@ mod tests {
             lexeme: "1".to_string(),
             literal: Some(LiteralValue::FValue(1.0)),
             line_number: 0,
+            column: 0,
+            offset: 0,
         };
 
         let plus = scanner::Token {
@@ -769,6 +1169,8 @@ mod tests {
             lexeme: "+".to_string(),
             literal: None,
             line_number: 0,
+            column: 0,
+            offset: 0,
         };
 
         let two = scanner::Token {
@@ -776,6 +1178,8 @@ mod tests {
             lexeme: "2".to_string(),
             literal: Some(LiteralValue::FValue(2.0)),
             line_number: 0,
+            column: 0,
+            offset: 0,
         };
 
         let semicolon = scanner::Token {
@@ -783,6 +1187,8 @@ mod tests {
             lexeme: ";".to_string(),
             literal: None,
             line_number: 0,
+            column: 0,
+            offset: 0,
         };
 
         let eof = scanner::Token {
@@ -790,6 +1196,8 @@ mod tests {
             lexeme: "".to_string(),
             literal: None,
             line_number: 0,
+            column: 0,
+            offset: 0,
         };
 
         let tokens = vec![one, plus, two, semicolon, eof];
@@ -853,4 +1261,38 @@ mod tests {
 
         assert_eq!(string_exp, "(+ 2 (* 3 4))");
     }
+
+    #[test]
+    fn test_index_assignment() {
+        let source = "a[1] = b[0][2];";
+        let mut scanner = Scanner::new(source);
+        scanner.scan_tokens().unwrap();
+        let tokens = scanner.tokens;
+        let mut parser = Parser::new(tokens);
+        let parsed_exp = parser.parse().unwrap();
+        assert_eq!(parsed_exp.len(), 1);
+        let string_exp = parsed_exp[0].tostring();
+
+        assert_eq!(
+            string_exp,
+            "(set-index (var a) 1 to (index (index (var b) 0) 2))"
+        );
+    }
+
+    #[test]
+    fn test_match_patterns() {
+        let source = "match (x) { -1 => a, \"s\" => b, Point p => p, _ => nil, };";
+        let mut scanner = Scanner::new(source);
+        scanner.scan_tokens().unwrap();
+        let tokens = scanner.tokens;
+        let mut parser = Parser::new(tokens);
+        let parsed_exp = parser.parse().unwrap();
+        assert_eq!(parsed_exp.len(), 1);
+        let string_exp = parsed_exp[0].tostring();
+
+        assert_eq!(
+            string_exp,
+            "(match (var x) ((- 1) (var a)) (\"s\" (var b)) (Point p (var p)) (_ nil))"
+        );
+    }
 }
@@ -0,0 +1,261 @@
+use crate::checker::Checker;
+use crate::debugger;
+use crate::diagnostic::Diagnostic;
+use crate::environment::Environment;
+use crate::interpreter::Interpreter;
+use crate::optimizer;
+use crate::parser::Parser;
+use crate::resolver::Resolver;
+use crate::scanner::Scanner;
+use crate::stmt::Stmt;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// the top level of the script, profiled like a function that is called once
+const SCRIPT: &str = "<script>";
+
+thread_local! {
+    // the profiler attached to the running script, the tree-walking
+    // interpreter reports to it through the same hooks as the debugger
+    static PROFILER: RefCell<Option<Profiler>> = const { RefCell::new(None) };
+}
+
+#[derive(Clone, Copy, Default)]
+struct Stats {
+    calls: usize,
+    // time from entering to leaving, counted once for recursive calls
+    inclusive: Duration,
+    // inclusive time minus the time spent in the calls it made
+    exclusive: Duration,
+}
+
+struct Call {
+    function: String,
+    start: Instant,
+    children: Duration,
+}
+
+struct Profiler {
+    // the line each full expression starts on, from the parser
+    lines: HashMap<usize, usize>,
+    globals: Environment,
+    calls: Vec<Call>,
+    functions: HashMap<String, Stats>,
+    hits: HashMap<usize, usize>,
+    // exclusive time per chain of active calls, outermost first
+    stacks: HashMap<Vec<String>, Duration>,
+}
+
+pub struct Profile {
+    functions: HashMap<String, Stats>,
+    hits: HashMap<usize, usize>,
+    stacks: HashMap<Vec<String>, Duration>,
+}
+
+// runs a script on the tree-walking interpreter and records where its time goes
+pub fn run(source: &str) -> Result<Profile, Vec<Diagnostic>> {
+    let tokens = Scanner::new(source).scan_tokens()?;
+    let mut parser = Parser::new(tokens);
+    let statements = optimizer::optimize(parser.parse()?);
+    let locals = Resolver::new()
+        .resolve(&statements.iter().collect())
+        .map_err(|diagnostic| vec![diagnostic])?;
+    Checker::new().check(&statements.iter().collect())?;
+
+    let mut interp = Interpreter::new();
+    interp.resolve(locals);
+    let profiler = Profiler {
+        lines: parser.lines().clone(),
+        globals: interp.environment.clone(),
+        calls: vec![],
+        functions: HashMap::new(),
+        hits: HashMap::new(),
+        stacks: HashMap::new(),
+    };
+
+    PROFILER.with(|attached| *attached.borrow_mut() = Some(profiler));
+    enter(SCRIPT);
+    let result = interp.interpret(statements.iter().collect());
+    exit();
+    let profiler = PROFILER
+        .with(|attached| attached.borrow_mut().take())
+        .expect("profiler was attached above");
+
+    result.map_err(|err| vec![Diagnostic::from(err)])?;
+    return Ok(Profile {
+        functions: profiler.functions,
+        hits: profiler.hits,
+        stacks: profiler.stacks,
+    });
+}
+
+pub fn on_statement(statement: &Stmt, env: &Environment) {
+    PROFILER.with(|attached| {
+        if let Some(profiler) = attached.borrow_mut().as_mut() {
+            // imported modules number their expressions separately, so their lines are unknown
+            if !env.shares_globals(&profiler.globals) {
+                return;
+            }
+            if let Some(line) = debugger::statement_line(statement, &profiler.lines) {
+                *profiler.hits.entry(line).or_insert(0) += 1;
+            }
+        }
+    });
+}
+
+pub fn enter(function: &str) {
+    PROFILER.with(|attached| {
+        if let Some(profiler) = attached.borrow_mut().as_mut() {
+            profiler.calls.push(Call {
+                function: function.to_string(),
+                start: Instant::now(),
+                children: Duration::ZERO,
+            });
+        }
+    });
+}
+
+pub fn exit() {
+    PROFILER.with(|attached| {
+        if let Some(profiler) = attached.borrow_mut().as_mut() {
+            profiler.exit();
+        }
+    });
+}
+
+impl Profiler {
+    fn exit(&mut self) {
+        let stack: Vec<String> = self
+            .calls
+            .iter()
+            .map(|call| call.function.clone())
+            .collect();
+        let call = match self.calls.pop() {
+            Some(call) => call,
+            None => return,
+        };
+        let elapsed = call.start.elapsed();
+        let exclusive = elapsed.saturating_sub(call.children);
+
+        let recursive = self
+            .calls
+            .iter()
+            .any(|outer| outer.function == call.function);
+        let stats = self.functions.entry(call.function).or_default();
+        stats.calls += 1;
+        stats.exclusive += exclusive;
+        if !recursive {
+            stats.inclusive += elapsed;
+        }
+
+        *self.stacks.entry(stack).or_default() += exclusive;
+        if let Some(caller) = self.calls.last_mut() {
+            caller.children += elapsed;
+        }
+    }
+}
+
+impl Profile {
+    // functions by exclusive time, then the most executed lines
+    pub fn table(&self) -> String {
+        let mut functions: Vec<(&String, &Stats)> = self.functions.iter().collect();
+        functions.sort_by(|a, b| b.1.exclusive.cmp(&a.1.exclusive).then(a.0.cmp(b.0)));
+        let width = functions
+            .iter()
+            .map(|(name, _)| name.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max("function".len());
+
+        let mut table = format!(
+            "{:<width$}  {:>8}  {:>14}  {:>14}\n",
+            "function", "calls", "inclusive (ms)", "exclusive (ms)"
+        );
+        for (name, stats) in functions {
+            table.push_str(&format!(
+                "{:<width$}  {:>8}  {:>14.3}  {:>14.3}\n",
+                name,
+                stats.calls,
+                stats.inclusive.as_secs_f64() * 1000.0,
+                stats.exclusive.as_secs_f64() * 1000.0
+            ));
+        }
+
+        let mut hits: Vec<(&usize, &usize)> = self.hits.iter().collect();
+        hits.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        table.push_str(&format!("\n{:>6}  {:>8}\n", "line", "hits"));
+        for (line, count) in hits {
+            table.push_str(&format!("{:>6}  {:>8}\n", line, count));
+        }
+        return table;
+    }
+
+    // one `outer;inner microseconds` line per call stack, as flamegraph tools read it
+    pub fn collapsed(&self) -> String {
+        let mut stacks: Vec<(String, u128)> = self
+            .stacks
+            .iter()
+            .map(|(stack, time)| (stack.join(";"), time.as_micros()))
+            .filter(|(_, micros)| *micros > 0)
+            .collect();
+        stacks.sort();
+        return stacks
+            .into_iter()
+            .map(|(stack, micros)| format!("{} {}\n", stack, micros))
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_calls_and_lines() {
+        let profile = run("fun f(n) {\n  return n + clock() * 0;\n}\nfor (var i = 0; i < 3; i = i + 1) {\n  f(i);\n}\n").unwrap();
+
+        assert_eq!(profile.functions["f"].calls, 3);
+        assert_eq!(profile.functions["clock"].calls, 3);
+        assert_eq!(profile.functions[SCRIPT].calls, 1);
+        assert_eq!(profile.hits[&2], 3);
+        assert_eq!(profile.hits[&5], 3);
+        assert!(profile.stacks.contains_key(&vec![
+            SCRIPT.to_string(),
+            "f".to_string(),
+            "clock".to_string()
+        ]));
+
+        let table = profile.table();
+        assert!(table.starts_with("function "));
+        assert!(table.contains("\n  line      hits\n"));
+    }
+
+    #[test]
+    fn names_methods_after_their_class() {
+        let profile = run("class A { m() {} }\nclass B { m() {} init() { this.f = clock; } }\nA().m();\nvar b = B();\nb.m();\nb.m();\nb.f();").unwrap();
+
+        assert_eq!(profile.functions["A.m"].calls, 1);
+        assert_eq!(profile.functions["B.m"].calls, 2);
+        assert_eq!(profile.functions["B.init"].calls, 1);
+        // a function stored in a field is not a method
+        assert_eq!(profile.functions["clock"].calls, 1);
+        assert!(!profile.functions.contains_key("m"));
+        assert!(profile
+            .stacks
+            .contains_key(&vec![SCRIPT.to_string(), "B.m".to_string()]));
+    }
+
+    #[test]
+    fn counts_recursive_time_once() {
+        let profile =
+            run("fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }\nfib(10);")
+                .unwrap();
+        let fib = profile.functions["fib"];
+        let script = profile.functions[SCRIPT];
+
+        assert_eq!(fib.calls, 177);
+        assert!(fib.inclusive <= script.inclusive);
+        assert!(fib.exclusive <= fib.inclusive);
+    }
+}
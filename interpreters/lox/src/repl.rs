@@ -0,0 +1,256 @@
+use crate::environment;
+use crate::stmt::Stmt;
+use crate::{execute, parse, report, Backend};
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+const HELP: &str = "\
+:env          list the globals defined in this session
+:load <file>  run a script in this session
+:ast <expr>   print the syntax tree of an expression
+:history      list previous entries
+:reset        forget everything defined in this session
+:help         show this message
+:quit         leave the repl";
+
+struct Repl {
+    backend: Backend,
+    use_vm: bool,
+    json: bool,
+    // expression ids keep counting across entries, see Parser::starting_at
+    next_id: usize,
+    history: Vec<String>,
+    history_file: Option<PathBuf>,
+}
+
+pub fn run_prompt(json: bool, use_vm: bool) -> Result<(), String> {
+    let mut repl = Repl::new(json, use_vm);
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    loop {
+        let mut entry = String::new();
+        loop {
+            print!("{}", if entry.is_empty() { "> " } else { "... " });
+            io::stdout().flush().map_err(|err| err.to_string())?;
+
+            match lines.next() {
+                None => return Ok(()),
+                Some(line) => {
+                    let line = line.map_err(|err| err.to_string())?;
+                    entry.push_str(&line);
+                    entry.push('\n');
+                }
+            }
+            if !is_incomplete(&entry) {
+                break;
+            }
+        }
+
+        let entry = entry.trim();
+        if entry.is_empty() {
+            continue;
+        }
+        repl.remember(entry);
+
+        if let Some(command) = entry.strip_prefix(':') {
+            if !repl.command(command) {
+                return Ok(());
+            }
+        } else {
+            repl.eval(entry);
+        }
+    }
+}
+
+impl Repl {
+    fn new(json: bool, use_vm: bool) -> Self {
+        let history_file =
+            env::var_os("HOME").map(|home| PathBuf::from(home).join(".jlox_history"));
+        let history = match &history_file {
+            Some(path) => fs::read_to_string(path)
+                .map(|contents| contents.lines().map(|line| line.to_string()).collect())
+                .unwrap_or_default(),
+            None => vec![],
+        };
+
+        return Self {
+            backend: Backend::new(use_vm),
+            use_vm,
+            json,
+            next_id: 0,
+            history,
+            history_file,
+        };
+    }
+
+    // multi-line entries are stored on one line so the file stays one entry per line
+    fn remember(&mut self, entry: &str) {
+        let entry = entry
+            .lines()
+            .map(str::trim)
+            .collect::<Vec<&str>>()
+            .join(" ");
+        if let Some(path) = &self.history_file {
+            let file = fs::OpenOptions::new().create(true).append(true).open(path);
+            if let Ok(mut file) = file {
+                let _ = writeln!(file, "{}", entry);
+            }
+        }
+        self.history.push(entry);
+    }
+
+    // returns false when the repl should exit
+    fn command(&mut self, command: &str) -> bool {
+        let (name, arg) = match command.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (command, ""),
+        };
+
+        match name {
+            "env" => {
+                let natives = environment::get_globals();
+                let natives = natives.borrow();
+                let mut globals: Vec<(String, String)> = self
+                    .backend
+                    .globals()
+                    .into_iter()
+                    .filter(|(name, _)| !natives.contains_key(name))
+                    .map(|(name, value)| (name, value.to_string()))
+                    .collect();
+                globals.sort();
+                for (name, value) in globals {
+                    println!("{} = {}", name, value);
+                }
+            }
+            "load" => match fs::read_to_string(arg) {
+                Ok(contents) => self.run(&contents, arg),
+                Err(err) => println!("cannot load '{}': {}", arg, err),
+            },
+            "ast" => match parse_expression(arg) {
+                Some(expression) => println!("{}", expression),
+                None => println!(":ast expects a single expression"),
+            },
+            "history" => {
+                for (i, entry) in self.history.iter().enumerate() {
+                    println!("{:>4}  {}", i + 1, entry);
+                }
+            }
+            "reset" => {
+                self.backend = Backend::new(self.use_vm);
+                self.next_id = 0;
+            }
+            "help" => println!("{}", HELP),
+            "quit" | "q" => return false,
+            _ => println!("unknown command ':{}', try :help", name),
+        }
+        return true;
+    }
+
+    fn eval(&mut self, entry: &str) {
+        let source = with_semicolon(entry);
+        match parse(&source, self.next_id) {
+            Ok((statements, next_id)) => {
+                self.next_id = next_id;
+                let statements: Vec<Stmt> = statements.into_iter().map(echo).collect();
+                if let Err(diagnostics) = execute(&mut self.backend, statements) {
+                    report(diagnostics, &source, "<repl>", self.json);
+                }
+            }
+            Err(diagnostics) => report(diagnostics, &source, "<repl>", self.json),
+        }
+    }
+
+    fn run(&mut self, contents: &str, file: &str) {
+        match parse(contents, self.next_id) {
+            Ok((statements, next_id)) => {
+                self.next_id = next_id;
+                if let Err(diagnostics) = execute(&mut self.backend, statements) {
+                    report(diagnostics, contents, file, self.json);
+                }
+            }
+            Err(diagnostics) => report(diagnostics, contents, file, self.json),
+        }
+    }
+}
+
+// a bare expression statement prints its value instead of discarding it
+fn echo(statement: Stmt) -> Stmt {
+    match statement {
+        Stmt::Expression { expression } => return Stmt::Print { expression },
+        statement => return statement,
+    }
+}
+
+// `1 + 2` is accepted without the trailing semicolon
+pub fn with_semicolon(entry: &str) -> String {
+    if parse(entry, 0).is_err() && !entry.ends_with(';') && !entry.ends_with('}') {
+        return format!("{};", entry);
+    }
+    return entry.to_string();
+}
+
+fn parse_expression(source: &str) -> Option<String> {
+    let (mut statements, _) = parse(&with_semicolon(source), 0).ok()?;
+    match statements.pop() {
+        Some(Stmt::Expression { expression }) if statements.is_empty() => {
+            return Some(expression.to_string());
+        }
+        _ => return None,
+    }
+}
+
+// more lines are needed while a bracket or string is still open, closing
+// brackets that were never opened are left for the parser to report
+pub fn is_incomplete(source: &str) -> bool {
+    let mut depth: i32 = 0;
+    let mut chars = source.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '(' | '{' | '[' => depth += 1,
+            ')' | '}' | ']' => depth -= 1,
+            '"' => loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => {
+                        chars.next();
+                    }
+                    Some(_) => (),
+                    None => return true,
+                }
+            },
+            '/' if chars.peek() == Some(&'/') => while chars.next_if(|c| *c != '\n').is_some() {},
+            _ => (),
+        }
+    }
+
+    return depth > 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_brackets_need_more_input() {
+        assert!(is_incomplete("fun f() {\n"));
+        assert!(is_incomplete("print (1 +\n"));
+        assert!(is_incomplete("var xs = [1,\n"));
+        assert!(is_incomplete("print \"multi\nline"));
+        assert!(!is_incomplete("fun f() { return 1; }\n"));
+        assert!(!is_incomplete("print \"{\";\n"));
+        assert!(!is_incomplete("print \"\\\"{\";\n"));
+        assert!(!is_incomplete("print 1; // {\n"));
+        assert!(!is_incomplete("}\n"));
+    }
+
+    #[test]
+    fn bare_expressions_get_a_semicolon() {
+        assert_eq!(with_semicolon("1 + 2"), "1 + 2;");
+        assert_eq!(with_semicolon("print 1;"), "print 1;");
+        assert_eq!(with_semicolon("fun f() {}"), "fun f() {}");
+    }
+}
@@ -1,3 +1,4 @@
+use crate::diagnostic::{Diagnostic, DiagnosticKind};
 use crate::expr;
 use crate::scanner;
 use crate::stmt;
@@ -8,13 +9,40 @@ enum FunctionType {
     None,
     Function,
     Method,
+    Initializer,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
+// a name in the source and the declaration it refers to, declarations refer to themselves
+#[derive(Debug, Clone)]
+pub struct Reference {
+    pub name: scanner::Token,
+    pub declaration: scanner::Token,
 }
 
 #[allow(dead_code)]
 pub struct Resolver {
     scopes: Vec<HashMap<String, bool>>,
     current_function: FunctionType,
+    current_class: ClassType,
+    // number of loops around the current statement, within the current function
+    loop_depth: usize,
     locals: HashMap<usize, usize>,
+    // local variables per scope that have not been read yet, reported when the scope ends
+    unread: Vec<HashMap<String, scanner::Token>>,
+    warnings: Vec<Diagnostic>,
+    // the declaring token of each name per scope, for editor support
+    declarations: Vec<HashMap<String, scanner::Token>>,
+    global_declarations: HashMap<String, scanner::Token>,
+    // globals can be used before they are declared, so they are matched up at the end
+    global_uses: Vec<scanner::Token>,
+    references: Vec<Reference>,
 }
 
 impl Resolver {
@@ -22,33 +50,78 @@ impl Resolver {
         return Self {
             scopes: vec![],
             current_function: FunctionType::None,
+            current_class: ClassType::None,
+            loop_depth: 0,
             locals: HashMap::new(),
+            unread: vec![],
+            warnings: vec![],
+            declarations: vec![],
+            global_declarations: HashMap::new(),
+            global_uses: vec![],
+            references: vec![],
         };
     }
 
-    pub fn resolve(mut self, stms: &Vec<&stmt::Stmt>) -> Result<HashMap<usize, usize>, String> {
+    pub fn resolve(mut self, stms: &Vec<&stmt::Stmt>) -> Result<HashMap<usize, usize>, Diagnostic> {
         self.resolve_many(stms)?;
         return Ok(self.locals);
     }
 
-    fn resolve_internal(&mut self, stm: &stmt::Stmt) -> Result<(), String> {
+    // resolves the program for its lint warnings only, in source order
+    pub fn check(mut self, stms: &Vec<&stmt::Stmt>) -> Result<Vec<Diagnostic>, Diagnostic> {
+        // a parameter shadows a global declared further down just the same
+        for stm in stms {
+            let name = match stm {
+                stmt::Stmt::Var { name, .. }
+                | stmt::Stmt::Function { name, .. }
+                | stmt::Stmt::Class { name, .. }
+                | stmt::Stmt::Import { path: _, name } => name,
+                _ => continue,
+            };
+            self.global_declarations
+                .entry(name.lexeme.clone())
+                .or_insert(name.clone());
+        }
+        self.resolve_many(stms)?;
+        self.warnings
+            .sort_by_key(|warning| warning.span.map(|span| span.start));
+        return Ok(self.warnings);
+    }
+
+    // every declaration and every use of a name that could be matched to one
+    pub fn references(mut self, stms: &Vec<&stmt::Stmt>) -> Result<Vec<Reference>, Diagnostic> {
+        self.resolve_many(stms)?;
+        for name in std::mem::take(&mut self.global_uses) {
+            if let Some(declaration) = self.global_declarations.get(&name.lexeme) {
+                self.references.push(Reference {
+                    name,
+                    declaration: declaration.clone(),
+                });
+            }
+        }
+        self.references
+            .sort_by_key(|reference| reference.name.offset);
+        return Ok(self.references);
+    }
+
+    fn resolve_internal(&mut self, stm: &stmt::Stmt) -> Result<(), Diagnostic> {
         match stm {
             stmt::Stmt::Block { statements: _ } => self.resolve_block(stm)?,
             stmt::Stmt::Var {
                 name: _,
+                annotation: _,
                 initializer: _,
             } => self.resolve_var(stm)?,
-            stmt::Stmt::Class { name, methods } => {
-                for method in methods {
-                    let declaration = FunctionType::Method;
-                    self.resolve_function(method, declaration)?;
-                }
-                self.declare(name)?;
-                self.define(name);
-            }
+            stmt::Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => self.resolve_class(name, superclass, methods)?,
             stmt::Stmt::Function {
                 name: _,
                 params: _,
+                param_annotations: _,
+                return_annotation: _,
                 body: _,
             } => self.resolve_function(stm, FunctionType::Function)?,
             stmt::Stmt::Expression { expression } => self.resolve_expr(expression)?,
@@ -58,32 +131,95 @@ impl Resolver {
                 els: _,
             } => self.resolve_if_stmt(stm)?,
             stmt::Stmt::Print { expression } => self.resolve_expr(expression)?,
-            stmt::Stmt::ReturnStmt { keyword: _, value } => {
+            stmt::Stmt::ReturnStmt { keyword, value } => {
                 if self.current_function == FunctionType::None {
-                    return Err("return statement is not allowed outside of a function".to_string());
+                    return Err(error(
+                        keyword,
+                        "return statement is not allowed outside of a function",
+                    ));
                 }
 
                 if let Some(value) = value {
+                    if self.current_function == FunctionType::Initializer {
+                        return Err(error(keyword, "cannot return a value from an initializer"));
+                    }
                     self.resolve_expr(value)?;
                 }
             }
-            stmt::Stmt::WhileStmt { condition, body } => {
+            stmt::Stmt::WhileStmt {
+                condition,
+                body,
+                increment,
+            } => {
                 self.resolve_expr(condition)?;
+                self.loop_depth += 1;
                 self.resolve_internal(body.as_ref())?;
+                self.loop_depth -= 1;
+                if let Some(increment) = increment {
+                    self.resolve_expr(increment)?;
+                }
+            }
+            stmt::Stmt::Break { keyword } => {
+                if self.loop_depth == 0 {
+                    return Err(error(keyword, "cannot use 'break' outside of a loop"));
+                }
+            }
+            stmt::Stmt::Continue { keyword } => {
+                if self.loop_depth == 0 {
+                    return Err(error(keyword, "cannot use 'continue' outside of a loop"));
+                }
+            }
+            stmt::Stmt::Import { path: _, name } => {
+                self.declare(name)?;
+                self.define(name);
+            }
+            stmt::Stmt::Throw { keyword: _, value } => self.resolve_expr(value)?,
+            stmt::Stmt::Try {
+                keyword: _,
+                body,
+                catch,
+                finally,
+            } => {
+                self.resolve_internal(body)?;
+                if let Some((name, handler)) = catch {
+                    // the caught value lives in a scope of its own around the handler block
+                    self.begin_scope();
+                    self.declare(name)?;
+                    self.define(name);
+                    self.resolve_internal(handler)?;
+                    self.end_scope();
+                }
+                if let Some(finally) = finally {
+                    self.resolve_internal(finally)?;
+                }
             }
         }
 
         return Ok(());
     }
 
-    fn resolve_many(&mut self, stmts: &Vec<&stmt::Stmt>) -> Result<(), String> {
-        for stm in stmts {
+    fn resolve_many(&mut self, stmts: &Vec<&stmt::Stmt>) -> Result<(), Diagnostic> {
+        for (i, stm) in stmts.iter().enumerate() {
             self.resolve_internal(stm)?;
+
+            let keyword = match stm {
+                stmt::Stmt::ReturnStmt { keyword, value: _ } => Some(keyword),
+                stmt::Stmt::Break { keyword } => Some(keyword),
+                stmt::Stmt::Continue { keyword } => Some(keyword),
+                stmt::Stmt::Throw { keyword, value: _ } => Some(keyword),
+                _ => None,
+            };
+            if let (Some(keyword), true) = (keyword, i + 1 < stmts.len()) {
+                self.warn(
+                    keyword,
+                    format!("code after '{}' is unreachable", keyword.lexeme),
+                );
+            }
         }
         return Ok(());
     }
 
-    fn resolve_block(&mut self, stm: &stmt::Stmt) -> Result<(), String> {
+    fn resolve_block(&mut self, stm: &stmt::Stmt) -> Result<(), Diagnostic> {
         match stm {
             stmt::Stmt::Block { statements } => {
                 self.begin_scope();
@@ -95,19 +231,43 @@ impl Resolver {
         return Ok(());
     }
 
-    fn resolve_var(&mut self, stm: &stmt::Stmt) -> Result<(), String> {
-        if let stmt::Stmt::Var { name, initializer } = stm {
+    fn resolve_var(&mut self, stm: &stmt::Stmt) -> Result<(), Diagnostic> {
+        if let stmt::Stmt::Var {
+            name,
+            annotation: _,
+            initializer,
+        } = stm
+        {
             self.declare(name)?;
             self.resolve_expr(initializer)?;
             self.define(name);
+
+            // globals may be read by code that is not resolved yet, a leading
+            // underscore marks a local as intentionally unused
+            if let Some(unread) = self.unread.last_mut() {
+                if !name.lexeme.starts_with('_') {
+                    unread.insert(name.lexeme.clone(), name.clone());
+                }
+            }
         } else {
             panic!("incorrect type in resolve var");
         }
         return Ok(());
     }
 
-    fn resolve_function(&mut self, stm: &stmt::Stmt, fn_type: FunctionType) -> Result<(), String> {
-        if let stmt::Stmt::Function { name, params, body } = stm {
+    fn resolve_function(
+        &mut self,
+        stm: &stmt::Stmt,
+        fn_type: FunctionType,
+    ) -> Result<(), Diagnostic> {
+        if let stmt::Stmt::Function {
+            name,
+            params,
+            param_annotations: _,
+            return_annotation: _,
+            body,
+        } = stm
+        {
             self.declare(name)?;
             self.define(name);
 
@@ -121,14 +281,100 @@ impl Resolver {
         }
     }
 
+    fn resolve_class(
+        &mut self,
+        name: &scanner::Token,
+        superclass: &Option<expr::Expr>,
+        methods: &[Box<stmt::Stmt>],
+    ) -> Result<(), Diagnostic> {
+        let enclosing_class = self.current_class;
+        self.current_class = ClassType::Class;
+
+        self.declare(name)?;
+        self.define(name);
+
+        if let Some(superclass) = superclass {
+            if let expr::Expr::Variable {
+                id: _,
+                name: super_name,
+            } = superclass
+            {
+                if super_name.lexeme == name.lexeme {
+                    return Err(error(super_name, "a class cannot inherit from itself"));
+                }
+            }
+
+            self.current_class = ClassType::Subclass;
+            self.resolve_expr(superclass)?;
+
+            self.begin_scope();
+            let size = self.scopes.len();
+            self.scopes[size - 1].insert("super".to_string(), true);
+        }
+
+        self.begin_scope();
+        let size = self.scopes.len();
+        self.scopes[size - 1].insert("this".to_string(), true);
+
+        for method in methods {
+            if let stmt::Stmt::Function {
+                name: method_name,
+                params,
+                param_annotations: _,
+                return_annotation: _,
+                body,
+            } = method.as_ref()
+            {
+                let declaration = if method_name.lexeme == "init" {
+                    FunctionType::Initializer
+                } else {
+                    FunctionType::Method
+                };
+                self.resolve_function_helper(
+                    params,
+                    &body.iter().map(|b| b.as_ref()).collect(),
+                    declaration,
+                )?;
+            } else {
+                panic!("class method expects function type");
+            }
+        }
+
+        self.end_scope();
+
+        if superclass.is_some() {
+            self.end_scope();
+        }
+
+        self.current_class = enclosing_class;
+
+        return Ok(());
+    }
+
     fn resolve_function_helper(
         &mut self,
         params: &Vec<scanner::Token>,
         body: &Vec<&stmt::Stmt>,
         resolving_function: FunctionType,
-    ) -> Result<(), String> {
+    ) -> Result<(), Diagnostic> {
         let enclosing_function = self.current_function;
         self.current_function = resolving_function;
+        let enclosing_loop_depth = self.loop_depth;
+        self.loop_depth = 0;
+        for param in params {
+            if self
+                .scopes
+                .iter()
+                .any(|scope| scope.contains_key(&param.lexeme))
+                || self.global_declarations.contains_key(&param.lexeme)
+            {
+                self.warn(
+                    param,
+                    format!("parameter '{}' shadows an outer variable", param.lexeme),
+                );
+            }
+        }
+
         self.begin_scope();
         for param in params {
             self.declare(param)?;
@@ -138,11 +384,12 @@ impl Resolver {
         self.end_scope();
 
         self.current_function = enclosing_function;
+        self.loop_depth = enclosing_loop_depth;
 
         return Ok(());
     }
 
-    fn resolve_if_stmt(&mut self, stm: &stmt::Stmt) -> Result<(), String> {
+    fn resolve_if_stmt(&mut self, stm: &stmt::Stmt) -> Result<(), Diagnostic> {
         if let stmt::Stmt::IfStmt {
             predicate,
             then,
@@ -163,23 +410,70 @@ impl Resolver {
 
     fn begin_scope(&mut self) {
         self.scopes.push(HashMap::new());
+        self.unread.push(HashMap::new());
+        self.declarations.push(HashMap::new());
     }
 
     fn end_scope(&mut self) {
         self.scopes.pop().expect("stack underflow in scope");
+        self.declarations.pop().expect("stack underflow in scope");
+        let unread = self.unread.pop().expect("stack underflow in scope");
+        for (name, token) in unread {
+            self.warn(&token, format!("local variable '{}' is never read", name));
+        }
     }
 
-    fn declare(&mut self, name: &scanner::Token) -> Result<(), String> {
+    fn warn(&mut self, token: &scanner::Token, msg: String) {
+        self.warnings
+            .push(Diagnostic::at_token(DiagnosticKind::Lint, msg, token));
+    }
+
+    fn reference(&mut self, name: &scanner::Token) {
+        for i in (0..self.scopes.len()).rev() {
+            if self.scopes[i].contains_key(&name.lexeme) {
+                if let Some(declaration) = self.declarations[i].get(&name.lexeme) {
+                    self.references.push(Reference {
+                        name: name.clone(),
+                        declaration: declaration.clone(),
+                    });
+                }
+                return;
+            }
+        }
+        self.global_uses.push(name.clone());
+    }
+
+    // assignments do not count, only the innermost variable with the name is read
+    fn mark_read(&mut self, name: &str) {
+        for i in (0..self.scopes.len()).rev() {
+            if self.scopes[i].contains_key(name) {
+                self.unread[i].remove(name);
+                return;
+            }
+        }
+    }
+
+    fn declare(&mut self, name: &scanner::Token) -> Result<(), Diagnostic> {
+        self.references.push(Reference {
+            name: name.clone(),
+            declaration: name.clone(),
+        });
+
         let size = self.scopes.len();
         if self.scopes.is_empty() {
+            // globals may be redeclared, uses go to the first declaration
+            self.global_declarations
+                .entry(name.lexeme.clone())
+                .or_insert(name.clone());
             return Ok(()); // scopes vec is empty, must be in global scope so do nothing
         }
 
         if self.scopes[size - 1].contains_key(&name.lexeme.clone()) {
-            return Err("a variable with this name is already in scope".to_string());
+            return Err(error(name, "a variable with this name is already in scope"));
         }
 
         self.scopes[size - 1].insert(name.lexeme.clone(), false);
+        self.declarations[size - 1].insert(name.lexeme.clone(), name.clone());
 
         return Ok(());
     }
@@ -193,7 +487,7 @@ impl Resolver {
         self.scopes[size - 1].insert(name.lexeme.clone(), true);
     }
 
-    fn resolve_expr(&mut self, exp: &expr::Expr) -> Result<(), String> {
+    fn resolve_expr(&mut self, exp: &expr::Expr) -> Result<(), Diagnostic> {
         match exp {
             expr::Expr::Variable { id: _, name: _ } => self.resolve_expr_var(exp, exp.get_id()),
             expr::Expr::Assign {
@@ -229,6 +523,37 @@ impl Resolver {
                 name: _,
             } => self.resolve_expr(object),
             expr::Expr::Grouping { id: _, expression } => self.resolve_expr(expression),
+            expr::Expr::Index {
+                id: _,
+                object,
+                bracket: _,
+                index,
+            } => {
+                self.resolve_expr(object)?;
+                self.resolve_expr(index)
+            }
+            expr::Expr::List {
+                id: _,
+                bracket: _,
+                elements,
+            } => {
+                for element in elements {
+                    self.resolve_expr(element)?;
+                }
+
+                return Ok(());
+            }
+            expr::Expr::Interpolation {
+                id: _,
+                quote: _,
+                parts,
+            } => {
+                for part in parts {
+                    self.resolve_expr(part)?;
+                }
+
+                return Ok(());
+            }
             expr::Expr::Literal { id: _, value: _ } => Ok(()),
             expr::Expr::Logical {
                 id: _,
@@ -239,6 +564,41 @@ impl Resolver {
                 self.resolve_expr(left)?;
                 return self.resolve_expr(right);
             }
+            expr::Expr::Map {
+                id: _,
+                brace: _,
+                entries,
+            } => {
+                for (key, value) in entries {
+                    self.resolve_expr(key)?;
+                    self.resolve_expr(value)?;
+                }
+
+                return Ok(());
+            }
+            expr::Expr::Match {
+                id: _,
+                keyword: _,
+                subject,
+                arms,
+            } => {
+                self.resolve_expr(subject)?;
+                for (pattern, body) in arms {
+                    if let expr::Pattern::Literal { value } = pattern {
+                        self.resolve_expr(value)?;
+                    }
+                    // like a caught value, the binding is scoped to its arm
+                    self.begin_scope();
+                    if let Some(name) = pattern.binding() {
+                        self.declare(name)?;
+                        self.define(name);
+                    }
+                    self.resolve_expr(body)?;
+                    self.end_scope();
+                }
+
+                return Ok(());
+            }
             expr::Expr::Set {
                 id: _,
                 object,
@@ -248,6 +608,35 @@ impl Resolver {
                 self.resolve_expr(object)?;
                 self.resolve_expr(value)
             }
+            expr::Expr::SetIndex {
+                id: _,
+                object,
+                bracket: _,
+                index,
+                value,
+            } => {
+                self.resolve_expr(object)?;
+                self.resolve_expr(index)?;
+                self.resolve_expr(value)
+            }
+            expr::Expr::Super {
+                id: _,
+                keyword,
+                method: _,
+            } => match self.current_class {
+                ClassType::None => Err(error(keyword, "cannot use 'super' outside of a class")),
+                ClassType::Class => Err(error(
+                    keyword,
+                    "cannot use 'super' in a class with no superclass",
+                )),
+                ClassType::Subclass => self.resolve_local(keyword, exp.get_id()),
+            },
+            expr::Expr::This { id: _, keyword } => {
+                if self.current_class == ClassType::None {
+                    return Err(error(keyword, "cannot use 'this' outside of a class"));
+                }
+                self.resolve_local(keyword, exp.get_id())
+            }
             expr::Expr::Unary {
                 id: _,
                 operator: _,
@@ -266,14 +655,19 @@ impl Resolver {
         }
     }
 
-    fn resolve_expr_var(&mut self, exp: &expr::Expr, resolve_id: usize) -> Result<(), String> {
+    fn resolve_expr_var(&mut self, exp: &expr::Expr, resolve_id: usize) -> Result<(), Diagnostic> {
         match exp {
             expr::Expr::Variable { id: _, name } => {
                 if !self.scopes.is_empty() {
                     if let Some(false) = self.scopes[self.scopes.len() - 1].get(&name.lexeme) {
-                        return Err("cannot read local varaible in its own initializer".to_string());
+                        return Err(error(
+                            name,
+                            "cannot read local varaible in its own initializer",
+                        ));
                     }
                 }
+                self.mark_read(&name.lexeme);
+                self.reference(name);
                 return self.resolve_local(name, resolve_id);
             }
             expr::Expr::Call {
@@ -289,7 +683,11 @@ impl Resolver {
         }
     }
 
-    fn resolve_local(&mut self, name: &scanner::Token, resolve_id: usize) -> Result<(), String> {
+    fn resolve_local(
+        &mut self,
+        name: &scanner::Token,
+        resolve_id: usize,
+    ) -> Result<(), Diagnostic> {
         let size = self.scopes.len();
         if size == 0 {
             return Ok(());
@@ -305,9 +703,14 @@ impl Resolver {
         return Ok(()); // assume it's global
     }
 
-    fn resolve_expr_assign(&mut self, exp: &expr::Expr, resolve_id: usize) -> Result<(), String> {
+    fn resolve_expr_assign(
+        &mut self,
+        exp: &expr::Expr,
+        resolve_id: usize,
+    ) -> Result<(), Diagnostic> {
         if let expr::Expr::Assign { id: _, name, value } = exp {
             self.resolve_expr(value.as_ref())?;
+            self.reference(name);
             self.resolve_local(name, resolve_id)?;
         } else {
             panic!("incorrect type in resolve assign");
@@ -316,3 +719,78 @@ impl Resolver {
         return Ok(());
     }
 }
+
+fn error(token: &scanner::Token, msg: &str) -> Diagnostic {
+    return Diagnostic::at_token(DiagnosticKind::Resolve, msg, token);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn warnings(source: &str) -> Vec<String> {
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        return Resolver::new()
+            .check(&statements.iter().collect())
+            .unwrap()
+            .into_iter()
+            .map(|warning| warning.message)
+            .collect();
+    }
+
+    #[test]
+    fn warns_about_unread_locals() {
+        assert_eq!(
+            warnings("var g = 1; { var a = 1; var b = 2; var _c = 3; a = 2; print b; }"),
+            vec!["local variable 'a' is never read"]
+        );
+    }
+
+    #[test]
+    fn warns_about_shadowing_parameters() {
+        assert_eq!(
+            warnings("fun f(x) { fun g(x) { return x; } return g(x); }"),
+            vec!["parameter 'x' shadows an outer variable"]
+        );
+        assert_eq!(
+            warnings("var outer = 1; fun f(outer) { return outer; } fun g(later) { return later; } var later = 2;"),
+            vec![
+                "parameter 'outer' shadows an outer variable",
+                "parameter 'later' shadows an outer variable"
+            ]
+        );
+    }
+
+    #[test]
+    fn warns_about_unreachable_code() {
+        assert_eq!(
+            warnings("fun f() { return 1; print 2; } while (true) { break; }"),
+            vec!["code after 'return' is unreachable"]
+        );
+    }
+
+    #[test]
+    fn scopes_match_bindings() {
+        // the binding is not the variable being initialized
+        assert_eq!(
+            warnings(
+                "{ var n = match (1) { n => n }; var p = match (n) { Point p => p, _ => nil }; }"
+            ),
+            vec!["local variable 'p' is never read"]
+        );
+    }
+
+    #[test]
+    fn scopes_catch_variables() {
+        assert_eq!(
+            warnings("{ var e = 1; try { throw e; print e; } catch (e) { var e = 2; } }"),
+            vec![
+                "code after 'throw' is unreachable",
+                "local variable 'e' is never read"
+            ]
+        );
+    }
+}
@@ -1,15 +1,12 @@
+use crate::diagnostic::{Diagnostic, DiagnosticKind, Span};
 use std::collections::HashMap;
 
 fn is_digit(ch: char) -> bool {
-    return ch as u8 >= '0' as u8 && ch as u8 <= '9' as u8;
+    return ch.is_ascii_digit();
 }
 
 fn is_alpha(ch: char) -> bool {
-    let uch = ch as u8;
-
-    return (uch >= 'a' as u8 && uch <= 'z' as u8)
-        || (uch >= 'A' as u8 && uch <= 'Z' as u8)
-        || (ch == '_');
+    return ch.is_ascii_alphabetic() || ch == '_';
 }
 
 fn is_alpha_numeric(ch: char) -> bool {
@@ -19,19 +16,28 @@ fn is_alpha_numeric(ch: char) -> bool {
 fn get_keywords_hashmap() -> HashMap<&'static str, TokenType> {
     return HashMap::from([
         ("and", TokenType::And),
+        ("as", TokenType::As),
+        ("break", TokenType::Break),
+        ("catch", TokenType::Catch),
         ("class", TokenType::Class),
+        ("continue", TokenType::Continue),
         ("else", TokenType::Else),
         ("false", TokenType::False),
+        ("finally", TokenType::Finally),
         ("for", TokenType::For),
         ("fun", TokenType::Fun),
         ("if", TokenType::If),
+        ("import", TokenType::Import),
+        ("match", TokenType::Match),
         ("nil", TokenType::Nil),
         ("or", TokenType::Or),
         ("print", TokenType::Print),
         ("return", TokenType::Return),
         ("super", TokenType::Super),
         ("this", TokenType::This),
+        ("throw", TokenType::Throw),
         ("true", TokenType::True),
+        ("try", TokenType::Try),
         ("var", TokenType::Var),
         ("while", TokenType::While),
     ]);
@@ -40,9 +46,17 @@ fn get_keywords_hashmap() -> HashMap<&'static str, TokenType> {
 pub struct Scanner<'a> {
     source: &'a str,
     pub tokens: Vec<Token>,
+    // comments are not tokens, they are kept aside for the formatter
+    pub comments: Vec<Comment>,
     start: usize,
     current: usize,
     line: usize,
+    line_start: usize,
+    token_line: usize,
+    token_column: usize,
+    // one entry per interpolation being scanned, counting the braces opened
+    // inside it so the '}' that resumes the string can be told apart
+    interpolations: Vec<usize>,
 
     keywords: HashMap<&'static str, TokenType>,
 }
@@ -52,53 +66,88 @@ impl<'a> Scanner<'a> {
         return Self {
             source: source,
             tokens: vec![],
+            comments: vec![],
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            token_line: 1,
+            token_column: 1,
+            interpolations: vec![],
             keywords: get_keywords_hashmap(),
         };
     }
 
-    pub fn scan_tokens(self: &mut Self) -> Result<Vec<Token>, String> {
+    pub fn scan_tokens(&mut self) -> Result<Vec<Token>, Vec<Diagnostic>> {
         let mut errors = vec![];
         while !self.is_at_end() {
             self.start = self.current;
+            self.token_line = self.line;
+            self.token_column = self.column(self.start);
             match self.scan_token() {
                 Ok(_) => (),
                 Err(msg) => errors.push(msg),
             }
         }
 
+        if !self.interpolations.is_empty() {
+            self.start = self.current;
+            errors.push(self.error("unterminated string interpolation"));
+        }
+
         self.tokens.push(Token {
             token_type: TokenType::Eof,
             lexeme: "".to_string(),
             literal: None,
             line_number: self.line,
+            column: self.column(self.current),
+            offset: self.current,
         });
 
         if errors.len() > 0 {
-            let mut joined = "".to_string();
-            for error in errors {
-                joined.push_str(&error);
-                joined.push_str("\n");
-            }
-            return Err(joined);
+            return Err(errors);
         }
 
         return Ok(self.tokens.clone());
     }
 
-    fn scan_token(self: &mut Self) -> Result<(), String> {
+    fn scan_token(&mut self) -> Result<(), Diagnostic> {
         let c = self.advance();
 
         match c {
             '(' => self.add_token(TokenType::LeftParen),
             ')' => self.add_token(TokenType::RightParen),
-            '{' => self.add_token(TokenType::LeftBrace),
-            '}' => self.add_token(TokenType::RightBrace),
+            '{' => {
+                if let Some(depth) = self.interpolations.last_mut() {
+                    *depth += 1;
+                }
+                self.add_token(TokenType::LeftBrace);
+            }
+            '}' => match self.interpolations.last_mut() {
+                Some(0) => {
+                    self.interpolations.pop();
+                    self.string_lit()?;
+                }
+                Some(depth) => {
+                    *depth -= 1;
+                    self.add_token(TokenType::RightBrace);
+                }
+                None => self.add_token(TokenType::RightBrace),
+            },
+            '[' => self.add_token(TokenType::LeftBracket),
+            ']' => self.add_token(TokenType::RightBracket),
+            ':' => self.add_token(TokenType::Colon),
             ',' => self.add_token(TokenType::Comma),
             '.' => self.add_token(TokenType::Dot),
-            '-' => self.add_token(TokenType::Minus),
+            '-' => {
+                let token = if self.char_match('>') {
+                    // ->
+                    TokenType::Arrow
+                } else {
+                    TokenType::Minus
+                };
+                self.add_token(token);
+            }
             '+' => self.add_token(TokenType::Plus),
             ';' => self.add_token(TokenType::Semicolon),
             '*' => self.add_token(TokenType::Star),
@@ -113,8 +162,11 @@ impl<'a> Scanner<'a> {
             }
             '=' => {
                 let token = if self.char_match('=') {
-                    // !=
+                    // ==
                     TokenType::EqualEqual
+                } else if self.char_match('>') {
+                    // =>
+                    TokenType::FatArrow
                 } else {
                     TokenType::Equal
                 };
@@ -146,6 +198,7 @@ impl<'a> Scanner<'a> {
                         }
                         self.advance();
                     }
+                    self.add_comment();
                 } else {
                     self.add_token(TokenType::Slash);
                 }
@@ -154,11 +207,11 @@ impl<'a> Scanner<'a> {
                 if self.char_match('>') {
                     self.add_token(TokenType::Pipe);
                 } else {
-                    return Err(format!("expected '>' at line {}", self.line));
+                    return Err(self.error("expected '>' after '|'"));
                 }
             }
             ' ' | '\r' | '\t' => {}
-            '\n' => self.line += 1,
+            '\n' => self.new_line(),
             '"' => self.string_lit()?,
             c => {
                 if is_digit(c) {
@@ -166,7 +219,7 @@ impl<'a> Scanner<'a> {
                 } else if is_alpha(c) {
                     self.identifier();
                 } else {
-                    return Err(format!("unrecognized char at line {}: {}", self.line, c));
+                    return Err(self.error(&format!("unrecognized char: {}", c)));
                 }
             }
         }
@@ -174,7 +227,7 @@ impl<'a> Scanner<'a> {
         return Ok(());
     }
 
-    fn identifier(self: &mut Self) {
+    fn identifier(&mut self) {
         while is_alpha_numeric(self.peek()) {
             self.advance();
         }
@@ -187,7 +240,7 @@ impl<'a> Scanner<'a> {
         }
     }
 
-    fn number_lit(self: &mut Self) -> Result<(), String> {
+    fn number_lit(&mut self) -> Result<(), Diagnostic> {
         while is_digit(self.peek()) {
             self.advance();
         }
@@ -206,103 +259,201 @@ impl<'a> Scanner<'a> {
             Ok(value) => {
                 self.add_token_lit(TokenType::NumberLit, Some(LiteralValue::FValue(value)))
             }
-            Err(_) => return Err(format!("could not parse number: {}", substring)),
+            Err(_) => {
+                let msg = format!("could not parse number: {}", substring);
+                return Err(self.error(&msg));
+            }
         }
 
         return Ok(());
     }
 
-    fn string_lit(self: &mut Self) -> Result<(), String> {
-        // "some string wrapped in double quotes"
-        while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
+    // "some string wrapped in double quotes", a segment that ends in ${ becomes
+    // an Interpolation token and the string resumes after the matching }
+    fn string_lit(&mut self) -> Result<(), Diagnostic> {
+        let mut value = String::new();
+        // a bad escape is reported once the whole string is consumed, so
+        // scanning picks up again after the closing quote
+        let mut error = None;
+
+        loop {
+            if self.is_at_end() {
+                return Err(self.error("unterminated string"));
+            }
+            match self.advance() {
+                '"' => break,
+                '$' if self.peek() == '{' => {
+                    self.advance();
+                    self.interpolations.push(0);
+                    if let Some(error) = error {
+                        return Err(error);
+                    }
+                    self.add_token_lit(
+                        TokenType::Interpolation,
+                        Some(LiteralValue::StringValue(value)),
+                    );
+                    return Ok(());
+                }
+                '\\' => match self.escape() {
+                    Ok(c) => value.push(c),
+                    Err(msg) => {
+                        error = error.or(Some(msg));
+                    }
+                },
+                '\n' => {
+                    self.new_line();
+                    value.push('\n');
+                }
+                c => value.push(c),
             }
-            self.advance();
         }
 
-        if self.is_at_end() {
-            return Err("Unterminated string".to_string());
+        if let Some(error) = error {
+            return Err(error);
         }
-
-        self.advance();
-
-        let value = &self.source[self.start + 1..self.current - 1];
-
-        self.add_token_lit(
-            TokenType::StringLit,
-            Some(LiteralValue::StringValue(value.to_string())),
-        );
+        self.add_token_lit(TokenType::StringLit, Some(LiteralValue::StringValue(value)));
 
         return Ok(());
     }
 
-    fn peek(self: &Self) -> char {
+    // the character after a backslash, \u{1F600} takes one to six hex digits
+    fn escape(&mut self) -> Result<char, Diagnostic> {
         if self.is_at_end() {
-            return '\0'; // null character
-        } else {
-            return self.source.chars().nth(self.current).unwrap();
+            return Err(self.error("unterminated string"));
+        }
+        match self.advance() {
+            'n' => return Ok('\n'),
+            't' => return Ok('\t'),
+            'r' => return Ok('\r'),
+            '0' => return Ok('\0'),
+            '"' => return Ok('"'),
+            '\\' => return Ok('\\'),
+            '$' => return Ok('$'),
+            'u' => {
+                if !self.char_match('{') {
+                    return Err(self.error("expected '{' after \\u"));
+                }
+                let mut digits = String::new();
+                while self.peek().is_ascii_hexdigit() {
+                    digits.push(self.advance());
+                }
+                if !self.char_match('}') || digits.is_empty() || digits.len() > 6 {
+                    return Err(
+                        self.error("a unicode escape takes one to six hex digits, like \\u{1F600}")
+                    );
+                }
+                let code = u32::from_str_radix(&digits, 16).unwrap();
+                return char::from_u32(code).ok_or_else(|| {
+                    self.error(&format!("\\u{{{}}} is not a valid character", digits))
+                });
+            }
+            c => return Err(self.error(&format!("unknown escape sequence '\\{}'", c))),
         }
     }
 
-    fn peek_next(self: &Self) -> char {
-        if self.is_at_end() {
-            return '\0';
-        }
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.line_start = self.current;
+    }
 
-        return self.source.chars().nth(self.current + 1).unwrap();
+    fn error(&self, msg: &str) -> Diagnostic {
+        let span = Span {
+            line: self.token_line,
+            column: self.token_column,
+            start: self.start,
+            end: self.current,
+        };
+        return Diagnostic::new(DiagnosticKind::Scan, msg, Some(span));
     }
 
-    fn char_match(self: &mut Self, ch: char) -> bool {
-        if self.is_at_end() {
-            return false;
-        }
-        if self.source.chars().nth(self.current).unwrap() != ch {
+    // columns count characters from one, for carets and editors
+    fn column(&self, offset: usize) -> usize {
+        return self.source[self.line_start..offset].chars().count() + 1;
+    }
+
+    // start, current and every offset are byte positions, characters outside
+    // ascii take more than one
+    fn peek(&self) -> char {
+        return self.source[self.current..].chars().next().unwrap_or('\0');
+    }
+
+    fn peek_next(&self) -> char {
+        return self.source[self.current..].chars().nth(1).unwrap_or('\0');
+    }
+
+    fn char_match(&mut self, ch: char) -> bool {
+        if self.is_at_end() || self.peek() != ch {
             return false;
-        } else {
-            self.current += 1;
-            return true;
         }
+        self.current += ch.len_utf8();
+        return true;
     }
 
-    fn advance(self: &mut Self) -> char {
-        let c = self.source.chars().nth(self.current).unwrap();
-        self.current += 1;
+    fn advance(&mut self) -> char {
+        let c = self.peek();
+        self.current += c.len_utf8();
 
         return c;
     }
 
-    fn add_token(self: &mut Self, token_type: TokenType) {
+    fn add_token(&mut self, token_type: TokenType) {
         self.add_token_lit(token_type, None);
     }
 
-    fn add_token_lit(self: &mut Self, token_type: TokenType, literal: Option<LiteralValue>) {
+    fn add_token_lit(&mut self, token_type: TokenType, literal: Option<LiteralValue>) {
         let text = self.source[self.start..self.current].to_string();
 
         self.tokens.push(Token {
             token_type: token_type,
             lexeme: text,
             literal: literal,
-            line_number: self.line,
+            line_number: self.token_line,
+            column: self.token_column,
+            offset: self.start,
+        });
+    }
+
+    fn add_comment(&mut self) {
+        let own_line = match self.tokens.last() {
+            Some(token) => token.line_number != self.token_line,
+            None => true,
+        };
+
+        self.comments.push(Comment {
+            text: self.source[self.start..self.current].trim_end().to_string(),
+            line: self.token_line,
+            offset: self.start,
+            own_line,
         });
     }
 
-    fn is_at_end(self: &Self) -> bool {
+    fn is_at_end(&self) -> bool {
         return self.current >= self.source.len();
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct Comment {
+    pub text: String,
+    pub line: usize,
+    pub offset: usize,
+    // nothing but whitespace comes before it on its line
+    pub own_line: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
     pub literal: Option<LiteralValue>,
     pub line_number: usize,
+    pub column: usize,
+    pub offset: usize,
 }
 
-impl Token {
-    pub fn to_string(self: &Self) -> String {
-        format!("{} {} {:?}", self.token_type, self.lexeme, self.literal)
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} {} {:?}", self.token_type, self.lexeme, self.literal)
     }
 }
 
@@ -319,6 +470,9 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
+    Colon,
     Comma,
     Dot,
     Minus,
@@ -336,28 +490,41 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
-    Pipe, // |>
+    Pipe,     // |>
+    Arrow,    // ->
+    FatArrow, // =>
 
     // literals
     Identifier,
     StringLit,
+    // the part of a string before an embedded ${ expression
+    Interpolation,
     NumberLit,
 
     // keywords
     And,
+    As,
+    Break,
+    Catch,
     Class,
+    Continue,
     Else,
     False,
+    Finally,
     Fun,
     For,
     If,
+    Import,
+    Match,
     Nil,
     Or,
     Print,
     Return,
     Super,
     This,
+    Throw,
     True,
+    Try,
     Var,
     While,
 
@@ -390,18 +557,34 @@ mod tests {
         assert_eq!(scanner.tokens[6].token_type, TokenType::Eof);
     }
 
+    #[test]
+    fn handle_collection_tokens() {
+        let source = "[1]{\"k\": 2}";
+        let mut scanner = Scanner::new(source);
+        scanner.scan_tokens().unwrap();
+
+        assert_eq!(scanner.tokens.len(), 9);
+        assert_eq!(scanner.tokens[0].token_type, TokenType::LeftBracket);
+        assert_eq!(scanner.tokens[2].token_type, TokenType::RightBracket);
+        assert_eq!(scanner.tokens[3].token_type, TokenType::LeftBrace);
+        assert_eq!(scanner.tokens[5].token_type, TokenType::Colon);
+        assert_eq!(scanner.tokens[7].token_type, TokenType::RightBrace);
+    }
+
     #[test]
     fn handle_two_char_tokens() {
-        let source = "! != == >=";
+        let source = "! != == >= -> =>";
         let mut scanner = Scanner::new(source);
         scanner.scan_tokens().unwrap();
 
-        assert_eq!(scanner.tokens.len(), 5);
+        assert_eq!(scanner.tokens.len(), 7);
         assert_eq!(scanner.tokens[0].token_type, TokenType::Bang);
         assert_eq!(scanner.tokens[1].token_type, TokenType::BangEqual);
         assert_eq!(scanner.tokens[2].token_type, TokenType::EqualEqual);
         assert_eq!(scanner.tokens[3].token_type, TokenType::GreaterEqual);
-        assert_eq!(scanner.tokens[4].token_type, TokenType::Eof);
+        assert_eq!(scanner.tokens[4].token_type, TokenType::Arrow);
+        assert_eq!(scanner.tokens[5].token_type, TokenType::FatArrow);
+        assert_eq!(scanner.tokens[6].token_type, TokenType::Eof);
     }
 
     #[test]
@@ -448,6 +631,43 @@ mod tests {
         }
     }
 
+    #[test]
+    fn handle_string_escapes() {
+        let source = r#""a\tb \"q\" \\ \u{1F600} \$""#;
+        let mut scanner = Scanner::new(source);
+        scanner.scan_tokens().unwrap();
+
+        match scanner.tokens[0].literal.as_ref().unwrap() {
+            LiteralValue::StringValue(val) => assert_eq!(val, "a\tb \"q\" \\ \u{1F600} $"),
+            _ => panic!("Incorrect literal type"),
+        }
+        assert!(Scanner::new(r#""\x""#).scan_tokens().is_err());
+        assert!(Scanner::new(r#""\u{110000}""#).scan_tokens().is_err());
+    }
+
+    #[test]
+    fn handle_string_interpolation() {
+        let source = "\"a ${ {} } b ${c}\"";
+        let mut scanner = Scanner::new(source);
+        scanner.scan_tokens().unwrap();
+
+        let types: Vec<TokenType> = scanner.tokens.iter().map(|t| t.token_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Interpolation,
+                TokenType::LeftBrace,
+                TokenType::RightBrace,
+                TokenType::Interpolation,
+                TokenType::Identifier,
+                TokenType::StringLit,
+                TokenType::Eof,
+            ]
+        );
+        assert_eq!(scanner.tokens[3].lexeme, "} b ${");
+        assert!(Scanner::new("\"a ${b").scan_tokens().is_err());
+    }
+
     #[test]
     fn handle_number_literal() {
         let source = "123.123\n321.\n5.0";
@@ -510,4 +730,56 @@ mod tests {
         assert_eq!(scanner.tokens[11].token_type, TokenType::Semicolon);
         assert_eq!(scanner.tokens[12].token_type, TokenType::Eof);
     }
+
+    #[test]
+    fn handle_columns_and_offsets() {
+        let source = "var a = 1;\n  print \"x\ny\" + a;";
+        let mut scanner = Scanner::new(source);
+        scanner.scan_tokens().unwrap();
+
+        assert_eq!(scanner.tokens[1].lexeme, "a");
+        assert_eq!(scanner.tokens[1].line_number, 1);
+        assert_eq!(scanner.tokens[1].column, 5);
+        assert_eq!(scanner.tokens[1].offset, 4);
+
+        assert_eq!(scanner.tokens[5].token_type, TokenType::Print);
+        assert_eq!(scanner.tokens[5].line_number, 2);
+        assert_eq!(scanner.tokens[5].column, 3);
+
+        // a multi-line string is located where it starts
+        assert_eq!(scanner.tokens[6].token_type, TokenType::StringLit);
+        assert_eq!(scanner.tokens[6].line_number, 2);
+        assert_eq!(scanner.tokens[6].column, 9);
+
+        assert_eq!(scanner.tokens[7].token_type, TokenType::Plus);
+        assert_eq!(scanner.tokens[7].line_number, 3);
+        assert_eq!(scanner.tokens[7].column, 4);
+    }
+
+    #[test]
+    fn handle_error_location() {
+        let source = "var a = 1;\nvar b = #;";
+        let mut scanner = Scanner::new(source);
+        let errors = scanner.scan_tokens().unwrap_err();
+
+        assert_eq!(errors.len(), 1);
+        let span = errors[0].span.unwrap();
+        assert_eq!(span.line, 2);
+        assert_eq!(span.column, 9);
+    }
+
+    #[test]
+    fn handle_non_ascii_text() {
+        let source = "// café\nvar s = \"é${1}ı\"; ı";
+        let mut scanner = Scanner::new(source);
+        let errors = scanner.scan_tokens().unwrap_err();
+
+        // offsets are in bytes, so lexemes slice cleanly
+        assert_eq!(scanner.tokens[3].lexeme, "\"é${");
+        assert_eq!(scanner.tokens[5].lexeme, "}ı\"");
+        assert_eq!(scanner.tokens[6].offset, 27);
+        assert_eq!(scanner.tokens[6].column, 17);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "unrecognized char: ı");
+    }
 }
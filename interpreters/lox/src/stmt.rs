@@ -9,8 +9,11 @@ pub enum Stmt {
     Print {
         expression: expr::Expr,
     },
+    // annotations are the type name after the colon, checked before running and
+    // ignored at runtime
     Var {
         name: scanner::Token,
+        annotation: Option<scanner::Token>,
         initializer: expr::Expr,
     },
     Block {
@@ -18,6 +21,7 @@ pub enum Stmt {
     },
     Class {
         name: scanner::Token,
+        superclass: Option<expr::Expr>,
         methods: Vec<Box<Stmt>>,
     },
     IfStmt {
@@ -28,54 +32,146 @@ pub enum Stmt {
     WhileStmt {
         condition: expr::Expr,
         body: Box<Stmt>,
+        // the increment clause of a desugared for loop, run after every iteration
+        // including ones that end in continue
+        increment: Option<expr::Expr>,
     },
     Function {
         name: scanner::Token,
         params: Vec<scanner::Token>,
+        // one per parameter
+        param_annotations: Vec<Option<scanner::Token>>,
+        return_annotation: Option<scanner::Token>,
         body: Vec<Box<Stmt>>,
     },
     ReturnStmt {
         keyword: scanner::Token,
         value: Option<expr::Expr>,
     },
+    Import {
+        path: scanner::Token,
+        name: scanner::Token,
+    },
+    Break {
+        keyword: scanner::Token,
+    },
+    Continue {
+        keyword: scanner::Token,
+    },
+    Throw {
+        keyword: scanner::Token,
+        value: expr::Expr,
+    },
+    // the parser requires at least one of catch and finally, body and both
+    // handlers are always blocks
+    Try {
+        keyword: scanner::Token,
+        body: Box<Stmt>,
+        catch: Option<(scanner::Token, Box<Stmt>)>,
+        finally: Option<Box<Stmt>>,
+    },
 }
 
 impl Stmt {
-    #[allow(dead_code)]
     pub fn tostring(&self) -> String {
         match self {
             Stmt::Expression { expression } => expression.to_string(),
             Stmt::Print { expression } => format!("(print {})", expression.to_string()),
             Stmt::Var {
                 name,
-                initializer: _,
-            } => format!("(var {})", name.lexeme),
-            Stmt::Block { statements } => format!(
-                "(block {})",
-                statements
-                    .into_iter()
-                    .map(|stmt| stmt.tostring())
-                    .collect::<String>()
-            ),
+                annotation: _,
+                initializer,
+            } => {
+                format!("(var {} {})", name.lexeme, initializer.to_string())
+            }
+            Stmt::Import { path, name } => format!("(import {} as {})", path.lexeme, name.lexeme),
+            Stmt::Block { statements } => format!("(block{})", join(statements)),
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                let superclass = match superclass {
+                    Some(superclass) => format!(" < {}", superclass.to_string()),
+                    None => "".to_string(),
+                };
+                format!("(class {}{}{})", name.lexeme, superclass, join(methods))
+            }
             Stmt::IfStmt {
-                predicate: _,
-                then: _,
-                els: _,
-            } => todo!(),
+                predicate,
+                then,
+                els,
+            } => match els {
+                Some(els) => format!(
+                    "(if {} {} {})",
+                    predicate.to_string(),
+                    then.tostring(),
+                    els.tostring()
+                ),
+                None => format!("(if {} {})", predicate.to_string(), then.tostring()),
+            },
             Stmt::WhileStmt {
-                condition: _,
-                body: _,
-            } => todo!(),
-            Stmt::Function {
-                name: _,
-                params: _,
-                body: _,
-            } => todo!(),
-            Stmt::ReturnStmt {
+                condition,
+                body,
+                increment,
+            } => match increment {
+                Some(increment) => format!(
+                    "(while {} {} {})",
+                    condition.to_string(),
+                    body.tostring(),
+                    increment.to_string()
+                ),
+                None => format!("(while {} {})", condition.to_string(), body.tostring()),
+            },
+            Stmt::Break { keyword: _ } => "(break)".to_string(),
+            Stmt::Continue { keyword: _ } => "(continue)".to_string(),
+            Stmt::Throw { keyword: _, value } => format!("(throw {})", value.to_string()),
+            Stmt::Try {
                 keyword: _,
-                value: _,
-            } => todo!(),
-            _ => todo!(),
+                body,
+                catch,
+                finally,
+            } => {
+                let mut text = format!("(try {}", body.tostring());
+                if let Some((name, handler)) = catch {
+                    text.push_str(&format!(" (catch {} {})", name.lexeme, handler.tostring()));
+                }
+                if let Some(finally) = finally {
+                    text.push_str(&format!(" (finally {})", finally.tostring()));
+                }
+                text.push(')');
+                text
+            }
+            Stmt::Function {
+                name,
+                params,
+                param_annotations: _,
+                return_annotation: _,
+                body,
+            } => {
+                let params: Vec<String> = params.iter().map(|p| p.lexeme.clone()).collect();
+                format!("(fun {} ({}){})", name.lexeme, params.join(" "), join(body))
+            }
+            Stmt::ReturnStmt { keyword: _, value } => match value {
+                Some(value) => format!("(return {})", value.to_string()),
+                None => "(return)".to_string(),
+            },
         }
     }
 }
+
+// a name with its type annotation, as it is written in the source
+pub fn annotated(name: &scanner::Token, annotation: &Option<scanner::Token>) -> String {
+    match annotation {
+        Some(annotation) => return format!("{}: {}", name.lexeme, annotation.lexeme),
+        None => return name.lexeme.clone(),
+    }
+}
+
+// each statement preceded by a space, for the end of an s-expression
+fn join(statements: &[Box<Stmt>]) -> String {
+    return statements
+        .iter()
+        .map(|statement| format!(" {}", statement.tostring()))
+        .collect();
+}
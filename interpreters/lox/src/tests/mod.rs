@@ -1,105 +1,211 @@
+// every file in src/tests/cases is a test of its own on both backends, named
+// after the file, so `cargo test strings` runs only the matching cases. build.rs
+// generates the list. a case states what it expects in comments:
+//
+//   print 1;  // expect: 1
+//   nil.x;    // expect runtime error: cannot access property on type Nil
+//   // expect parse error at line 3
+//   // expect resolve error at line 4: cannot use 'this' outside of a class
+//
+// expect lines are the printed output in order, a runtime error is expected on
+// the line of its annotation, and any expected error stands for a failing exit
+//
+// cases run in process, tests/cli.rs runs a few through the binary to check
+// its exit code and what it writes to stderr
 #[cfg(test)]
 mod tests {
-    use std::fs::{read_dir, read_to_string, DirEntry};
-    use std::process::Command;
+    use crate::{Diagnostic, Lox};
+    use std::cell::RefCell;
+    use std::fs::read_to_string;
+    use std::io::{self, Write};
+    use std::rc::Rc;
 
-    #[test]
-    fn execute_tests() {
-        let cases = read_dir("./src/tests/cases").unwrap();
-
-        let mut msgs = vec![];
-        let mut errors = vec![];
-        for case in cases {
-            let case = case.unwrap();
-            let name = case.path().display().to_string();
-            if name.contains(".swp") {
-                continue;
-            }
-            match run_test(case) {
-                Ok(_) => {
-                    msgs.push(format!("Running {name:.<50}...ok"));
-                }
-                Err(msg) => {
-                    errors.push(msg);
-                    msgs.push(format!("Running {name:.<50}...failed"));
+    macro_rules! case {
+        ($name:ident, $file:expr) => {
+            #[test]
+            fn $name() {
+                if let Err(failure) = super::run_case($file, USE_VM) {
+                    panic!("{}: {}", $file, failure);
                 }
             }
-        }
-
-        println!("Ran {} tests", msgs.len());
-        msgs.sort();
-        for msg in msgs {
-            println!("{msg}");
-        }
+        };
+    }
 
-        if errors.len() > 0 {
-            panic!("Errors:\n\n{}", errors.join("\n\n"));
-        }
+    mod tree {
+        const USE_VM: bool = false;
+        include!(concat!(env!("OUT_DIR"), "/cases.rs"));
     }
 
-    fn run_test(file: DirEntry) -> Result<(), String> {
-        let contents = read_to_string(file.path()).unwrap();
-        let lines = contents.split("\n").collect::<Vec<&str>>();
+    mod vm {
+        const USE_VM: bool = true;
+        include!(concat!(env!("OUT_DIR"), "/cases.rs"));
+    }
 
-        let mut test_code = vec![];
+    #[derive(Debug, PartialEq)]
+    struct ExpectedError {
+        kind: String,
+        line: usize,
+        message: Option<String>,
+    }
 
-        let mut idx = 0;
-        for (i, line) in lines.iter().enumerate() {
-            if line.starts_with("// --- Test") {
-                continue;
-            }
-            if line.starts_with("// --- Expected") {
-                idx = i;
-                break;
-            }
-            test_code.push(line.clone());
+    impl ExpectedError {
+        fn matches(&self, diagnostic: &Diagnostic) -> bool {
+            return self.kind == diagnostic.kind.to_string()
+                && Some(self.line) == diagnostic.span.map(|span| span.line)
+                && self
+                    .message
+                    .as_ref()
+                    .is_none_or(|message| *message == diagnostic.message);
         }
+    }
 
-        let mut expected_output = vec![];
+    struct Output(Rc<RefCell<Vec<u8>>>);
 
-        for line in &lines[idx + 1..] {
-            if line.len() > 0 {
-                let output_line = line.to_string();
-                expected_output.push((output_line[3..]).to_string());
-            }
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            return self.0.borrow_mut().write(buf);
         }
 
-        let input = test_code.join("\n");
+        fn flush(&mut self) -> io::Result<()> {
+            return Ok(());
+        }
+    }
 
-        let output = Command::new("cargo")
-            .arg("run")
-            .arg("e")
-            .arg(input)
-            .output()
-            .unwrap();
+    fn run_case(file: &str, use_vm: bool) -> Result<(), String> {
+        let source =
+            read_to_string(format!("./src/tests/cases/{}", file)).map_err(|err| err.to_string())?;
+        let (expected_output, expected_errors) = expectations(&source)?;
 
-        let lines = std::str::from_utf8(output.stdout.as_slice())
-            .unwrap()
-            .split("\n")
-            .collect::<Vec<&str>>();
+        let output = Rc::new(RefCell::new(vec![]));
+        let mut lox = if use_vm { Lox::with_vm() } else { Lox::new() };
+        lox.set_output(Output(output.clone()));
+        let diagnostics = lox.run(&source).err().unwrap_or_default();
 
-        if !(lines.len() == expected_output.len() || lines.len() == expected_output.len() + 1) {
+        let output = String::from_utf8_lossy(&output.borrow()).to_string();
+        let output: Vec<&str> = output.lines().collect();
+        if output != expected_output {
             return Err(format!(
-                "{:#?}: output length does not match expected output: {} != {}\nFull output:\n{}",
-                file.file_name(),
-                lines.len(),
-                expected_output.len(),
-                lines.join("\n"),
+                "output differs\n{}",
+                diff(&expected_output, &output)
             ));
         }
 
-        for (i, expected) in expected_output.iter().enumerate() {
-            if lines[i] != (*expected).trim() {
-                return Err(format!(
-                    "{:?}: {} != {}\nFull output:\n{}",
-                    file.file_name(),
-                    lines[i],
-                    expected,
-                    lines.join("\n"),
+        let unexpected: Vec<&Diagnostic> = diagnostics
+            .iter()
+            .filter(|diagnostic| !expected_errors.iter().any(|e| e.matches(diagnostic)))
+            .collect();
+        let missing: Vec<&ExpectedError> = expected_errors
+            .iter()
+            .filter(|e| !diagnostics.iter().any(|diagnostic| e.matches(diagnostic)))
+            .collect();
+        if !unexpected.is_empty() || !missing.is_empty() {
+            let mut report = "errors differ".to_string();
+            for e in missing {
+                let message = e.message.as_deref().unwrap_or("...");
+                report.push_str(&format!(
+                    "\n- {} error at line {}: {}",
+                    e.kind, e.line, message
                 ));
             }
+            for diagnostic in unexpected {
+                let line = diagnostic.span.map_or(0, |span| span.line);
+                report.push_str(&format!(
+                    "\n+ {} error at line {}: {}",
+                    diagnostic.kind, line, diagnostic.message
+                ));
+            }
+            return Err(report);
         }
 
         return Ok(());
     }
+
+    fn expectations(source: &str) -> Result<(Vec<&str>, Vec<ExpectedError>), String> {
+        let mut output = vec![];
+        let mut errors = vec![];
+
+        for (i, line) in source.lines().enumerate() {
+            let annotation = match line.find("// expect") {
+                Some(start) => &line[start + "// expect".len()..],
+                None => continue,
+            };
+
+            if let Some(text) = annotation.strip_prefix(": ") {
+                output.push(text);
+            } else if let Some(message) = annotation.strip_prefix(" runtime error: ") {
+                errors.push(ExpectedError {
+                    kind: "runtime".to_string(),
+                    line: i + 1,
+                    message: Some(message.to_string()),
+                });
+            } else if let Some((kind, rest)) = annotation
+                .strip_prefix(' ')
+                .and_then(|rest| rest.split_once(" error at line "))
+            {
+                let (number, message) = match rest.split_once(": ") {
+                    Some((number, message)) => (number, Some(message.to_string())),
+                    None => (rest, None),
+                };
+                let line = number
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("line {}: bad line number '{}'", i + 1, number))?;
+                errors.push(ExpectedError {
+                    kind: kind.to_string(),
+                    line,
+                    message,
+                });
+            } else {
+                return Err(format!(
+                    "line {}: unknown annotation '{}'",
+                    i + 1,
+                    line.trim()
+                ));
+            }
+        }
+
+        return Ok((output, errors));
+    }
+
+    // the expected lines marked with -, the actual ones with +
+    fn diff(expected: &[&str], actual: &[&str]) -> String {
+        let mut lines = vec![];
+        for i in 0..expected.len().max(actual.len()) {
+            match (expected.get(i), actual.get(i)) {
+                (Some(e), Some(a)) if e == a => lines.push(format!("  {}", e)),
+                (e, a) => {
+                    if let Some(e) = e {
+                        lines.push(format!("- {}", e));
+                    }
+                    if let Some(a) = a {
+                        lines.push(format!("+ {}", a));
+                    }
+                }
+            }
+        }
+        return lines.join("\n");
+    }
+
+    #[test]
+    fn reads_annotations() {
+        let source = "print 1; // expect: 1\nnil.x; // expect runtime error: oops\n// expect parse error at line 7\n";
+        let (output, errors) = expectations(source).unwrap();
+        assert_eq!(output, vec!["1"]);
+        assert_eq!(
+            errors,
+            vec![
+                ExpectedError {
+                    kind: "runtime".to_string(),
+                    line: 2,
+                    message: Some("oops".to_string()),
+                },
+                ExpectedError {
+                    kind: "parse".to_string(),
+                    line: 7,
+                    message: None,
+                },
+            ]
+        );
+        assert!(expectations("// expect nothing").is_err());
+    }
 }
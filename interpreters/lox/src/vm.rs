@@ -0,0 +1,751 @@
+use crate::chunk::{Function, OpCode};
+use crate::compiler::{self, MATCH_FUNCTION};
+use crate::diagnostic::Diagnostic;
+use crate::environment;
+use crate::error::RuntimeError;
+use crate::expr::{self, LiteralValue};
+use crate::gc;
+use crate::limits;
+use crate::module;
+use crate::output;
+use crate::scanner;
+use crate::stmt;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+const FRAMES_MAX: usize = 4096;
+
+pub struct Closure {
+    pub function: Rc<Function>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    // the globals of the script that defined the function, which differ from
+    // the running script's when the function was imported from a module
+    pub globals: Rc<RefCell<HashMap<String, LiteralValue>>>,
+}
+
+// an upvalue points into the stack until its variable goes out of scope,
+// then it takes ownership of the value
+pub enum Upvalue {
+    Open(usize),
+    Closed(LiteralValue),
+}
+
+struct CallFrame {
+    closure: Rc<Closure>,
+    // once the frame has been left for a call, ip points past the call instruction
+    ip: usize,
+    base: usize,
+    // name of the class when this frame runs an initializer on behalf of a constructor
+    class_name: Option<String>,
+}
+
+impl CallFrame {
+    fn name(&self) -> String {
+        return match &self.class_name {
+            Some(class_name) => format!("{}.{}", class_name, self.closure.function.name),
+            None => self.closure.function.name.clone(),
+        };
+    }
+}
+
+// an active try block, errors unwind the frames and stack back to where it
+// was entered and continue at its catch or finally code
+struct Handler {
+    frame: usize,
+    stack: usize,
+    target: usize,
+    finally: bool,
+}
+
+pub struct VM {
+    stack: Vec<LiteralValue>,
+    frames: Vec<CallFrame>,
+    globals: Rc<RefCell<HashMap<String, LiteralValue>>>,
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    handlers: Vec<Handler>,
+    // errors held while a finally block runs, its slot refers to one by index
+    pending: Vec<RuntimeError>,
+}
+
+// stores the location of the failing instruction before handing the error back
+macro_rules! attempt {
+    ($vm:ident, $start:ident, $result:expr) => {
+        match $result {
+            Ok(value) => value,
+            Err(err) => {
+                $vm.frames.last_mut().unwrap().ip = $start + 1;
+                return Err(err);
+            }
+        }
+    };
+}
+
+impl VM {
+    pub fn new() -> Self {
+        let globals = environment::get_globals();
+        return Self {
+            stack: vec![],
+            frames: vec![],
+            globals,
+            open_upvalues: vec![],
+            handlers: vec![],
+            pending: vec![],
+        };
+    }
+
+    pub fn interpret(&mut self, stmts: &Vec<&stmt::Stmt>) -> Result<(), Diagnostic> {
+        let function = compiler::Compiler::new().compile(stmts)?;
+        let closure = gc::closure(Closure {
+            function,
+            upvalues: vec![],
+            globals: self.globals.clone(),
+        });
+
+        self.stack.push(LiteralValue::Closure {
+            closure: closure.clone(),
+            receiver: None,
+        });
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            base: 0,
+            class_name: None,
+        });
+
+        loop {
+            let err = match self.execute() {
+                Ok(()) => {
+                    self.pending.clear();
+                    return Ok(());
+                }
+                Err(err) => err,
+            };
+            match self.handlers.pop() {
+                Some(handler) => self.handle(handler, err),
+                None => return Err(Diagnostic::from(self.unwind(err))),
+            }
+        }
+    }
+
+    fn execute(&mut self) -> Result<(), RuntimeError> {
+        loop {
+            let frame = self.frames.last().expect("no frame to execute");
+            let closure = frame.closure.clone();
+            let chunk = &closure.function.chunk;
+            let base = frame.base;
+            let mut ip = frame.ip;
+
+            loop {
+                let start = ip;
+                let op = OpCode::from_byte(chunk.code[ip]);
+                ip += 1;
+                if !limits::step() {
+                    attempt!(self, start, Err(limits::steps_exceeded()));
+                }
+
+                match op {
+                    OpCode::Constant => {
+                        let index = chunk.read_u16(ip) as usize;
+                        ip += 2;
+                        self.stack.push(chunk.constants[index].clone());
+                    }
+                    OpCode::Nil => self.stack.push(LiteralValue::Nil),
+                    OpCode::True => self.stack.push(LiteralValue::True),
+                    OpCode::False => self.stack.push(LiteralValue::False),
+                    OpCode::Pop => {
+                        self.stack.pop();
+                    }
+                    OpCode::GetLocal => {
+                        let slot = chunk.code[ip] as usize;
+                        ip += 1;
+                        self.stack.push(self.stack[base + slot].clone());
+                    }
+                    OpCode::SetLocal => {
+                        let slot = chunk.code[ip] as usize;
+                        ip += 1;
+                        self.stack[base + slot] = self.peek(0).clone();
+                    }
+                    OpCode::GetGlobal => {
+                        let name = constant_name(chunk, ip);
+                        ip += 2;
+                        let value = attempt!(
+                            self,
+                            start,
+                            closure.globals.borrow().get(name).cloned().ok_or_else(|| {
+                                RuntimeError::new(format!(
+                                    "variable '{}' has not been declared",
+                                    name
+                                ))
+                            })
+                        );
+                        self.stack.push(value);
+                    }
+                    OpCode::DefineGlobal => {
+                        let name = constant_name(chunk, ip);
+                        ip += 2;
+                        let value = self.stack.pop().unwrap();
+                        closure.globals.borrow_mut().insert(name.to_string(), value);
+                    }
+                    OpCode::SetGlobal => {
+                        let name = constant_name(chunk, ip);
+                        ip += 2;
+                        if !closure.globals.borrow().contains_key(name) {
+                            attempt!(
+                                self,
+                                start,
+                                Err(RuntimeError::new(format!(
+                                    "variable '{}' has not been declared",
+                                    name
+                                )))
+                            );
+                        }
+                        closure
+                            .globals
+                            .borrow_mut()
+                            .insert(name.to_string(), self.peek(0).clone());
+                    }
+                    OpCode::GetUpvalue => {
+                        let index = chunk.code[ip] as usize;
+                        ip += 1;
+                        let value = match &*closure.upvalues[index].borrow() {
+                            Upvalue::Open(slot) => self.stack[*slot].clone(),
+                            Upvalue::Closed(value) => value.clone(),
+                        };
+                        self.stack.push(value);
+                    }
+                    OpCode::SetUpvalue => {
+                        let index = chunk.code[ip] as usize;
+                        ip += 1;
+                        let value = self.peek(0).clone();
+                        let mut upvalue = closure.upvalues[index].borrow_mut();
+                        match &mut *upvalue {
+                            Upvalue::Open(slot) => self.stack[*slot] = value,
+                            Upvalue::Closed(closed) => *closed = value,
+                        }
+                    }
+                    OpCode::GetProperty => {
+                        let name = constant_name(chunk, ip);
+                        ip += 2;
+                        let object = self.stack.pop().unwrap();
+                        let value = attempt!(
+                            self,
+                            start,
+                            object.get_property(name).map_err(RuntimeError::new)
+                        );
+                        self.stack.push(value);
+                    }
+                    OpCode::SetProperty => {
+                        let name = constant_name(chunk, ip);
+                        ip += 2;
+                        let value = self.stack.pop().unwrap();
+                        let object = self.stack.pop().unwrap();
+                        attempt!(
+                            self,
+                            start,
+                            object.set_property(name, value).map_err(RuntimeError::new)
+                        );
+                        self.stack.push(LiteralValue::Nil);
+                    }
+                    OpCode::GetSuper => {
+                        let name = constant_name(chunk, ip);
+                        ip += 2;
+                        let superclass = self.stack.pop().unwrap();
+                        let this = self.stack.pop().unwrap();
+                        let method = attempt!(
+                            self,
+                            start,
+                            superclass.find_method(name).ok_or_else(|| {
+                                RuntimeError::new(format!(
+                                    "no method named {} on the superclass",
+                                    name
+                                ))
+                            })
+                        );
+                        self.stack.push(method.bind(this));
+                    }
+                    OpCode::Equal => {
+                        attempt!(self, start, self.binary(scanner::TokenType::EqualEqual))
+                    }
+                    OpCode::NotEqual => {
+                        attempt!(self, start, self.binary(scanner::TokenType::BangEqual))
+                    }
+                    OpCode::Greater => {
+                        attempt!(self, start, self.binary(scanner::TokenType::Greater))
+                    }
+                    OpCode::GreaterEqual => {
+                        attempt!(self, start, self.binary(scanner::TokenType::GreaterEqual))
+                    }
+                    OpCode::Less => attempt!(self, start, self.binary(scanner::TokenType::Less)),
+                    OpCode::LessEqual => {
+                        attempt!(self, start, self.binary(scanner::TokenType::LessEqual))
+                    }
+                    OpCode::Add => attempt!(self, start, self.binary(scanner::TokenType::Plus)),
+                    OpCode::Subtract => {
+                        attempt!(self, start, self.binary(scanner::TokenType::Minus))
+                    }
+                    OpCode::Multiply => {
+                        attempt!(self, start, self.binary(scanner::TokenType::Star))
+                    }
+                    OpCode::Divide => attempt!(self, start, self.binary(scanner::TokenType::Slash)),
+                    OpCode::Not => attempt!(self, start, self.unary(scanner::TokenType::Bang)),
+                    OpCode::Negate => attempt!(self, start, self.unary(scanner::TokenType::Minus)),
+                    OpCode::Print => {
+                        let value = self.stack.pop().unwrap();
+                        attempt!(self, start, output::print_line(&value.display()));
+                    }
+                    OpCode::Jump => {
+                        let offset = chunk.read_u16(ip) as usize;
+                        ip += 2 + offset;
+                    }
+                    OpCode::JumpIfFalse => {
+                        let offset = chunk.read_u16(ip) as usize;
+                        ip += 2;
+                        let falsy = attempt!(
+                            self,
+                            start,
+                            self.peek(0).is_falsy().map_err(RuntimeError::new)
+                        );
+                        if falsy == LiteralValue::True {
+                            ip += offset;
+                        }
+                    }
+                    OpCode::Loop => {
+                        let offset = chunk.read_u16(ip) as usize;
+                        ip += 2;
+                        ip -= offset;
+                    }
+                    OpCode::Call => {
+                        let arg_count = chunk.code[ip] as usize;
+                        ip += 1;
+                        self.frames.last_mut().unwrap().ip = ip;
+                        let pushed_frame = attempt!(
+                            self,
+                            start,
+                            self.call_value(arg_count, chunk.token_at(start))
+                        );
+                        if pushed_frame {
+                            break;
+                        }
+                    }
+                    OpCode::Closure => {
+                        let function = chunk.functions[chunk.read_u16(ip) as usize].clone();
+                        ip += 2;
+                        let mut upvalues = vec![];
+                        for _ in 0..function.upvalue_count {
+                            let is_local = chunk.code[ip] == 1;
+                            let index = chunk.code[ip + 1] as usize;
+                            ip += 2;
+                            if is_local {
+                                upvalues.push(self.capture_upvalue(base + index));
+                            } else {
+                                upvalues.push(closure.upvalues[index].clone());
+                            }
+                        }
+                        self.stack.push(LiteralValue::Closure {
+                            closure: gc::closure(Closure {
+                                function,
+                                upvalues,
+                                globals: closure.globals.clone(),
+                            }),
+                            receiver: None,
+                        });
+                    }
+                    OpCode::CloseUpvalue => {
+                        self.close_upvalues(self.stack.len() - 1);
+                        self.stack.pop();
+                    }
+                    OpCode::Return => {
+                        let result = self.stack.pop().unwrap();
+                        self.close_upvalues(base);
+                        self.frames.pop();
+                        self.stack.truncate(base);
+                        if self.frames.is_empty() {
+                            return Ok(());
+                        }
+                        self.stack.push(result);
+                        break;
+                    }
+                    OpCode::Class => {
+                        let name = constant_name(chunk, ip).to_string();
+                        let has_superclass = chunk.code[ip + 2] == 1;
+                        ip += 3;
+                        let superclass = if has_superclass {
+                            match self.peek(0) {
+                                LiteralValue::LoxClass { .. } => {
+                                    Some(Box::new(self.peek(0).clone()))
+                                }
+                                other => {
+                                    let message = format!(
+                                        "superclass of {} must be a class, got {}",
+                                        name,
+                                        other.to_type()
+                                    );
+                                    attempt!(self, start, Err(RuntimeError::new(message)))
+                                }
+                            }
+                        } else {
+                            None
+                        };
+                        self.stack.push(LiteralValue::LoxClass {
+                            name,
+                            methods: HashMap::new(),
+                            superclass,
+                        });
+                    }
+                    OpCode::Method => {
+                        let name = constant_name(chunk, ip).to_string();
+                        ip += 2;
+                        let method = self.stack.pop().unwrap();
+                        if let Some(LiteralValue::LoxClass { methods, .. }) = self.stack.last_mut()
+                        {
+                            methods.insert(name, method);
+                        }
+                    }
+                    OpCode::BuildList => {
+                        let count = chunk.read_u16(ip) as usize;
+                        ip += 2;
+                        attempt!(self, start, limits::allocate(count * limits::VALUE_BYTES));
+                        let items = self.stack.split_off(self.stack.len() - count);
+                        self.stack.push(LiteralValue::List(gc::list(items)));
+                    }
+                    OpCode::Concat => {
+                        let count = chunk.read_u16(ip) as usize;
+                        ip += 2;
+                        let parts = self.stack.split_off(self.stack.len() - count);
+                        let text: String = parts.iter().map(|part| part.display()).collect();
+                        attempt!(self, start, limits::allocate(text.len()));
+                        self.stack.push(LiteralValue::StringLit(text));
+                    }
+                    OpCode::BuildMap => {
+                        let count = chunk.read_u16(ip) as usize;
+                        ip += 2;
+                        let flat = self.stack.split_off(self.stack.len() - count * 2);
+                        let map = LiteralValue::Map(gc::map(vec![]));
+                        for pair in flat.chunks(2) {
+                            attempt!(
+                                self,
+                                start,
+                                expr::index_set(&map, &pair[0], pair[1].clone())
+                                    .map_err(RuntimeError::new)
+                            );
+                        }
+                        self.stack.push(map);
+                    }
+                    OpCode::Import => {
+                        let module =
+                            attempt!(self, start, module::import(chunk.token_at(start), true));
+                        ip += 2;
+                        self.stack.push(module);
+                    }
+                    OpCode::PushHandler => {
+                        let offset = chunk.read_u16(ip) as usize;
+                        let finally = chunk.code[ip + 2] == 1;
+                        self.handlers.push(Handler {
+                            frame: self.frames.len(),
+                            stack: self.stack.len(),
+                            target: ip + 2 + offset,
+                            finally,
+                        });
+                        ip += 3;
+                    }
+                    OpCode::PopHandler => {
+                        self.handlers.pop();
+                    }
+                    OpCode::Throw => {
+                        let value = self.stack.pop().unwrap();
+                        attempt!(
+                            self,
+                            start,
+                            Err(RuntimeError::thrown(chunk.token_at(start), value))
+                        );
+                    }
+                    OpCode::Rethrow => {
+                        let index = match self.stack.pop() {
+                            Some(LiteralValue::Number(index)) => index as usize,
+                            _ => panic!("finally block lost its pending error"),
+                        };
+                        // errors left by finally blocks that were jumped out of are dropped too
+                        self.pending.truncate(index + 1);
+                        let err = self.pending.pop().expect("pending error underflow");
+                        attempt!(self, start, Err(err));
+                    }
+                    OpCode::MatchLiteral => {
+                        let pattern = self.stack.pop().unwrap();
+                        let value = self.stack.pop().unwrap();
+                        self.stack.push(LiteralValue::from_bool(value == pattern));
+                    }
+                    OpCode::MatchClass => {
+                        let name = constant_name(chunk, ip);
+                        ip += 2;
+                        let value = self.stack.pop().unwrap();
+                        self.stack
+                            .push(LiteralValue::from_bool(value.is_instance_of(name)));
+                    }
+                    OpCode::NoMatch => {
+                        let value = self.stack.pop().unwrap();
+                        attempt!(
+                            self,
+                            start,
+                            Err(RuntimeError::new(format!(
+                                "no match arm for {}",
+                                value.to_string()
+                            )))
+                        );
+                    }
+                    OpCode::GetIndex => {
+                        let index = self.stack.pop().unwrap();
+                        let object = self.stack.pop().unwrap();
+                        let value = attempt!(
+                            self,
+                            start,
+                            expr::index_get(&object, &index).map_err(RuntimeError::new)
+                        );
+                        self.stack.push(value);
+                    }
+                    OpCode::SetIndex => {
+                        let value = self.stack.pop().unwrap();
+                        let index = self.stack.pop().unwrap();
+                        let object = self.stack.pop().unwrap();
+                        attempt!(
+                            self,
+                            start,
+                            expr::index_set(&object, &index, value.clone())
+                                .map_err(RuntimeError::new)
+                        );
+                        self.stack.push(value);
+                    }
+                }
+            }
+        }
+    }
+
+    // returns whether a new frame was pushed, natives and classes without an
+    // initializer complete immediately
+    pub fn globals(&self) -> HashMap<String, LiteralValue> {
+        return self.globals.borrow().clone();
+    }
+
+    pub fn global_scope(&self) -> Rc<gc::Scope> {
+        return self.globals.clone();
+    }
+
+    pub fn global(&self, name: &str) -> Option<LiteralValue> {
+        return self.globals.borrow().get(name).cloned();
+    }
+
+    pub fn define_global(&self, name: &str, value: LiteralValue) {
+        self.globals.borrow_mut().insert(name.to_string(), value);
+    }
+
+    pub fn remove_global(&self, name: &str) -> Option<LiteralValue> {
+        return self.globals.borrow_mut().remove(name);
+    }
+
+    fn call_value(
+        &mut self,
+        arg_count: usize,
+        call: &scanner::Token,
+    ) -> Result<bool, RuntimeError> {
+        let callee_slot = self.stack.len() - arg_count - 1;
+        let callee = self.stack[callee_slot].clone();
+        match callee {
+            LiteralValue::Closure { closure, receiver } => {
+                check_arity(
+                    "callable",
+                    &closure.function.name,
+                    closure.function.arity,
+                    arg_count,
+                )?;
+                if let Some(receiver) = receiver {
+                    self.stack[callee_slot] = *receiver;
+                }
+                self.push_frame(closure, callee_slot, None)?;
+                return Ok(true);
+            }
+            LiteralValue::Callable { name, arity, fun } => {
+                check_arity("callable", &name, arity, arg_count)?;
+                let args = self.stack.split_off(callee_slot + 1);
+                self.stack.pop();
+                let result = fun(&args).map_err(|mut err| {
+                    err.locate(call);
+                    err.push_frame(&name, call.line_number);
+                    err
+                })?;
+                self.stack.push(result);
+                return Ok(false);
+            }
+            LiteralValue::LoxClass { ref name, .. } => {
+                let initializer = match callee.find_method("init") {
+                    Some(LiteralValue::Closure {
+                        closure,
+                        receiver: _,
+                    }) => Some(closure),
+                    _ => None,
+                };
+                let arity = initializer.as_ref().map_or(0, |init| init.function.arity);
+                check_arity("class", name, arity, arg_count)?;
+                limits::allocate(limits::VALUE_BYTES)?;
+
+                self.stack[callee_slot] = LiteralValue::LoxInstance {
+                    class: Box::new(callee.clone()),
+                    fields: gc::fields(),
+                };
+                return match initializer {
+                    Some(initializer) => {
+                        self.push_frame(initializer, callee_slot, Some(name.clone()))?;
+                        Ok(true)
+                    }
+                    None => Ok(false),
+                };
+            }
+            other => {
+                return Err(RuntimeError::new(format!(
+                    "{} is not a callable",
+                    other.to_type()
+                )))
+            }
+        }
+    }
+
+    fn push_frame(
+        &mut self,
+        closure: Rc<Closure>,
+        base: usize,
+        class_name: Option<String>,
+    ) -> Result<(), RuntimeError> {
+        if self.frames.len() >= FRAMES_MAX {
+            return Err(RuntimeError::new("stack overflow"));
+        }
+        limits::check_depth(self.frames.len())?;
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            base,
+            class_name,
+        });
+        return Ok(());
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        for upvalue in &self.open_upvalues {
+            if let Upvalue::Open(open_slot) = *upvalue.borrow() {
+                if open_slot == slot {
+                    return upvalue.clone();
+                }
+            }
+        }
+
+        let upvalue = gc::upvalue(Upvalue::Open(slot));
+        self.open_upvalues.push(upvalue.clone());
+        return upvalue;
+    }
+
+    fn close_upvalues(&mut self, from: usize) {
+        let stack = &self.stack;
+        self.open_upvalues.retain(|upvalue| {
+            let slot = match *upvalue.borrow() {
+                Upvalue::Open(slot) => slot,
+                Upvalue::Closed(_) => return false,
+            };
+            if slot < from {
+                return true;
+            }
+            *upvalue.borrow_mut() = Upvalue::Closed(stack[slot].clone());
+            return false;
+        });
+    }
+
+    fn binary(&mut self, operator: scanner::TokenType) -> Result<(), RuntimeError> {
+        let right = self.stack.pop().unwrap();
+        let left = self.stack.pop().unwrap();
+        let result = expr::binary_op(&left, operator, &right).map_err(RuntimeError::new)?;
+        if let LiteralValue::StringLit(text) = &result {
+            limits::allocate(text.len())?;
+        }
+        self.stack.push(result);
+        return Ok(());
+    }
+
+    fn unary(&mut self, operator: scanner::TokenType) -> Result<(), RuntimeError> {
+        let right = self.stack.pop().unwrap();
+        let result = expr::unary_op(operator, &right).map_err(RuntimeError::new)?;
+        self.stack.push(result);
+        return Ok(());
+    }
+
+    fn peek(&self, distance: usize) -> &LiteralValue {
+        return &self.stack[self.stack.len() - 1 - distance];
+    }
+
+    // resumes at a handler, a catch block receives the error's value and a
+    // finally block a reference to the error it rethrows when done
+    fn handle(&mut self, handler: Handler, mut err: RuntimeError) {
+        self.trace(&mut err, handler.frame);
+        self.close_upvalues(handler.stack);
+        self.frames.truncate(handler.frame);
+        self.stack.truncate(handler.stack);
+
+        if handler.finally {
+            self.stack
+                .push(LiteralValue::Number(self.pending.len() as f64));
+            self.pending.push(err);
+        } else {
+            self.stack.push(err.value());
+        }
+        self.frames.last_mut().unwrap().ip = handler.target;
+    }
+
+    // locates the error at the failing instruction and records every active call,
+    // then resets the vm so it can run again
+    fn unwind(&mut self, mut err: RuntimeError) -> RuntimeError {
+        self.trace(&mut err, 1);
+
+        self.stack.clear();
+        self.frames.clear();
+        self.open_upvalues.clear();
+        self.handlers.clear();
+        self.pending.clear();
+
+        return err;
+    }
+
+    // records the calls above the given frame, which are about to be left
+    fn trace(&self, err: &mut RuntimeError, down_to: usize) {
+        if let Some(frame) = self.frames.last() {
+            err.locate(frame.closure.function.chunk.token_at(frame.ip - 1));
+        }
+        for i in (down_to..self.frames.len()).rev() {
+            // match arms run in a function of their own that is not a call
+            // in the source, no user function can be named after the keyword
+            if self.frames[i].closure.function.name == MATCH_FUNCTION {
+                continue;
+            }
+            let caller = &self.frames[i - 1];
+            let line = caller
+                .closure
+                .function
+                .chunk
+                .token_at(caller.ip - 1)
+                .line_number;
+            err.push_frame(&self.frames[i].name(), line);
+        }
+    }
+}
+
+fn constant_name(chunk: &crate::chunk::Chunk, ip: usize) -> &str {
+    match &chunk.constants[chunk.read_u16(ip) as usize] {
+        LiteralValue::StringLit(name) => return name,
+        other => panic!("expected a name constant, got {}", other.to_type()),
+    }
+}
+
+fn check_arity(kind: &str, name: &str, arity: usize, arg_count: usize) -> Result<(), RuntimeError> {
+    if arity != arg_count {
+        return Err(RuntimeError::new(format!(
+            "{} {} expected {} arguments but got {}",
+            kind, name, arity, arg_count
+        )));
+    }
+    return Ok(());
+}
@@ -0,0 +1,69 @@
+// the case files run in process through the embedding api, see src/tests/mod.rs.
+// these run a few of them through the binary to check what the process does
+// with an error: diagnostics on stderr, program output on stdout, exit code 1
+use std::process::{Command, Output};
+
+fn lox(args: &[&str]) -> Output {
+    return Command::new(env!("CARGO_BIN_EXE_lox"))
+        .args(args)
+        .output()
+        .expect("could not run the lox binary");
+}
+
+fn text(bytes: &[u8]) -> String {
+    return String::from_utf8(bytes.to_vec()).unwrap();
+}
+
+#[test]
+fn parse_errors_exit_before_running() {
+    let output = lox(&["src/tests/cases/parse_error.jlox"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(text(&output.stdout), "");
+    let stderr = text(&output.stderr);
+    assert!(stderr.starts_with(
+        "parse error: expected variable name\n --> src/tests/cases/parse_error.jlox:2:5\n"
+    ));
+    assert!(stderr.contains(
+        "parse error: expected expression\n --> src/tests/cases/parse_error.jlox:3:11\n"
+    ));
+}
+
+#[test]
+fn runtime_errors_keep_earlier_output() {
+    for mut args in [vec![], vec!["--vm"]] {
+        args.push("src/tests/cases/fun_runtime_error.jlox");
+        let output = lox(&args);
+
+        assert_eq!(output.status.code(), Some(1));
+        assert_eq!(text(&output.stdout), "before\n");
+        let stderr = text(&output.stderr);
+        assert!(stderr.starts_with(
+            "runtime error: binary operation Plus not supported for inconsistent types\n --> src/tests/cases/fun_runtime_error.jlox:2:12\n"
+        ));
+        assert!(
+            stderr.ends_with("  = in inner() called at line 7\n  = in outer() called at line 12\n")
+        );
+    }
+}
+
+#[test]
+fn json_errors_go_to_stderr() {
+    let output = lox(&["--json", "src/tests/cases/uncaught_exception.jlox"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(text(&output.stdout), "finally\n");
+    assert_eq!(
+        text(&output.stderr),
+        "{\"kind\":\"runtime\",\"message\":\"uncaught exception: bad input\",\"file\":\"src/tests/cases/uncaught_exception.jlox\",\"line\":2,\"column\":3,\"span\":{\"start\":15,\"end\":20},\"notes\":[\"in fail() called at line 6\"]}\n"
+    );
+}
+
+#[test]
+fn passing_scripts_exit_cleanly() {
+    let output = lox(&["e", "print 1 + 2;"]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(text(&output.stdout), "3\n");
+    assert_eq!(text(&output.stderr), "");
+}